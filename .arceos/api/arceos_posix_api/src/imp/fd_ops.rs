@@ -26,6 +26,8 @@ pub trait FileLike: Send + Sync {
 
 def_resource! {
     pub static FD_TABLE: ResArc<RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>>> = ResArc::new();
+    /// The close-on-exec flags of the file descriptors in [`FD_TABLE`].
+    pub static FD_CLOEXEC: ResArc<RwLock<[bool; AX_FILE_LIMIT]>> = ResArc::new();
 }
 
 impl FD_TABLE {
@@ -40,6 +42,13 @@ impl FD_TABLE {
     }
 }
 
+impl FD_CLOEXEC {
+    /// Return a copy of the inner flags.
+    pub fn copy_inner(&self) -> RwLock<[bool; AX_FILE_LIMIT]> {
+        RwLock::new(*self.read())
+    }
+}
+
 pub fn get_table_count() -> usize{
     FD_TABLE.read().count()
 }
@@ -54,7 +63,9 @@ pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
 
 /// Add a file to the file descriptor table.
 pub fn add_file_like(f: Arc<dyn FileLike>) -> LinuxResult<c_int> {
-    Ok(FD_TABLE.write().add(f).map_err(|_| LinuxError::EMFILE)? as c_int)
+    let fd = FD_TABLE.write().add(f).map_err(|_| LinuxError::EMFILE)?;
+    FD_CLOEXEC.write()[fd] = false;
+    Ok(fd as c_int)
 }
 
 /// Close a file by `fd`.
@@ -63,10 +74,36 @@ pub fn close_file_like(fd: c_int) -> LinuxResult {
         .write()
         .remove(fd as usize)
         .ok_or(LinuxError::EBADF)?;
+    FD_CLOEXEC.write()[fd as usize] = false;
     drop(f);
     Ok(())
 }
 
+/// Set the close-on-exec flag of `fd`.
+pub fn set_cloexec(fd: c_int, cloexec: bool) -> LinuxResult {
+    get_file_like(fd)?;
+    FD_CLOEXEC.write()[fd as usize] = cloexec;
+    Ok(())
+}
+
+/// Get the close-on-exec flag of `fd`.
+pub fn get_cloexec(fd: c_int) -> LinuxResult<bool> {
+    get_file_like(fd)?;
+    Ok(FD_CLOEXEC.read()[fd as usize])
+}
+
+/// Close the file descriptors with the close-on-exec flag, on `execve`.
+pub fn close_on_exec() {
+    let mut table = FD_TABLE.write();
+    let mut cloexec = FD_CLOEXEC.write();
+    for fd in 0..AX_FILE_LIMIT {
+        if cloexec[fd] {
+            table.remove(fd);
+            cloexec[fd] = false;
+        }
+    }
+}
+
 /// Close a file by `fd`.
 pub fn sys_close(fd: c_int) -> c_int {
     debug!("sys_close <= {}", fd);
@@ -111,6 +148,7 @@ pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> c_int {
             .write()
             .add_at(new_fd as usize, f)
             .map_err(|_| LinuxError::EMFILE)?;
+        FD_CLOEXEC.write()[new_fd as usize] = false;
 
         Ok(new_fd)
     })
@@ -125,8 +163,9 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
         match cmd as u32 {
             ctypes::F_DUPFD => dup_fd(fd),
             ctypes::F_DUPFD_CLOEXEC => {
-                // TODO: Change fd flags
-                dup_fd(fd)
+                let new_fd = dup_fd(fd)?;
+                set_cloexec(new_fd, true)?;
+                Ok(new_fd)
             }
             ctypes::F_SETFL => {
                 if fd == 0 || fd == 1 || fd == 2 {
@@ -135,8 +174,14 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
                 get_file_like(fd)?.set_nonblocking(arg & (ctypes::O_NONBLOCK as usize) > 0)?;
                 Ok(0)
             }
-            ctypes::F_GETFD => {
-                Ok(1)
+            ctypes::F_GETFD => Ok(if get_cloexec(fd)? {
+                ctypes::FD_CLOEXEC as c_int
+            } else {
+                0
+            }),
+            ctypes::F_SETFD => {
+                set_cloexec(fd, arg & ctypes::FD_CLOEXEC as usize != 0)?;
+                Ok(0)
             }
             ctypes::F_GETFL => {
                 if fd == 0 || fd == 1 || fd == 2 {
@@ -165,4 +210,5 @@ fn init_stdio() {
         .add_at(2, Arc::new(stdout()) as _)
         .unwrap_or_else(|_| panic!()); // stderr
     FD_TABLE.init_new(spin::RwLock::new(fd_table));
+    FD_CLOEXEC.init_new(spin::RwLock::new([false; AX_FILE_LIMIT]));
 }
@@ -12,6 +12,29 @@ use crate::ctypes::timespec;
 use crate::AT_FDCWD;
 use crate::{ctypes, utils::char_ptr_to_str};
 
+/// The caches of file data kept outside the filesystems, such as the page
+/// cache of mapped files, which the I/O through file descriptors keeps
+/// coherent.
+pub trait FileCache: Send + Sync {
+    /// Writes back the cached data of the file at `path` in
+    /// `offset..offset + len`, before it is read.
+    fn before_read(&self, path: &str, offset: u64, len: usize);
+    /// Updates the cached data after `data` is written at `offset` in the file
+    /// at `path`.
+    fn after_write(&self, path: &str, offset: u64, data: &[u8]);
+    /// Drops the cached data past `size` after the file at `path` is
+    /// truncated to it.
+    fn after_truncate(&self, path: &str, size: u64);
+}
+
+static FILE_CACHE: spin::Once<&'static dyn FileCache> = spin::Once::new();
+
+/// Registers the caches of file data to keep coherent with the I/O through
+/// file descriptors.
+pub fn set_file_cache(cache: &'static dyn FileCache) {
+    FILE_CACHE.call_once(|| cache);
+}
+
 /// File wrapper for `axfs::fops::File`.
 pub struct File {
     inner: Mutex<axfs::fops::File>,
@@ -50,11 +73,23 @@ impl File {
 
 impl FileLike for File {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        Ok(self.inner.lock().read(buf)?)
+        let mut inner = self.inner.lock();
+        if let Some(cache) = FILE_CACHE.get() {
+            let pos = inner.seek(SeekFrom::Current(0))?;
+            cache.before_read(&self.path, pos, buf.len());
+        }
+        Ok(inner.read(buf)?)
     }
 
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
-        Ok(self.inner.lock().write(buf)?)
+        let mut inner = self.inner.lock();
+        let len = inner.write(buf)?;
+        if let Some(cache) = FILE_CACHE.get() {
+            // Appending writes start at the end of the file, not the cursor.
+            let end = inner.seek(SeekFrom::Current(0))?;
+            cache.after_write(&self.path, end - len as u64, &buf[..len]);
+        }
+        Ok(len)
     }
 
     fn stat(&self) -> LinuxResult<ctypes::stat> {
@@ -66,7 +101,7 @@ impl FileLike for File {
         
         Ok(ctypes::stat {
             st_ino: 1,
-            st_nlink: 1,
+            st_nlink: axfs::api::link_count(&self.path).unwrap_or(1) as _,
             st_mode,
             st_uid: 1000,
             st_gid: 1000,
@@ -106,11 +141,18 @@ impl FileLike for File {
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        if let Some(cache) = FILE_CACHE.get() {
+            cache.before_read(&self.path, offset, buf.len());
+        }
         Ok(self.inner.lock().read_at(offset, buf)?)
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> LinuxResult<usize> {
-        Ok(self.inner.lock().write_at(offset, buf)?)
+        let len = self.inner.lock().write_at(offset, buf)?;
+        if let Some(cache) = FILE_CACHE.get() {
+            cache.after_write(&self.path, offset, &buf[..len]);
+        }
+        Ok(len)
     }
 }
 
@@ -153,12 +195,19 @@ pub fn sys_open(filename: *const c_char, flags: c_int, mode: ctypes::mode_t) ->
     let filename = char_ptr_to_str(filename);
     debug!("sys_open <= {:?} {:#o} {:#o}", filename, flags, mode);
     syscall_body!(sys_open, {
-        add_file_or_directory_fd(
+        let filename = filename?;
+        let fd = add_file_or_directory_fd(
             axfs::fops::File::open,
             axfs::fops::Directory::open_dir,
-            filename?,
+            filename,
             &flags_to_options(flags, mode),
-        )
+        )?;
+        if flags as u32 & ctypes::O_TRUNC != 0 {
+            if let Some(cache) = FILE_CACHE.get() {
+                cache.after_truncate(filename, 0);
+            }
+        }
+        Ok(fd)
     })
 }
 
@@ -426,7 +475,7 @@ impl FileLike for Directory {
         let st_mode = ((ty as u32) << 12) | perm;
         Ok(ctypes::stat {
             st_ino: 1,
-            st_nlink: 1,
+            st_nlink: axfs::api::link_count(&self.path).unwrap_or(1) as _,
             st_mode,
             st_uid: 1000,
             st_gid: 1000,
@@ -205,6 +205,9 @@ pub unsafe fn sys_epoll_wait(
                 debug!("    timeout!");
                 return Ok(0);
             }
+            if crate::imp::task::current_interrupted() {
+                return Err(LinuxError::EINTR);
+            }
             crate::sys_sched_yield();
         }
     })
@@ -16,6 +16,8 @@ pub mod io_mpx;
 pub mod net;
 #[cfg(feature = "fs")]
 pub mod path_link;
+#[cfg(feature = "fd")]
+pub mod pidfd;
 #[cfg(feature = "pipe")]
 pub mod pipe;
 #[cfg(feature = "multitask")]
@@ -0,0 +1,85 @@
+use alloc::sync::Arc;
+use core::ffi::c_int;
+
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+
+use super::fd_ops::{FileLike, add_file_like};
+use crate::ctypes;
+
+/// A file descriptor referring to a process, as created by `pidfd_open`.
+///
+/// It becomes readable once the process exits.
+pub struct PidFd {
+    pid: usize,
+    exited: Arc<dyn Fn() -> bool + Send + Sync>,
+}
+
+impl PidFd {
+    /// Creates a pidfd for the process `pid`, given a function telling
+    /// whether the process has exited.
+    pub fn new(pid: usize, exited: Arc<dyn Fn() -> bool + Send + Sync>) -> Self {
+        Self { pid, exited }
+    }
+
+    /// Returns the ID of the referred process.
+    pub fn pid(&self) -> usize {
+        self.pid
+    }
+
+    /// Gets the pidfd by `fd`.
+    pub fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        let f = super::fd_ops::get_file_like(fd)?;
+        f.into_any()
+            .downcast::<Self>()
+            .map_err(|_| LinuxError::EINVAL)
+    }
+
+    /// Adds the pidfd to the file descriptor table.
+    pub fn add_to_fd_table(self) -> LinuxResult<c_int> {
+        add_file_like(Arc::new(self))
+    }
+}
+
+impl FileLike for PidFd {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o600,
+            st_blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: (self.exited)(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+}
@@ -6,6 +6,7 @@ use axio::PollState;
 use axsync::Mutex;
 
 use super::fd_ops::{FileLike, add_file_like, close_file_like};
+use super::task::current_interrupted;
 use crate::ctypes;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -133,6 +134,13 @@ impl FileLike for Pipe {
                     return Ok(read_size);
                 }
                 drop(ring_buffer);
+                if current_interrupted() {
+                    return if read_size > 0 {
+                        Ok(read_size)
+                    } else {
+                        Err(LinuxError::EINTR)
+                    };
+                }
                 // Data not ready, wait for write end
                 crate::sys_sched_yield(); // TODO: use synconize primitive
                 continue;
@@ -158,6 +166,13 @@ impl FileLike for Pipe {
             let mut ring_buffer = self.buffer.lock();
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
+                if current_interrupted() {
+                    return if write_size > 0 {
+                        Ok(write_size)
+                    } else {
+                        Err(LinuxError::EINTR)
+                    };
+                }
                 ring_buffer.set_waiter(1);      // 单核场景
                 drop(ring_buffer);
                 // Buffer is full, wait for read end to consume
@@ -4,6 +4,18 @@ use core::ffi::c_int;
 ///
 /// For single-threaded configuration (`multitask` feature is disabled), we just
 /// relax the CPU and wait for incoming interrupts.
+/// Whether the current task is asked to give up its blocking operations.
+pub(crate) fn current_interrupted() -> bool {
+    #[cfg(feature = "multitask")]
+    {
+        axtask::current().is_interrupted()
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        false
+    }
+}
+
 pub fn sys_sched_yield() -> c_int {
     #[cfg(feature = "multitask")]
     axtask::yield_now();
@@ -33,7 +33,7 @@ pub mod ctypes;
 
 pub use imp::io::{sys_read, sys_write, sys_writev, sys_readv, sys_pread64};
 #[cfg(feature = "fs")]
-pub use imp::path_link::{AT_FDCWD, FilePath, HARDLINK_MANAGER, handle_file_path};
+pub use imp::path_link::{AT_FDCWD, FilePath, handle_file_path};
 pub use imp::resources::{sys_getrlimit, sys_setrlimit};
 pub use imp::sys::sys_sysconf;
 pub use imp::task::{sys_exit, sys_getpid, sys_sched_yield};
@@ -41,11 +41,12 @@ pub use imp::time::{sys_clock_gettime, sys_get_time_of_day, sys_nanosleep};
 pub use imp::pthread::{query_futex, add_futex, remove_futex};
 #[cfg(feature = "fd")]
 pub use imp::fd_ops::{
-    FD_TABLE, add_file_like, get_file_like, sys_close, sys_dup, sys_dup2, sys_fcntl, get_table_count,
+    FD_CLOEXEC, FD_TABLE, FileLike, add_file_like, close_on_exec, get_file_like, set_cloexec, sys_close,
+    sys_dup, sys_dup2, sys_fcntl, get_table_count,
 };
 #[cfg(feature = "fs")]
 pub use imp::fs::{
-    Directory, File, sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_openat, sys_rename,
+    Directory, File, FileCache, set_file_cache, sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_openat, sys_rename,
     sys_stat,sys_utime,open_file
 };
 #[cfg(feature = "select")]
@@ -58,6 +59,8 @@ pub use imp::net::{
     sys_getsockname, sys_listen, sys_recv, sys_recvfrom, sys_send, sys_sendto, sys_shutdown,
     sys_socket,
 };
+#[cfg(feature = "fd")]
+pub use imp::pidfd::PidFd;
 #[cfg(feature = "pipe")]
 pub use imp::pipe::sys_pipe;
 #[cfg(feature = "multitask")]
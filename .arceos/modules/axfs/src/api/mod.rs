@@ -5,8 +5,12 @@ mod file;
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use crate::fs::FileSystemStat;
+#[cfg(feature = "devfs")]
+pub use crate::loopdev::LoopStatus;
 
 use alloc::{string::String, vec::Vec};
+use axerrno::LinuxResult;
 use axio::{self as io, prelude::*};
 
 /// Returns an iterator over the entries within a directory.
@@ -89,6 +93,111 @@ pub fn rename(old: &str, new: &str) -> io::Result<()> {
     crate::root::rename(old, new)
 }
 
+/// Creates a new hard link `new` to the file `old`.
+///
+/// This only works on the filesystems supporting hard links, and when both
+/// paths are in the same mounted fs.
+pub fn hard_link(old: &str, new: &str) -> io::Result<()> {
+    crate::root::link(old, new)
+}
+
+/// Returns the number of hard links to a file or directory.
+pub fn link_count(path: &str) -> io::Result<u64> {
+    crate::root::link_count(path)
+}
+
+/// Returns the identifier of the filesystem of a file, and its inode number
+/// if the filesystem supports hard links.
+pub fn file_id(path: &str) -> io::Result<(u64, Option<u64>)> {
+    crate::root::file_id(path)
+}
+
+/// Creates a symbolic link `path` pointing to `target`.
+///
+/// This only works on the filesystems supporting links.
+pub fn symlink(target: &str, path: &str) -> io::Result<()> {
+    crate::root::symlink(target, path)
+}
+
+/// Reads the target of a symbolic link.
+pub fn read_link(path: &str) -> io::Result<String> {
+    crate::root::read_link(path)
+}
+
+/// Resolves a path into an absolute path without symbolic links, following
+/// the last component too if `follow_last` is set or the path ends with '/'.
+///
+/// Fails with `ELOOP` after too many symbolic links.
+pub fn resolve_path(path: &str, follow_last: bool) -> LinuxResult<String> {
+    crate::root::resolve_path(path, follow_last)
+}
+
+/// Checks whether two paths are in the same mounted fs.
+pub fn same_filesystem(path: &str, other: &str) -> io::Result<bool> {
+    crate::root::same_filesystem(path, other)
+}
+
+/// Mounts a filesystem of type `fstype` on the directory `target`.
+///
+/// `source` is a block device such as `/dev/vdb` for `vfat` and `ext4`, and
+/// is only shown in `/proc/mounts` for `tmpfs`.
+pub fn mount(source: &str, target: &str, fstype: &str, read_only: bool) -> io::Result<()> {
+    crate::root::mount(source, target, fstype, read_only)
+}
+
+/// Mounts the directory `source` on the directory `target` too.
+pub fn bind_mount(source: &str, target: &str, read_only: bool) -> io::Result<()> {
+    crate::root::bind_mount(source, target, read_only)
+}
+
+/// Unmounts the filesystem mounted on `path`.
+///
+/// It fails with `ResourceBusy` if filesystems are mounted below, unless
+/// `detach` is set, which unmounts them too.
+pub fn umount(path: &str, detach: bool) -> io::Result<()> {
+    crate::root::umount(path, detach)
+}
+
+/// Changes whether the filesystem mounted on `path` is read-only.
+pub fn remount(path: &str, read_only: bool) -> io::Result<()> {
+    crate::root::remount(path, read_only)
+}
+
+/// Returns the statistics of the mounted filesystem holding a path.
+pub fn statfs(path: &str) -> io::Result<FileSystemStat> {
+    crate::root::statfs(path)
+}
+
+/// Checks whether a path is on a read-only mount.
+pub fn is_read_only(path: &str) -> io::Result<bool> {
+    crate::root::is_read_only(path)
+}
+
+/// Binds the loop device `dev`, such as `/dev/loop0`, to the regular file
+/// `file`, so that the filesystem image in it can be mounted.
+///
+/// It fails with `Unsupported` if `dev` is not a loop device, and with
+/// `ResourceBusy` if it is bound already.
+#[cfg(feature = "devfs")]
+pub fn loop_attach(dev: &str, file: &str, read_only: bool) -> io::Result<()> {
+    crate::loopdev::attach(dev, file, read_only)
+}
+
+/// Unbinds the loop device `dev`, failing with `BadState` if it is not bound.
+///
+/// The filesystems mounted from it keep the file until they are unmounted.
+#[cfg(feature = "devfs")]
+pub fn loop_detach(dev: &str) -> io::Result<()> {
+    crate::loopdev::detach(dev)
+}
+
+/// Returns the status of the loop device `dev`, failing with `BadState` if it
+/// is not bound.
+#[cfg(feature = "devfs")]
+pub fn loop_status(dev: &str) -> io::Result<LoopStatus> {
+    crate::loopdev::status(dev)
+}
+
 /// check whether absolute path exists.
 pub fn absolute_path_exists(path: &str) -> bool {
     crate::root::lookup(None, path).is_ok()
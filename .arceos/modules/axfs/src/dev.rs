@@ -1,18 +1,116 @@
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
 use axdriver::prelude::*;
+use axerrno::{AxResult, ax_err};
+use axsync::Mutex;
 
-const BLOCK_SIZE: usize = 512;
+pub(crate) const BLOCK_SIZE: usize = 512;
+
+/// A block device which filesystems can be mounted from, named as in `/dev`.
+pub trait BlockDevice: Send + Sync {
+    /// Opens the device for a filesystem to be mounted.
+    fn open(&self) -> AxResult<Disk>;
+}
+
+/// A block device driver found at boot, as a [`BlockDriverOps`] object in both
+/// the static and dynamic driver modes.
+struct AxDriver(AxBlockDevice);
+
+impl BaseDriverOps for AxDriver {
+    fn device_name(&self) -> &str {
+        self.0.device_name()
+    }
+
+    fn device_type(&self) -> DeviceType {
+        self.0.device_type()
+    }
+}
+
+impl BlockDriverOps for AxDriver {
+    fn num_blocks(&self) -> u64 {
+        self.0.num_blocks()
+    }
+
+    fn block_size(&self) -> usize {
+        self.0.block_size()
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        self.0.read_block(block_id, buf)
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        self.0.write_block(block_id, buf)
+    }
+
+    fn flush(&mut self) -> DevResult {
+        self.0.flush()
+    }
+}
+
+/// A block device driver.
+struct DriverDevice(Arc<Mutex<dyn BlockDriverOps>>);
+
+impl BlockDevice for DriverDevice {
+    fn open(&self) -> AxResult<Disk> {
+        Ok(Disk::from_shared(self.0.clone()))
+    }
+}
+
+static BLOCK_DEVICES: Mutex<BTreeMap<String, Arc<dyn BlockDevice>>> = Mutex::new(BTreeMap::new());
+
+/// Registers the block device `dev` as `/dev/{name}`.
+pub fn register_block_device(name: &str, dev: Arc<dyn BlockDevice>) -> AxResult {
+    let mut devices = BLOCK_DEVICES.lock();
+    if devices.contains_key(name) {
+        return ax_err!(AlreadyExists);
+    }
+    devices.insert(name.into(), dev);
+    Ok(())
+}
+
+/// Unregisters the block device `/dev/{name}`.
+pub fn unregister_block_device(name: &str) -> AxResult {
+    match BLOCK_DEVICES.lock().remove(name) {
+        Some(_) => Ok(()),
+        None => ax_err!(NotFound),
+    }
+}
+
+/// Registers the block device driver `dev` as `/dev/{name}`.
+pub(crate) fn register_driver(name: &str, dev: AxBlockDevice) -> AxResult {
+    let driver = Arc::new(Mutex::new(AxDriver(dev)));
+    register_block_device(name, Arc::new(DriverDevice(driver)))
+}
+
+/// Opens the block device at `path` for a filesystem to be mounted.
+///
+/// It fails with `Unsupported` (`ENODEV`) if there is no such device.
+pub(crate) fn open_block_device(path: &str) -> AxResult<Disk> {
+    let dev = path
+        .strip_prefix("/dev/")
+        .and_then(|name| BLOCK_DEVICES.lock().get(name).cloned());
+    match dev {
+        Some(dev) => dev.open(),
+        None => ax_err!(Unsupported, "no such block device"),
+    }
+}
 
 /// A disk device with a cursor.
 pub struct Disk {
     block_id: u64,
     offset: usize,
-    dev: AxBlockDevice,
+    dev: Arc<Mutex<dyn BlockDriverOps>>,
 }
 
 impl Disk {
     /// Create a new disk.
     pub fn new(dev: AxBlockDevice) -> Self {
-        assert_eq!(BLOCK_SIZE, dev.block_size());
+        Self::from_shared(Arc::new(Mutex::new(AxDriver(dev))))
+    }
+
+    /// Create a new disk on a driver which other disks may share.
+    pub fn from_shared(dev: Arc<Mutex<dyn BlockDriverOps>>) -> Self {
+        assert_eq!(BLOCK_SIZE, dev.lock().block_size());
         Self {
             block_id: 0,
             offset: 0,
@@ -22,7 +120,7 @@ impl Disk {
 
     /// Get the size of the disk.
     pub fn size(&self) -> u64 {
-        self.dev.num_blocks() * BLOCK_SIZE as u64
+        self.dev.lock().num_blocks() * BLOCK_SIZE as u64
     }
 
     /// Get the position of the cursor.
@@ -41,7 +139,7 @@ impl Disk {
         let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
             // whole block
             let mut data = [0u8; BLOCK_SIZE];
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.dev.lock().read_block(self.block_id, &mut data)?;
             buf[0..BLOCK_SIZE].copy_from_slice(&data);
             // self.dev
             //     .read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
@@ -53,7 +151,7 @@ impl Disk {
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.dev.lock().read_block(self.block_id, &mut data)?;
             buf[..count].copy_from_slice(&data[start..start + count]);
 
             self.offset += count;
@@ -70,7 +168,9 @@ impl Disk {
     pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
         let write_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
             // whole block
-            self.dev.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
+            self.dev
+                .lock()
+                .write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
             self.block_id += 1;
             BLOCK_SIZE
         } else {
@@ -79,9 +179,9 @@ impl Disk {
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.dev.lock().read_block(self.block_id, &mut data)?;
             data[start..start + count].copy_from_slice(&buf[..count]);
-            self.dev.write_block(self.block_id, &data)?;
+            self.dev.lock().write_block(self.block_id, &data)?;
 
             self.offset += count;
             if self.offset >= BLOCK_SIZE {
@@ -99,6 +199,7 @@ impl Disk {
         let block_id = offset / BLOCK_SIZE;
         let mut block_data = [0u8; BLOCK_SIZE];
         self.dev
+            .lock()
             .read_block(block_id as u64, &mut block_data)
             .unwrap();
         block_data
@@ -113,7 +214,7 @@ impl Disk {
         );
         assert!(offset % BLOCK_SIZE == 0);
         let block_id = offset / BLOCK_SIZE;
-        self.dev.write_block(block_id as u64, buf).unwrap();
+        self.dev.lock().write_block(block_id as u64, buf).unwrap();
         Ok(buf.len())
     }
 }
@@ -286,6 +286,11 @@ impl File {
         self.access_node(Cap::empty())?.get_attr()
     }
 
+    /// Checks whether the file is opened for writing.
+    pub fn is_writable(&self) -> bool {
+        self.node.can_access(Cap::WRITE)
+    }
+
     pub fn set_time(&mut self, atime:[isize;2], mtime:[isize;2]){
         info!("atime:{:?}, mtime:{:?}", atime, mtime);
         if atime[1] != -1{
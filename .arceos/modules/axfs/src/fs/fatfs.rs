@@ -1,22 +1,30 @@
-use alloc::sync::Arc;
-use core::cell::UnsafeCell;
+use alloc::sync::{Arc, Weak};
 
 use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
 use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
 
+use super::{FileSystemStat, VfsStatOps};
 use crate::dev::Disk;
 
 const BLOCK_SIZE: usize = 512;
 
 pub struct FatFileSystem {
     inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
-    root_dir: UnsafeCell<Option<VfsNodeRef>>,
+    this: Weak<Self>,
 }
 
-pub struct FileWrapper<'a, IO: IoTrait>(Mutex<File<'a, IO, NullTimeProvider, LossyOemCpConverter>>);
-pub struct DirWrapper<'a, IO: IoTrait>(Dir<'a, IO, NullTimeProvider, LossyOemCpConverter>);
+// The nodes borrow their filesystem, which they keep alive with the second
+// field. Fields are dropped in order, so the borrow ends first.
+pub struct FileWrapper<'a, IO: IoTrait>(
+    Mutex<File<'a, IO, NullTimeProvider, LossyOemCpConverter>>,
+    Arc<dyn VfsOps>,
+);
+pub struct DirWrapper<'a, IO: IoTrait>(
+    Dir<'a, IO, NullTimeProvider, LossyOemCpConverter>,
+    Arc<dyn VfsOps>,
+);
 
 pub trait IoTrait: Read + Write + Seek {}
 
@@ -29,42 +37,50 @@ unsafe impl<'a, IO: IoTrait> Sync for DirWrapper<'a, IO> {}
 
 impl FatFileSystem {
     #[cfg(feature = "use-ramdisk")]
-    pub fn new(mut disk: Disk) -> Self {
+    pub fn new(mut disk: Disk) -> Arc<Self> {
         let opts = fatfs::FormatVolumeOptions::new();
         fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
         let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
             .expect("failed to initialize FAT filesystem");
-        Self {
-            inner,
-            root_dir: UnsafeCell::new(None),
-        }
+        Self::from_inner(inner)
     }
 
     #[cfg(not(feature = "use-ramdisk"))]
-    pub fn new(disk: Disk) -> Self {
+    pub fn new(disk: Disk) -> Arc<Self> {
         let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
             .expect("failed to initialize FAT filesystem");
-        Self {
-            inner,
-            root_dir: UnsafeCell::new(None),
-        }
+        Self::from_inner(inner)
     }
 
-    pub fn init(&'static self) {
-        // must be called before later operations
-        unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
+    /// Mounts the FAT filesystem on `disk` after boot.
+    ///
+    /// The filesystem is freed once unmounted and all its nodes are dropped.
+    pub fn mount_disk(disk: Disk) -> VfsResult<Arc<Self>> {
+        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new()).map_err(as_vfs_err)?;
+        Ok(Self::from_inner(inner))
+    }
+
+    fn from_inner(
+        inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
+    ) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            inner,
+            this: this.clone(),
+        })
     }
 
     fn new_file<IO: IoTrait>(
         file: File<'_, IO, NullTimeProvider, LossyOemCpConverter>,
-    ) -> Arc<FileWrapper<IO>> {
-        Arc::new(FileWrapper(Mutex::new(file)))
+        fs: Arc<dyn VfsOps>,
+    ) -> Arc<FileWrapper<'_, IO>> {
+        Arc::new(FileWrapper(Mutex::new(file), fs))
     }
 
     fn new_dir<IO: IoTrait>(
         dir: Dir<'_, IO, NullTimeProvider, LossyOemCpConverter>,
-    ) -> Arc<DirWrapper<IO>> {
-        Arc::new(DirWrapper(dir))
+        fs: Arc<dyn VfsOps>,
+    ) -> Arc<DirWrapper<'_, IO>> {
+        Arc::new(DirWrapper(dir, fs))
     }
 }
 
@@ -112,9 +128,9 @@ impl<IO: IoTrait> VfsNodeOps for DirWrapper<'static, IO> {
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
-        self.0
-            .open_dir("..")
-            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir)))
+        self.0.open_dir("..").map_or(None, |dir| {
+            Some(FatFileSystem::new_dir(dir, self.1.clone()))
+        })
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
@@ -129,9 +145,9 @@ impl<IO: IoTrait> VfsNodeOps for DirWrapper<'static, IO> {
 
         // TODO: use `fatfs::Dir::find_entry`, but it's not public.
         if let Ok(file) = self.0.open_file(path) {
-            Ok(FatFileSystem::new_file(file))
+            Ok(FatFileSystem::new_file(file, self.1.clone()))
         } else if let Ok(dir) = self.0.open_dir(path) {
-            Ok(FatFileSystem::new_dir(dir))
+            Ok(FatFileSystem::new_dir(dir, self.1.clone()))
         } else {
             Err(VfsError::NotFound)
         }
@@ -204,10 +220,27 @@ impl<IO: IoTrait> VfsNodeOps for DirWrapper<'static, IO> {
     }
 }
 
+impl VfsStatOps for FatFileSystem {
+    fn statfs(&self) -> VfsResult<FileSystemStat> {
+        // FAT has no inodes, so no inode counts, as in Linux.
+        let stats = self.inner.stats().map_err(as_vfs_err)?;
+        Ok(FileSystemStat {
+            block_size: stats.cluster_size() as u64,
+            blocks: stats.total_clusters() as u64,
+            blocks_free: stats.free_clusters() as u64,
+            blocks_avail: stats.free_clusters() as u64,
+            name_max: 255,
+            ..Default::default()
+        })
+    }
+}
+
 impl VfsOps for FatFileSystem {
     fn root_dir(&self) -> VfsNodeRef {
-        let root_dir = unsafe { (*self.root_dir.get()).as_ref().unwrap() };
-        root_dir.clone()
+        let fs = self.this.upgrade().unwrap();
+        // SAFETY: the directory keeps `fs` alive while it borrows it.
+        let inner = unsafe { &*(&self.inner as *const fatfs::FileSystem<_, _, _>) };
+        Self::new_dir(inner.root_dir(), fs)
     }
 }
 
@@ -294,13 +327,13 @@ impl Clone for FileWrapper<'static, Disk> {
     fn clone(&self) -> Self {
         let file = self.0.lock();
         let cloned_file = file.clone();
-        Self(Mutex::new(cloned_file))
+        Self(Mutex::new(cloned_file), self.1.clone())
     }
 }
 
 pub struct FatFileSystemFromFile {
     inner: fatfs::FileSystem<FileWrapper<'static, Disk>, NullTimeProvider, LossyOemCpConverter>,
-    root_dir: UnsafeCell<Option<VfsNodeRef>>,
+    this: Weak<Self>,
 }
 
 unsafe impl Sync for FatFileSystemFromFile {}
@@ -308,25 +341,22 @@ unsafe impl Send for FatFileSystemFromFile {}
 
 #[allow(unused)]
 impl FatFileSystemFromFile {
-    pub fn new(file: FileWrapper<'static, Disk>) -> Self {
+    pub fn new(file: FileWrapper<'static, Disk>) -> Arc<Self> {
         let inner = fatfs::FileSystem::new(file, fatfs::FsOptions::new())
             .expect("failed to initialize FAT filesystem");
-        Self {
+        Arc::new_cyclic(|this| Self {
             inner,
-            root_dir: UnsafeCell::new(None),
-        }
-    }
-
-    pub fn init(&'static self) {
-        // must be called before later operations
-        unsafe { *self.root_dir.get() = Some(FatFileSystem::new_dir(self.inner.root_dir())) }
+            this: this.clone(),
+        })
     }
 }
 
 impl VfsOps for FatFileSystemFromFile {
     fn root_dir(&self) -> VfsNodeRef {
-        let root_dir = unsafe { (*self.root_dir.get()).as_ref().unwrap() };
-        root_dir.clone()
+        let fs = self.this.upgrade().unwrap();
+        // SAFETY: the directory keeps `fs` alive while it borrows it.
+        let inner = unsafe { &*(&self.inner as *const fatfs::FileSystem<_, _, _>) };
+        FatFileSystem::new_dir(inner.root_dir(), fs)
     }
 }
 
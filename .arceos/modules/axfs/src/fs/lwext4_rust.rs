@@ -1,21 +1,32 @@
 use crate::alloc::string::String;
+use alloc::ffi::CString;
 use alloc::sync::Arc;
+use alloc::vec;
 use axerrno::AxError;
 use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lwext4_rust::bindings::{
-    O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_CUR, SEEK_END, SEEK_SET,
+    O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_CUR, SEEK_END, SEEK_SET, ext4_flink,
+    ext4_fsymlink, ext4_inode, ext4_mount_point_stats, ext4_mount_stats, ext4_raw_inode_fill,
+    ext4_readlink,
 };
 use lwext4_rust::{Ext4BlockWrapper, Ext4File, InodeTypes, KernelDevOp};
 
+use super::{FileSystemStat, VfsNodeLinkOps, VfsStatOps};
 use crate::dev::Disk;
 pub const BLOCK_SIZE: usize = 512;
+/// The maximum length of a symbolic link target.
+const PATH_MAX: usize = 4096;
+
+/// Whether an ext4 filesystem is mounted.
+static EXT4_MOUNTED: AtomicBool = AtomicBool::new(false);
 
 #[allow(dead_code)]
 pub struct Ext4FileSystem {
     inner: Ext4BlockWrapper<Disk>,
-    root: VfsNodeRef,
+    root: Arc<FileWrapper>,
 }
 
 unsafe impl Sync for Ext4FileSystem {}
@@ -29,15 +40,61 @@ impl Ext4FileSystem {
 
     #[cfg(not(feature = "use-ramdisk"))]
     pub fn new(disk: Disk) -> Self {
+        Self::try_new(disk).expect("failed to initialize EXT4 filesystem")
+    }
+
+    /// Mounts the ext4 filesystem on `disk`.
+    ///
+    /// Mounting a second ext4 filesystem is not supported: it fails with
+    /// `Unsupported` while another one is mounted, since [`Ext4BlockWrapper`]
+    /// registers all of them as the same lwext4 device and mount point.
+    pub fn try_new(disk: Disk) -> VfsResult<Self> {
         info!(
             "Got Disk size:{}, position:{}",
             disk.size(),
             disk.position()
         );
-        let inner =
-            Ext4BlockWrapper::<Disk>::new(disk).expect("failed to initialize EXT4 filesystem");
+        if EXT4_MOUNTED.swap(true, Ordering::AcqRel) {
+            return Err(VfsError::Unsupported);
+        }
+        let inner = Ext4BlockWrapper::<Disk>::new(disk).map_err(|_| {
+            EXT4_MOUNTED.store(false, Ordering::Release);
+            VfsError::InvalidData
+        })?;
         let root = Arc::new(FileWrapper::new("/", InodeTypes::EXT4_DE_DIR));
-        Self { inner, root }
+        Ok(Self { inner, root })
+    }
+
+    /// Returns the root directory, which also provides the hard link
+    /// operations.
+    pub fn link_root(&self) -> Arc<FileWrapper> {
+        self.root.clone()
+    }
+}
+
+impl VfsStatOps for Ext4FileSystem {
+    fn statfs(&self) -> VfsResult<FileSystemStat> {
+        // lwext4 mounts the filesystem on "/".
+        let mut stats: ext4_mount_stats = unsafe { core::mem::zeroed() };
+        if unsafe { ext4_mount_point_stats(c"/".as_ptr(), &mut stats) } != 0 {
+            return Err(VfsError::Io);
+        }
+        Ok(FileSystemStat {
+            block_size: stats.block_size as u64,
+            blocks: stats.blocks_count,
+            blocks_free: stats.free_blocks_count,
+            blocks_avail: stats.free_blocks_count,
+            files: stats.inodes_count as u64,
+            files_free: stats.free_inodes_count as u64,
+            name_max: 255,
+            ..Default::default()
+        })
+    }
+}
+
+impl Drop for Ext4FileSystem {
+    fn drop(&mut self) {
+        EXT4_MOUNTED.store(false, Ordering::Release);
     }
 }
 
@@ -48,7 +105,7 @@ impl VfsOps for Ext4FileSystem {
     fn root_dir(&self) -> VfsNodeRef {
         trace!("Get root_dir");
         //let root_dir = unsafe { (*self.root.get()).as_ref().unwrap() };
-        Arc::clone(&self.root)
+        self.root.clone()
     }
 }
 
@@ -124,6 +181,13 @@ impl VfsNodeOps for FileWrapper {
             let fsize = file.file_size();
             let _ = file.file_close();
             fsize
+        } else if vtype == VfsNodeType::SymLink {
+            // The size of a symbolic link is the length of its target.
+            let path = file.get_path();
+            let mut buf = vec![0u8; PATH_MAX];
+            let mut len = 0;
+            unsafe { ext4_readlink(path.as_ptr(), buf.as_mut_ptr() as _, buf.len(), &mut len) };
+            len as u64
         } else {
             0 // DIR size ?
         };
@@ -266,6 +330,9 @@ impl VfsNodeOps for FileWrapper {
         } else if file.check_inode_exist(fpath, InodeTypes::EXT4_DE_REG_FILE) {
             trace!("lookup new FILE FileWrapper");
             Ok(Arc::new(Self::new(fpath, InodeTypes::EXT4_DE_REG_FILE)))
+        } else if file.check_inode_exist(fpath, InodeTypes::EXT4_DE_SYMLINK) {
+            trace!("lookup new SYMLINK FileWrapper");
+            Ok(Arc::new(Self::new(fpath, InodeTypes::EXT4_DE_SYMLINK)))
         } else {
             Err(VfsError::NotFound)
         }
@@ -326,6 +393,83 @@ impl VfsNodeOps for FileWrapper {
     }
 }
 
+impl FileWrapper {
+    /// Returns the number and the on-disk inode of the node at `path`.
+    fn raw_inode(&self, path: &str) -> VfsResult<(u32, ext4_inode)> {
+        let fpath = self.path_deal_with(path);
+        let fpath = if fpath.is_empty() {
+            String::from(self.0.lock().get_path().to_str().unwrap())
+        } else {
+            fpath
+        };
+        let fpath = CString::new(fpath).map_err(|_| VfsError::InvalidInput)?;
+        let mut ino = 0;
+        let mut inode: ext4_inode = unsafe { core::mem::zeroed() };
+        let _file = self.0.lock();
+        match unsafe { ext4_raw_inode_fill(fpath.as_ptr(), &mut ino, &mut inode) } {
+            0 => Ok((ino, inode)),
+            e => Err(e.try_into().unwrap()),
+        }
+    }
+}
+
+/// Links are native in ext4: hard links are directory entries referring to
+/// the same inode, which is freed by `file_remove` once its last link is
+/// gone, and symbolic links keep their targets in their inodes.
+impl VfsNodeLinkOps for FileWrapper {
+    fn link(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        info!("link ext4fs: {} -> {}", dst_path, src_path);
+        let src =
+            CString::new(self.path_deal_with(src_path)).map_err(|_| VfsError::InvalidInput)?;
+        let dst =
+            CString::new(self.path_deal_with(dst_path)).map_err(|_| VfsError::InvalidInput)?;
+        let _file = self.0.lock();
+        match unsafe { ext4_flink(src.as_ptr(), dst.as_ptr()) } {
+            0 => Ok(()),
+            e => Err(e.try_into().unwrap()),
+        }
+    }
+
+    fn nlink(&self, path: &str) -> VfsResult<u64> {
+        let (_, inode) = self.raw_inode(path)?;
+        Ok(u16::from_le(inode.links_count) as u64)
+    }
+
+    fn ino(&self, path: &str) -> VfsResult<u64> {
+        let (ino, _) = self.raw_inode(path)?;
+        Ok(ino as u64)
+    }
+
+    fn symlink(&self, target: &str, path: &str) -> VfsResult {
+        info!("symlink ext4fs: {} -> {}", path, target);
+        let target = CString::new(target).map_err(|_| VfsError::InvalidInput)?;
+        let fpath = CString::new(self.path_deal_with(path)).map_err(|_| VfsError::InvalidInput)?;
+        let _file = self.0.lock();
+        match unsafe { ext4_fsymlink(target.as_ptr(), fpath.as_ptr()) } {
+            0 => Ok(()),
+            e => Err(e.try_into().unwrap()),
+        }
+    }
+
+    fn read_link(&self, path: &str) -> VfsResult<String> {
+        let fpath = self.path_deal_with(path);
+        let mut file = self.0.lock();
+        if !file.check_inode_exist(&fpath, InodeTypes::EXT4_DE_SYMLINK) {
+            return Err(VfsError::InvalidInput);
+        }
+        let fpath = CString::new(fpath).map_err(|_| VfsError::InvalidInput)?;
+        let mut buf = vec![0u8; PATH_MAX];
+        let mut len = 0;
+        match unsafe { ext4_readlink(fpath.as_ptr(), buf.as_mut_ptr() as _, buf.len(), &mut len) } {
+            0 => {
+                buf.truncate(len);
+                String::from_utf8(buf).map_err(|_| VfsError::InvalidData)
+            }
+            e => Err(e.try_into().unwrap()),
+        }
+    }
+}
+
 impl Drop for FileWrapper {
     fn drop(&mut self) {
         let mut file = self.0.lock();
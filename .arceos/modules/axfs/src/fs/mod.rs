@@ -1,16 +1,142 @@
-cfg_if::cfg_if! {
-    if #[cfg(feature = "myfs")] {
-        pub mod myfs;
-    } else if #[cfg(feature = "lwext4_rs")] {
-        pub mod lwext4_rust;
-    } else if #[cfg(feature = "fatfs")] {
-        pub mod fatfs;
-    }
+use alloc::string::String;
+use axfs_vfs::{VfsDirEntry, VfsNodeRef, VfsOps, VfsResult};
 
-}
+#[cfg(feature = "myfs")]
+pub mod myfs;
+
+#[cfg(feature = "lwext4_rs")]
+pub mod lwext4_rust;
+
+#[cfg(feature = "fatfs")]
+pub mod fatfs;
 
 #[cfg(feature = "devfs")]
 pub use axfs_devfs as devfs;
 
 #[cfg(feature = "ramfs")]
 pub use axfs_ramfs as ramfs;
+
+#[cfg(feature = "mqueuefs")]
+pub mod mqueue;
+
+#[cfg(feature = "procfs")]
+pub mod sysvipc;
+
+/// Hard and symbolic link operations, which [`axfs_vfs::VfsNodeOps`] lacks,
+/// provided by the root directories of the filesystems supporting links.
+///
+/// The paths are relative to the directory.
+pub trait VfsNodeLinkOps: Send + Sync {
+    /// Creates the hard link `dst_path` to the file `src_path`.
+    fn link(&self, src_path: &str, dst_path: &str) -> VfsResult;
+
+    /// Returns the number of hard links to the node at `path`.
+    fn nlink(&self, path: &str) -> VfsResult<u64>;
+
+    /// Returns the inode number of the node at `path`, which its hard links
+    /// share.
+    fn ino(&self, path: &str) -> VfsResult<u64>;
+
+    /// Creates the symbolic link `path` pointing to `target`, which is
+    /// stored as is.
+    fn symlink(&self, target: &str, path: &str) -> VfsResult;
+
+    /// Returns the target of the symbolic link at `path`, failing with
+    /// `InvalidInput` if it is not a symbolic link.
+    fn read_link(&self, path: &str) -> VfsResult<String>;
+}
+
+/// The statistics of a filesystem, as in `statfs(2)`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystemStat {
+    /// The magic number of the filesystem type.
+    pub magic: u64,
+    /// The identifier of the mounted filesystem.
+    pub fsid: u64,
+    /// The size of the blocks counted below, in bytes.
+    pub block_size: u64,
+    /// The total number of blocks.
+    pub blocks: u64,
+    /// The number of free blocks.
+    pub blocks_free: u64,
+    /// The number of free blocks available to unprivileged users.
+    pub blocks_avail: u64,
+    /// The total number of inodes.
+    pub files: u64,
+    /// The number of free inodes.
+    pub files_free: u64,
+    /// The maximum length of file names.
+    pub name_max: u64,
+    /// Whether the filesystem is mounted read-only.
+    pub read_only: bool,
+}
+
+/// The `statfs` operation, which [`axfs_vfs::VfsOps`] lacks.
+///
+/// The magic number, identifier and read-only flag are filled in by the mount
+/// table, from the filesystem type and the mount.
+pub trait VfsStatOps: Send + Sync {
+    /// Returns the block and inode counts of the filesystem.
+    fn statfs(&self) -> VfsResult<FileSystemStat>;
+}
+
+/// The block size reported by the filesystems in memory.
+const PAGE_SIZE: u64 = 4096;
+
+/// Counts the nodes and the pages of data in the tree of the directory `dir`,
+/// itself included, for the filesystems in memory.
+fn count_tree(dir: VfsNodeRef) -> VfsResult<(u64, u64)> {
+    let (mut nodes, mut pages) = (1, 0);
+    let mut dirents: [VfsDirEntry; 16] = [const { VfsDirEntry::default() }; 16];
+    let mut idx = 0;
+    loop {
+        let n = dir.read_dir(idx, &mut dirents)?;
+        if n == 0 {
+            break;
+        }
+        for entry in &dirents[..n] {
+            let name = core::str::from_utf8(entry.name_as_bytes()).unwrap_or_default();
+            if name.is_empty() || name == "." || name == ".." {
+                continue;
+            }
+            let node = dir.clone().lookup(name)?;
+            if entry.entry_type().is_dir() {
+                let (sub_nodes, sub_pages) = count_tree(node)?;
+                nodes += sub_nodes;
+                pages += sub_pages;
+            } else {
+                nodes += 1;
+                pages += node.get_attr()?.size().div_ceil(PAGE_SIZE);
+            }
+        }
+        idx += n;
+    }
+    Ok((nodes, pages))
+}
+
+/// Reports the nodes and the pages in use, without any free, as Linux does for
+/// the filesystems in memory.
+fn memory_statfs(fs: &dyn VfsOps) -> VfsResult<FileSystemStat> {
+    let (files, blocks) = count_tree(fs.root_dir())?;
+    Ok(FileSystemStat {
+        block_size: PAGE_SIZE,
+        blocks,
+        files,
+        name_max: 255,
+        ..Default::default()
+    })
+}
+
+#[cfg(feature = "ramfs")]
+impl VfsStatOps for ramfs::RamFileSystem {
+    fn statfs(&self) -> VfsResult<FileSystemStat> {
+        memory_statfs(self)
+    }
+}
+
+#[cfg(feature = "devfs")]
+impl VfsStatOps for devfs::DeviceFileSystem {
+    fn statfs(&self) -> VfsResult<FileSystemStat> {
+        memory_statfs(self)
+    }
+}
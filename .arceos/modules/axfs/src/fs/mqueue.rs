@@ -0,0 +1,130 @@
+//! The filesystem of POSIX message queues, mounted on `/dev/mqueue`.
+//!
+//! It is a flat directory whose entries are the queues, created by the
+//! kernel and removed by `mq_unlink` or `unlink`.
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef};
+use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
+use spin::{Once, RwLock};
+
+use super::{FileSystemStat, VfsStatOps};
+
+static MQUEUE_FS: Once<Arc<MqueueFileSystem>> = Once::new();
+
+/// The filesystem of POSIX message queues.
+pub struct MqueueFileSystem {
+    root: Arc<MqueueDir>,
+}
+
+impl MqueueFileSystem {
+    /// Returns the filesystem, creating it on the first call.
+    pub fn get() -> Arc<Self> {
+        MQUEUE_FS
+            .call_once(|| {
+                Arc::new(Self {
+                    root: Arc::new(MqueueDir {
+                        entries: RwLock::new(BTreeMap::new()),
+                    }),
+                })
+            })
+            .clone()
+    }
+
+    /// Returns the root directory, holding the queues.
+    pub fn root(&self) -> Arc<MqueueDir> {
+        self.root.clone()
+    }
+}
+
+impl VfsOps for MqueueFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+impl VfsStatOps for MqueueFileSystem {
+    fn statfs(&self) -> VfsResult<FileSystemStat> {
+        // The queues and the root directory, which use no blocks.
+        Ok(FileSystemStat {
+            block_size: 4096,
+            files: self.root.entries.read().len() as u64 + 1,
+            name_max: 255,
+            ..Default::default()
+        })
+    }
+}
+
+/// The root directory of the filesystem, holding the queues by name.
+pub struct MqueueDir {
+    entries: RwLock<BTreeMap<String, VfsNodeRef>>,
+}
+
+impl MqueueDir {
+    /// Returns the queue named `name`.
+    pub fn get(&self, name: &str) -> Option<VfsNodeRef> {
+        self.entries.read().get(name).cloned()
+    }
+
+    /// Adds the queue `node` named `name`.
+    pub fn add(&self, name: &str, node: VfsNodeRef) -> VfsResult {
+        let mut entries = self.entries.write();
+        if entries.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        entries.insert(name.into(), node);
+        Ok(())
+    }
+}
+
+impl VfsNodeOps for MqueueDir {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o777),
+            VfsNodeType::Dir,
+            0,
+            0,
+        ))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        match path.trim_matches('/') {
+            "" | "." => Ok(self),
+            name => self.get(name).ok_or(VfsError::NotFound),
+        }
+    }
+
+    fn create(&self, _path: &str, _ty: VfsNodeType) -> VfsResult {
+        // Queues are only created by `mq_open`.
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        let name = path.trim_matches('/');
+        self.entries
+            .write()
+            .remove(name)
+            .map(|_| ())
+            .ok_or(VfsError::NotFound)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries = self.entries.read();
+        let mut iter = entries.iter().skip(start_idx.saturating_sub(2));
+        for (i, out_entry) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *out_entry = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *out_entry = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    let Some((name, node)) = iter.next() else {
+                        return Ok(i);
+                    };
+                    *out_entry = VfsDirEntry::new(name, node.get_attr()?.file_type());
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+}
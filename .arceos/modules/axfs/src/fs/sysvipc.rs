@@ -0,0 +1,123 @@
+//! The directory `/proc/sysvipc`, mounted inside procfs.
+//!
+//! Its files list the System V IPC objects. They are added by the kernel,
+//! which generates the listings when they are read.
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef};
+use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
+use spin::{Once, RwLock};
+
+use super::{FileSystemStat, VfsStatOps};
+
+static SYSVIPC_FS: Once<Arc<SysvIpcFileSystem>> = Once::new();
+
+/// The filesystem of the directory `/proc/sysvipc`.
+pub struct SysvIpcFileSystem {
+    root: Arc<SysvIpcDir>,
+}
+
+impl SysvIpcFileSystem {
+    /// Returns the filesystem, creating it on the first call.
+    pub fn get() -> Arc<Self> {
+        SYSVIPC_FS
+            .call_once(|| {
+                Arc::new(Self {
+                    root: Arc::new(SysvIpcDir {
+                        entries: RwLock::new(BTreeMap::new()),
+                    }),
+                })
+            })
+            .clone()
+    }
+
+    /// Returns the root directory, holding the listings.
+    pub fn root(&self) -> Arc<SysvIpcDir> {
+        self.root.clone()
+    }
+}
+
+impl VfsOps for SysvIpcFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+impl VfsStatOps for SysvIpcFileSystem {
+    fn statfs(&self) -> VfsResult<FileSystemStat> {
+        Ok(FileSystemStat {
+            block_size: 4096,
+            files: self.root.entries.read().len() as u64 + 1,
+            name_max: 255,
+            ..Default::default()
+        })
+    }
+}
+
+/// The root directory of the filesystem, holding the listings by name.
+pub struct SysvIpcDir {
+    entries: RwLock<BTreeMap<String, VfsNodeRef>>,
+}
+
+impl SysvIpcDir {
+    /// Adds the listing `node` named `name`.
+    pub fn add(&self, name: &str, node: VfsNodeRef) -> VfsResult {
+        let mut entries = self.entries.write();
+        if entries.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        entries.insert(name.into(), node);
+        Ok(())
+    }
+}
+
+impl VfsNodeOps for SysvIpcDir {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o555),
+            VfsNodeType::Dir,
+            0,
+            0,
+        ))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        match path.trim_matches('/') {
+            "" | "." => Ok(self),
+            name => self
+                .entries
+                .read()
+                .get(name)
+                .cloned()
+                .ok_or(VfsError::NotFound),
+        }
+    }
+
+    fn create(&self, _path: &str, _ty: VfsNodeType) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries = self.entries.read();
+        let mut iter = entries.iter().skip(start_idx.saturating_sub(2));
+        for (i, out_entry) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *out_entry = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *out_entry = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    let Some((name, node)) = iter.next() else {
+                        return Ok(i);
+                    };
+                    *out_entry = VfsDirEntry::new(name, node.get_attr()?.file_type());
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+}
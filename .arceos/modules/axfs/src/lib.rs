@@ -6,10 +6,16 @@
 //!
 //! - `fatfs`: Use [FAT] as the main filesystem and mount it on `/`. This feature
 //!    is **enabled** by default.
-//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
+//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`, with the loop
+//!    devices `/dev/loopN` for mounting filesystem images. This feature is
 //!    **enabled** by default.
 //! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
 //!    **enabled** by default.
+//! - `mqueuefs`: Mount the filesystem of POSIX message queues on
+//!    `/dev/mqueue`. This feature is **enabled** by default.
+//! - `lwext4_rs`: Use ext4 as the main filesystem instead of FAT. Both FAT and
+//!    ext4 filesystems on other block devices can be mounted when their
+//!    features are enabled.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
@@ -28,13 +34,21 @@ extern crate alloc;
 
 mod dev;
 mod fs;
+#[cfg(feature = "devfs")]
+mod loopdev;
 mod mounts;
 mod root;
 
 pub mod api;
 pub mod fops;
+pub use dev::{BlockDevice, Disk, register_block_device, unregister_block_device};
 pub use root::{CURRENT_DIR, CURRENT_DIR_PATH};
 
+#[cfg(feature = "mqueuefs")]
+pub use fs::mqueue;
+#[cfg(feature = "procfs")]
+pub use fs::sysvipc;
+
 use axdriver::{AxDeviceContainer, prelude::*};
 
 /// Initializes filesystems by block devices.
@@ -44,4 +58,16 @@ pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     let dev = blk_devs.take_one().expect("No block device found!");
     info!("  use block device 0: {:?}", dev.device_name());
     self::root::init_rootfs(self::dev::Disk::new(dev));
+
+    // The other devices are left for mounting, as /dev/vdb, /dev/vdc, ...
+    for (i, dev) in core::iter::from_fn(|| blk_devs.take_one()).enumerate() {
+        let name = alloc::format!("vd{}", (b'b' + i as u8) as char);
+        info!(
+            "  block device {}: {:?} as /dev/{}",
+            i + 1,
+            dev.device_name(),
+            name
+        );
+        self::dev::register_driver(&name, dev).ok();
+    }
 }
@@ -0,0 +1,238 @@
+//! Loop block devices, `/dev/loopN`, which make regular files usable as block
+//! devices, so that filesystem images can be mounted.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use axdriver::prelude::*;
+use axerrno::{AxResult, ax_err};
+use axfs_vfs::{
+    VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult,
+};
+use axsync::Mutex;
+use lazyinit::LazyInit;
+
+use crate::dev::{BLOCK_SIZE, BlockDevice, Disk};
+
+/// The names of the loop devices, created at boot as in Linux.
+const LOOP_NAMES: [&str; 8] = [
+    "loop0", "loop1", "loop2", "loop3", "loop4", "loop5", "loop6", "loop7",
+];
+
+static LOOP_DEVICES: LazyInit<Vec<Arc<LoopDevice>>> = LazyInit::new();
+
+/// The status of a loop device.
+pub struct LoopStatus {
+    /// The index `N` of `/dev/loopN`.
+    pub number: usize,
+    /// The path of the backing file.
+    pub file_name: String,
+    /// Whether the device is read-only.
+    pub read_only: bool,
+}
+
+/// A loop device, which may be bound to a backing file.
+pub struct LoopDevice {
+    index: usize,
+    backing: Mutex<Option<Backing>>,
+}
+
+/// The file bound to a loop device.
+struct Backing {
+    path: String,
+    file: VfsNodeRef,
+    read_only: bool,
+    driver: Arc<Mutex<LoopDriver>>,
+}
+
+/// The block driver of a bound loop device, reading and writing the blocks of
+/// the backing file.
+///
+/// The filesystems mounted from the device keep using it after the file is
+/// unbound, until they are unmounted.
+struct LoopDriver {
+    file: VfsNodeRef,
+    read_only: bool,
+}
+
+impl BaseDriverOps for LoopDriver {
+    fn device_name(&self) -> &str {
+        "loop"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+}
+
+impl BlockDriverOps for LoopDriver {
+    fn num_blocks(&self) -> u64 {
+        self.file
+            .get_attr()
+            .map_or(0, |attr| attr.size() / BLOCK_SIZE as u64)
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        let offset = block_id * BLOCK_SIZE as u64;
+        let mut pos = 0;
+        while pos < buf.len() {
+            match self.file.read_at(offset + pos as u64, &mut buf[pos..]) {
+                Ok(0) => {
+                    buf[pos..].fill(0); // beyond the end of the file
+                    break;
+                }
+                Ok(n) => pos += n,
+                Err(_) => return Err(DevError::Io),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        if self.read_only {
+            return Err(DevError::Unsupported);
+        }
+        let offset = block_id * BLOCK_SIZE as u64;
+        let mut pos = 0;
+        while pos < buf.len() {
+            match self.file.write_at(offset + pos as u64, &buf[pos..]) {
+                Ok(0) | Err(_) => return Err(DevError::Io),
+                Ok(n) => pos += n,
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> DevResult {
+        self.file.fsync().map_err(|_| DevError::Io)
+    }
+}
+
+impl LoopDevice {
+    /// Returns the name in `/dev`.
+    pub(crate) fn name(&self) -> &'static str {
+        LOOP_NAMES[self.index]
+    }
+
+    fn backing_file(&self) -> Option<VfsNodeRef> {
+        self.backing.lock().as_ref().map(|b| b.file.clone())
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    fn open(&self) -> AxResult<Disk> {
+        match self.backing.lock().as_ref() {
+            Some(backing) => Ok(Disk::from_shared(backing.driver.clone())),
+            None => ax_err!(InvalidInput, "loop device not bound"),
+        }
+    }
+}
+
+impl VfsNodeOps for LoopDevice {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let size = match self.backing_file() {
+            Some(file) => file.get_attr()?.size(),
+            None => 0,
+        };
+        let perm = VfsNodePerm::from_bits_truncate(0o660);
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::BlockDevice, size, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        match self.backing_file() {
+            Some(file) => file.read_at(offset, buf),
+            None => Ok(0),
+        }
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let file = match self.backing.lock().as_ref() {
+            Some(b) if b.read_only => return Err(VfsError::PermissionDenied),
+            Some(b) => b.file.clone(),
+            None => return Err(VfsError::BadState),
+        };
+        file.write_at(offset, buf)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(()) // the size of a device is fixed
+    }
+}
+
+/// Creates the loop devices, and registers them as block devices.
+pub(crate) fn init() -> &'static [Arc<LoopDevice>] {
+    let devices = (0..LOOP_NAMES.len())
+        .map(|index| {
+            let dev = Arc::new(LoopDevice {
+                index,
+                backing: Mutex::new(None),
+            });
+            crate::dev::register_block_device(dev.name(), dev.clone()).ok();
+            dev
+        })
+        .collect();
+    LOOP_DEVICES.init_once(devices)
+}
+
+/// Returns the loop device at `path`, failing with `Unsupported` if it is not
+/// a loop device.
+fn device(path: &str) -> AxResult<&'static Arc<LoopDevice>> {
+    let devices = LOOP_DEVICES
+        .get()
+        .map_or(&[][..], |devices| devices.as_slice());
+    let name = path.strip_prefix("/dev/").unwrap_or_default();
+    match LOOP_NAMES.iter().position(|&n| n == name) {
+        Some(index) if index < devices.len() => Ok(&devices[index]),
+        _ => ax_err!(Unsupported, "not a loop device"),
+    }
+}
+
+/// Binds the loop device `dev` to the regular file `file`, failing with
+/// `ResourceBusy` if it is bound already.
+pub(crate) fn attach(dev: &str, file: &str, read_only: bool) -> AxResult {
+    let dev = device(dev)?;
+    let node = crate::root::lookup(None, file)?;
+    if !node.get_attr()?.is_file() {
+        return ax_err!(InvalidInput, "not a regular file");
+    }
+    let mut backing = dev.backing.lock();
+    if backing.is_some() {
+        return ax_err!(ResourceBusy, "loop device bound already");
+    }
+    *backing = Some(Backing {
+        path: crate::root::absolute_path(file)?,
+        file: node.clone(),
+        read_only,
+        driver: Arc::new(Mutex::new(LoopDriver {
+            file: node,
+            read_only,
+        })),
+    });
+    Ok(())
+}
+
+/// Unbinds the loop device `dev`, failing with `BadState` if it is not bound.
+pub(crate) fn detach(dev: &str) -> AxResult {
+    match device(dev)?.backing.lock().take() {
+        Some(_) => Ok(()),
+        None => ax_err!(BadState, "loop device not bound"),
+    }
+}
+
+/// Returns the status of the loop device `dev`, failing with `BadState` if it
+/// is not bound.
+pub(crate) fn status(dev: &str) -> AxResult<LoopStatus> {
+    let dev = device(dev)?;
+    match dev.backing.lock().as_ref() {
+        Some(backing) => Ok(LoopStatus {
+            number: dev.index,
+            file_name: backing.path.clone(),
+            read_only: backing.read_only,
+        }),
+        None => ax_err!(BadState, "loop device not bound"),
+    }
+}
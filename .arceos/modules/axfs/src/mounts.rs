@@ -13,6 +13,9 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     devfs.add("null", Arc::new(null));
     devfs.add("zero", Arc::new(zero));
     foo_dir.add("bar", Arc::new(bar));
+    for dev in crate::loopdev::init() {
+        devfs.add(dev.name(), dev.clone());
+    }
     Arc::new(devfs)
 }
 
@@ -21,6 +24,16 @@ pub(crate) fn ramfs() -> Arc<fs::ramfs::RamFileSystem> {
     Arc::new(fs::ramfs::RamFileSystem::new())
 }
 
+#[cfg(feature = "mqueuefs")]
+pub(crate) fn mqueuefs() -> Arc<fs::mqueue::MqueueFileSystem> {
+    fs::mqueue::MqueueFileSystem::get()
+}
+
+#[cfg(feature = "procfs")]
+pub(crate) fn sysvipcfs() -> Arc<fs::sysvipc::SysvIpcFileSystem> {
+    fs::sysvipc::SysvIpcFileSystem::get()
+}
+
 #[cfg(feature = "procfs")]
 pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
     let procfs = fs::ramfs::RamFileSystem::new();
@@ -40,10 +53,16 @@ pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
     let file_over = proc_root.clone().lookup("./sys/vm/overcommit_memory")?;
     file_over.write_at(0, b"0\n")?;
 
+    // Create /proc/mounts, filled in as filesystems are mounted
+    proc_root.create("mounts", VfsNodeType::File)?;
+
     // Create /proc/self/stat
     proc_root.create("self", VfsNodeType::Dir)?;
     proc_root.create("self/stat", VfsNodeType::File)?;
 
+    // Create /proc/sysvipc, where the listings are mounted
+    proc_root.create("sysvipc", VfsNodeType::Dir)?;
+
     Ok(Arc::new(procfs))
 }
 
@@ -1,18 +1,18 @@
-//! Root directory of the filesystem
-//!
-//! TODO: it doesn't work very well if the mount points have containment relationships.
+//! Root directory of the filesystem, and the table of the filesystems mounted
+//! in it.
 
-use alloc::{string::String, sync::Arc, vec::Vec};
-use axerrno::{AxError, AxResult, ax_err};
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult, ax_err};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use axns::{ResArc, def_resource};
 use axsync::Mutex;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use lazyinit::LazyInit;
 use spin::RwLock;
 
 use crate::{
     api::FileType,
-    fs::{self},
+    fs::{self, FileSystemStat, VfsNodeLinkOps, VfsStatOps},
     mounts,
 };
 
@@ -35,21 +35,89 @@ impl CURRENT_DIR {
     }
 }
 
+/// A filesystem mounted in the tree.
 struct MountPoint {
-    path: &'static str,
+    /// The absolute path, `/` for the main filesystem.
+    path: String,
     fs: Arc<dyn VfsOps>,
+    /// The link operations of the filesystem, if it supports links, and the
+    /// path of the mounted directory relative to them.
+    links: Option<(Arc<dyn VfsNodeLinkOps>, String)>,
+    /// The `statfs` operation of the filesystem, if it supports it.
+    stat: Option<Arc<dyn VfsStatOps>>,
+    /// The identifier reported by `statfs`, shared by the bind mounts.
+    fsid: u64,
+    /// The source and type shown in `/proc/mounts`.
+    source: String,
+    fstype: String,
+    read_only: AtomicBool,
 }
 
+/// A directory of another mounted filesystem, mounted again by a bind mount.
+struct BindFileSystem(VfsNodeRef);
+
 struct RootDirectory {
-    main_fs: Arc<dyn VfsOps>,
-    mounts: RwLock<Vec<MountPoint>>,
+    root: Arc<MountPoint>,
+    mounts: RwLock<Vec<Arc<MountPoint>>>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
+/// The maximum number of symbolic links followed in a path, as in Linux.
+const MAX_SYMLINKS: usize = 40;
+
+/// The identifier of the next mounted filesystem.
+static NEXT_FSID: AtomicU64 = AtomicU64::new(1);
+
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    pub fn new(
+        path: &str,
+        fs: Arc<dyn VfsOps>,
+        source: &str,
+        fstype: &str,
+        read_only: bool,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            fs,
+            links: None,
+            stat: None,
+            fsid: NEXT_FSID.fetch_add(1, Ordering::Relaxed),
+            source: source.into(),
+            fstype: fstype.into(),
+            read_only: AtomicBool::new(read_only),
+        }
+    }
+
+    fn with_stat(mut self, stat: Arc<dyn VfsStatOps>) -> Self {
+        self.stat = Some(stat);
+        self
+    }
+
+    /// Returns the statistics of the filesystem, with no counts if it does
+    /// not support `statfs`.
+    fn statfs(&self) -> AxResult<FileSystemStat> {
+        let mut stat = match &self.stat {
+            Some(ops) => ops.statfs()?,
+            None => FileSystemStat {
+                name_max: 255,
+                ..Default::default()
+            },
+        };
+        stat.magic = fs_magic(&self.fstype);
+        stat.fsid = self.fsid;
+        stat.read_only = self.read_only();
+        Ok(stat)
+    }
+
+    fn read_only(&self) -> bool {
+        self.read_only.load(Ordering::Acquire)
+    }
+
+    /// Checks whether `path`, without the leading '/', is in this mount.
+    fn contains(&self, path: &str) -> bool {
+        path.strip_prefix(&self.path[1..])
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
     }
 }
 
@@ -59,67 +127,205 @@ impl Drop for MountPoint {
     }
 }
 
+impl VfsOps for BindFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.0.clone()
+    }
+}
+
 impl RootDirectory {
-    pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+    pub fn new(root: MountPoint) -> Self {
         Self {
-            main_fs,
+            root: Arc::new(root),
             mounts: RwLock::new(Vec::new()),
         }
     }
 
-    pub fn mount(&self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    /// Mounts `mp` on the directory `node`.
+    fn mount(&self, mp: MountPoint, node: VfsNodeRef) -> AxResult {
+        let path = mp.path.as_str();
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
         if !path.starts_with('/') {
             return ax_err!(InvalidInput, "mount path must start with '/'");
         }
-        if self.mounts.read().iter().any(|mp| mp.path == path) {
-            return ax_err!(InvalidInput, "mount point already exists");
+        if self.contains(path) {
+            return ax_err!(ResourceBusy, "mount point already exists");
         }
-        // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.write().push(MountPoint::new(path, fs));
+        if !node.get_attr()?.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        mp.fs.mount(path, node)?;
+        self.mounts.write().push(Arc::new(mp));
+        self.update_proc_mounts();
         Ok(())
     }
 
-    pub fn _umount(&self, path: &str) {
-        self.mounts.write().retain(|mp| mp.path != path);
+    /// Mounts `fs` on `path` at boot, creating the directory in the main
+    /// filesystem if it does not exist.
+    fn mount_boot<F>(&self, path: &str, fs: Arc<F>, fstype: &str) -> AxResult
+    where
+        F: VfsOps + VfsStatOps + 'static,
+    {
+        let main_root = self.root.fs.root_dir();
+        main_root.create(path, FileType::Dir)?;
+        let node = main_root.lookup(path)?;
+        let mp = MountPoint::new(path, fs.clone(), fstype, fstype, false).with_stat(fs);
+        self.mount(mp, node)
+    }
+
+    /// Mounts `mp` on the existing directory `mp.path`.
+    fn mount_at(&self, mp: MountPoint) -> AxResult {
+        let node =
+            self.lookup_mounted_fs(&mp.path, |fs, rest_path| fs.root_dir().lookup(rest_path))?;
+        self.mount(mp, node)
+    }
+
+    /// Unmounts the filesystem at `path`.
+    ///
+    /// It fails with `ResourceBusy` if filesystems are mounted below, unless
+    /// `detach` is set, which unmounts them too. The filesystems are released
+    /// once the operations on them finish.
+    fn umount(&self, path: &str, detach: bool) -> AxResult {
+        let path = path.trim_end_matches('/');
+        let below = |mp: &MountPoint| {
+            mp.path
+                .strip_prefix(path)
+                .is_some_and(|rest| rest.starts_with('/'))
+        };
+        let mut mounts = self.mounts.write();
+        if !mounts.iter().any(|mp| mp.path == path) {
+            return ax_err!(InvalidInput, "not a mount point");
+        }
+        if !detach && mounts.iter().any(|mp| below(mp)) {
+            return ax_err!(ResourceBusy, "filesystems mounted below");
+        }
+        let (removed, kept): (Vec<_>, Vec<_>) = mounts
+            .drain(..)
+            .partition(|mp| mp.path == path || below(mp));
+        *mounts = kept;
+        drop(mounts);
+        drop(removed);
+        self.update_proc_mounts();
+        Ok(())
+    }
+
+    /// Changes whether the mount at `path` is read-only.
+    fn remount(&self, path: &str, read_only: bool) -> AxResult {
+        let path = path.trim_end_matches('/');
+        let mp = if path.is_empty() {
+            self.root.clone()
+        } else {
+            match self.mounts.read().iter().find(|mp| mp.path == path) {
+                Some(mp) => mp.clone(),
+                None => return ax_err!(InvalidInput, "not a mount point"),
+            }
+        };
+        mp.read_only.store(read_only, Ordering::Release);
+        self.update_proc_mounts();
+        Ok(())
+    }
+
+    /// Returns a bind mount of the directory `source` on `target`.
+    fn bind(&self, source: &str, target: &str, read_only: bool) -> AxResult<MountPoint> {
+        let (src, rest_path) = self.lookup_mount(source);
+        let node = src.fs.root_dir().lookup(rest_path)?;
+        if !node.get_attr()?.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        let fs = Arc::new(BindFileSystem(node));
+        let mut mp = MountPoint::new(target, fs, &src.source, &src.fstype, read_only);
+        mp.stat = src.stat.clone();
+        mp.fsid = src.fsid;
+        mp.links = src
+            .links
+            .as_ref()
+            .map(|(ops, base)| (ops.clone(), format!("{base}{rest_path}")));
+        Ok(mp)
     }
 
     pub fn contains(&self, path: &str) -> bool {
+        let path = path.trim_end_matches('/');
         self.mounts.read().iter().any(|mp| mp.path == path)
     }
 
-    fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
-    where
-        F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
-    {
+    /// Lists the mounts in the format of `/proc/mounts`.
+    fn mounts_info(&self) -> String {
+        let mounts = self.mounts.read();
+        let mut info = String::new();
+        for mp in core::iter::once(&self.root).chain(mounts.iter()) {
+            let mode = if mp.read_only() { "ro" } else { "rw" };
+            info += &format!("{} {} {} {} 0 0\n", mp.source, mp.path, mp.fstype, mode);
+        }
+        info
+    }
+
+    /// Rewrites `/proc/mounts` after the mounts change.
+    fn update_proc_mounts(&self) {
+        let info = self.mounts_info();
+        let file = self.lookup_mounted_fs("/proc/mounts", |fs, rest_path| {
+            fs.root_dir().lookup(rest_path)
+        });
+        if let Ok(file) = file {
+            file.truncate(0).ok();
+            file.write_at(0, info.as_bytes()).ok();
+        }
+    }
+
+    /// Returns the mount holding `path`, and the path relative to it.
+    fn lookup_mount<'a>(&self, path: &'a str) -> (Arc<MountPoint>, &'a str) {
         debug!("lookup at root: {}", path);
         let path = path.trim_matches('/');
         if let Some(rest) = path.strip_prefix("./") {
-            return self.lookup_mounted_fs(rest, f);
+            return self.lookup_mount(rest);
         }
 
-        let mut idx = 0;
-        let mut max_len = 0;
-
-        // Find the filesystem that has the longest mounted path match
+        // Find the mount with the longest path containing `path`
         // TODO: more efficient, e.g. trie
-        for (i, mp) in self.mounts.read().iter().enumerate() {
+        let mounts = self.mounts.read();
+        match mounts
+            .iter()
+            .filter(|mp| mp.contains(path))
+            .max_by_key(|mp| mp.path.len())
+        {
             // skip the first '/'
-            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
-                max_len = mp.path.len() - 1;
-                idx = i;
-            }
+            Some(mp) => (mp.clone(), &path[mp.path.len() - 1..]),
+            None => (self.root.clone(), path), // not matched any mount point
         }
+    }
 
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(self.mounts.read()[idx].fs.clone(), &path[max_len..]) // matched at `idx`
+    /// Like [`Self::lookup_mount`], but fails if the mount is read-only.
+    fn lookup_writable_mount<'a>(&self, path: &'a str) -> AxResult<(Arc<MountPoint>, &'a str)> {
+        let (mp, rest_path) = self.lookup_mount(path);
+        if mp.read_only() {
+            return ax_err!(PermissionDenied, "read-only filesystem");
         }
+        Ok((mp, rest_path))
+    }
+
+    fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
+    where
+        F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
+    {
+        let (mp, rest_path) = self.lookup_mount(path);
+        f(mp.fs.clone(), rest_path)
+    }
+
+    /// Returns the link operations of the filesystem holding `path`, and the
+    /// path relative to them.
+    fn link_ops(&self, path: &str) -> AxResult<(Arc<dyn VfsNodeLinkOps>, String)> {
+        let (mp, rest_path) = self.lookup_mount(path);
+        match &mp.links {
+            Some((ops, base)) => Ok((ops.clone(), format!("{base}{rest_path}"))),
+            None => ax_err!(Unsupported, "links not supported"),
+        }
+    }
+
+    /// Checks whether `path` and `other` are in the same mounted fs.
+    fn same_fs(&self, path: &str, other: &str) -> AxResult<bool> {
+        let fs = self.lookup_mounted_fs(path, |fs, _| Ok(fs))?;
+        self.lookup_mounted_fs(other, |other_fs, _| Ok(Arc::ptr_eq(&fs, &other_fs)))
     }
 }
 
@@ -127,7 +333,7 @@ impl VfsNodeOps for RootDirectory {
     axfs_vfs::impl_vfs_dir_default! {}
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        self.main_fs.root_dir().get_attr()
+        self.root.fs.root_dir().get_attr()
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
@@ -135,33 +341,32 @@ impl VfsNodeOps for RootDirectory {
     }
 
     fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
-        self.lookup_mounted_fs(path, |fs, rest_path| {
-            if rest_path.is_empty() {
-                Ok(()) // already exists
-            } else {
-                fs.root_dir().create(rest_path, ty)
-            }
-        })
+        let (mp, rest_path) = self.lookup_mount(path);
+        if rest_path.is_empty() {
+            Ok(()) // already exists
+        } else if mp.read_only() {
+            ax_err!(PermissionDenied, "read-only filesystem")
+        } else {
+            mp.fs.root_dir().create(rest_path, ty)
+        }
     }
 
     fn remove(&self, path: &str) -> VfsResult {
-        self.lookup_mounted_fs(path, |fs, rest_path| {
-            if rest_path.is_empty() {
-                ax_err!(PermissionDenied) // cannot remove mount points
-            } else {
-                fs.root_dir().remove(rest_path)
-            }
-        })
+        let (mp, rest_path) = self.lookup_writable_mount(path)?;
+        if rest_path.is_empty() {
+            ax_err!(PermissionDenied) // cannot remove mount points
+        } else {
+            mp.fs.root_dir().remove(rest_path)
+        }
     }
 
     fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
-        self.lookup_mounted_fs(src_path, |fs, rest_path| {
-            if rest_path.is_empty() {
-                ax_err!(PermissionDenied) // cannot rename mount points
-            } else {
-                fs.root_dir().rename(rest_path, dst_path)
-            }
-        })
+        let (mp, rest_path) = self.lookup_writable_mount(src_path)?;
+        if rest_path.is_empty() {
+            ax_err!(PermissionDenied) // cannot rename mount points
+        } else {
+            mp.fs.root_dir().rename(rest_path, dst_path)
+        }
     }
 }
 
@@ -169,40 +374,62 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
             let main_fs = fs::myfs::new_myfs(disk);
+            let links = None;
+            let stat = None;
+            let fstype = "myfs";
         } else if #[cfg(feature = "lwext4_rs")] {
             static EXT4_FS: LazyInit<Arc<fs::lwext4_rust::Ext4FileSystem>> = LazyInit::new();
             EXT4_FS.init_once(Arc::new(fs::lwext4_rust::Ext4FileSystem::new(disk)));
             let main_fs = EXT4_FS.clone();
+            let links: Option<(Arc<dyn VfsNodeLinkOps>, String)> =
+                Some((EXT4_FS.link_root(), String::new()));
+            let stat: Option<Arc<dyn VfsStatOps>> = Some(EXT4_FS.clone());
+            let fstype = "ext4";
         } else if #[cfg(feature = "fatfs")] {
             static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
-            FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
-            FAT_FS.init();
+            FAT_FS.init_once(fs::fatfs::FatFileSystem::new(disk));
             let main_fs = FAT_FS.clone();
+            let links = None;
+            let stat: Option<Arc<dyn VfsStatOps>> = Some(FAT_FS.clone());
+            let fstype = "vfat";
         }
     }
 
-    let root_dir = RootDirectory::new(main_fs);
+    let mut root = MountPoint::new("/", main_fs, ROOT_DISK, fstype, false);
+    root.links = links;
+    root.stat = stat;
+    let root_dir = RootDirectory::new(root);
 
     #[cfg(feature = "devfs")]
     root_dir
-        .mount("/dev", mounts::devfs())
+        .mount_boot("/dev", mounts::devfs(), "devtmpfs")
         .expect("failed to mount devfs at /dev");
 
+    #[cfg(feature = "mqueuefs")]
+    root_dir
+        .mount_boot("/dev/mqueue", mounts::mqueuefs(), "mqueue")
+        .expect("failed to mount mqueuefs at /dev/mqueue");
+
     #[cfg(feature = "ramfs")]
     root_dir
-        .mount("/tmp", mounts::ramfs())
+        .mount_boot("/tmp", mounts::ramfs(), "tmpfs")
         .expect("failed to mount ramfs at /tmp");
 
     // Mount another ramfs as procfs
     #[cfg(feature = "procfs")]
     root_dir // should not fail
-        .mount("/proc", mounts::procfs().unwrap())
+        .mount_boot("/proc", mounts::procfs().unwrap(), "proc")
         .expect("fail to mount procfs at /proc");
 
+    #[cfg(feature = "procfs")]
+    root_dir
+        .mount_boot("/proc/sysvipc", mounts::sysvipcfs(), "proc")
+        .expect("failed to mount the System V IPC listings at /proc/sysvipc");
+
     // Mount another ramfs as sysfs
     #[cfg(feature = "sysfs")]
     root_dir // should not fail
-        .mount("/sys", mounts::sysfs().unwrap())
+        .mount_boot("/sys", mounts::sysfs().unwrap(), "sysfs")
         .expect("fail to mount sysfs at /sys");
 
     ROOT_DIR.init_once(Arc::new(root_dir));
@@ -212,6 +439,52 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     CURRENT_DIR_PATH.init_new(Mutex::new("/".into()));
 }
 
+/// The block device of the root filesystem.
+const ROOT_DISK: &str = "/dev/vda";
+
+/// Creates the mount of a filesystem of type `fstype` on `target`, on the
+/// block device `source`, or an empty one for `tmpfs`.
+#[allow(unused_variables)]
+fn new_mount(source: &str, target: &str, fstype: &str, read_only: bool) -> AxResult<MountPoint> {
+    match fstype {
+        #[cfg(feature = "ramfs")]
+        "tmpfs" => {
+            let fs = mounts::ramfs();
+            Ok(MountPoint::new(target, fs.clone(), source, fstype, read_only).with_stat(fs))
+        }
+        #[cfg(feature = "fatfs")]
+        "vfat" => {
+            let disk = crate::dev::open_block_device(source)?;
+            let fs = fs::fatfs::FatFileSystem::mount_disk(disk)?;
+            Ok(MountPoint::new(target, fs.clone(), source, fstype, read_only).with_stat(fs))
+        }
+        #[cfg(feature = "lwext4_rs")]
+        "ext4" => {
+            let disk = crate::dev::open_block_device(source)?;
+            let fs = Arc::new(fs::lwext4_rust::Ext4FileSystem::try_new(disk)?);
+            let mut mp = MountPoint::new(target, fs.clone(), source, fstype, read_only)
+                .with_stat(fs.clone());
+            let link_root: Arc<dyn VfsNodeLinkOps> = fs.link_root();
+            mp.links = Some((link_root, String::new()));
+            Ok(mp)
+        }
+        _ => ax_err!(Unsupported, "unknown filesystem type"),
+    }
+}
+
+/// Returns the magic number of the filesystem type, as in `statfs(2)`.
+fn fs_magic(fstype: &str) -> u64 {
+    match fstype {
+        "ext4" => 0xef53,
+        "vfat" => 0x4d44,
+        "tmpfs" | "devtmpfs" => 0x0102_1994,
+        "proc" => 0x9fa0,
+        "sysfs" => 0x6265_6572,
+        "mqueue" => 0x1980_0202,
+        _ => 0,
+    }
+}
+
 fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
     if path.starts_with('/') {
         ROOT_DIR.clone()
@@ -329,6 +602,162 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
     }
 }
 
+pub(crate) fn mount(source: &str, target: &str, fstype: &str, read_only: bool) -> AxResult {
+    let target = absolute_path(target)?;
+    let target = target.trim_end_matches('/');
+    let mounted = ROOT_DIR.root.source == source
+        || ROOT_DIR.mounts.read().iter().any(|mp| mp.source == source);
+    if fstype != "tmpfs" && mounted {
+        return ax_err!(ResourceBusy, "device already mounted");
+    }
+    let mp = new_mount(source, target, fstype, read_only)?;
+    ROOT_DIR.mount_at(mp)
+}
+
+pub(crate) fn bind_mount(source: &str, target: &str, read_only: bool) -> AxResult {
+    let target = absolute_path(target)?;
+    let mp = ROOT_DIR.bind(
+        &absolute_path(source)?,
+        target.trim_end_matches('/'),
+        read_only,
+    )?;
+    ROOT_DIR.mount_at(mp)
+}
+
+pub(crate) fn umount(path: &str, detach: bool) -> AxResult {
+    ROOT_DIR.umount(&absolute_path(path)?, detach)
+}
+
+pub(crate) fn remount(path: &str, read_only: bool) -> AxResult {
+    ROOT_DIR.remount(&absolute_path(path)?, read_only)
+}
+
+pub(crate) fn statfs(path: &str) -> AxResult<FileSystemStat> {
+    lookup(None, path)?;
+    ROOT_DIR.lookup_mount(&absolute_path(path)?).0.statfs()
+}
+
+pub(crate) fn is_read_only(path: &str) -> AxResult<bool> {
+    Ok(ROOT_DIR.lookup_mount(&absolute_path(path)?).0.read_only())
+}
+
+pub(crate) fn link(old: &str, new: &str) -> AxResult {
+    if lookup(None, old)?.get_attr()?.is_dir() {
+        return ax_err!(
+            PermissionDenied,
+            "hard links to directories are not allowed"
+        );
+    }
+    if lookup(None, new).is_ok() {
+        return ax_err!(AlreadyExists);
+    }
+    let new = absolute_path(new)?;
+    ROOT_DIR.lookup_writable_mount(&new)?;
+    let (ops, src_path) = ROOT_DIR.link_ops(&absolute_path(old)?)?;
+    let (_, dst_path) = ROOT_DIR.link_ops(&new)?;
+    ops.link(&src_path, &dst_path)
+}
+
+pub(crate) fn link_count(path: &str) -> AxResult<u64> {
+    lookup(None, path)?;
+    match ROOT_DIR.link_ops(&absolute_path(path)?) {
+        Ok((ops, rest_path)) => ops.nlink(&rest_path),
+        Err(_) => Ok(1), // no hard links but the only name
+    }
+}
+
+/// Returns the identifier of the filesystem of the file at `path`, and the
+/// inode number of the file on the filesystems supporting hard links, where
+/// the path does not identify the file.
+pub(crate) fn file_id(path: &str) -> AxResult<(u64, Option<u64>)> {
+    lookup(None, path)?;
+    let path = absolute_path(path)?;
+    let (mp, _) = ROOT_DIR.lookup_mount(&path);
+    let ino = match ROOT_DIR.link_ops(&path) {
+        Ok((ops, rest_path)) => Some(ops.ino(&rest_path)?),
+        Err(_) => None,
+    };
+    Ok((mp.fsid, ino))
+}
+
+pub(crate) fn symlink(target: &str, path: &str) -> AxResult {
+    if lookup(None, path).is_ok() {
+        return ax_err!(AlreadyExists);
+    }
+    let path = absolute_path(path)?;
+    ROOT_DIR.lookup_writable_mount(&path)?;
+    let (ops, rest_path) = ROOT_DIR.link_ops(&path)?;
+    ops.symlink(target, &rest_path)
+}
+
+pub(crate) fn read_link(path: &str) -> AxResult<String> {
+    let (ops, rest_path) = ROOT_DIR.link_ops(&absolute_path(path)?)?;
+    ops.read_link(&rest_path)
+}
+
+/// Resolves `path` into an absolute path without symbolic links.
+///
+/// The last component is followed too if `follow_last` is set or `path` ends
+/// with '/'. Relative targets are resolved from the directory of the link.
+pub(crate) fn resolve_path(path: &str, follow_last: bool) -> LinuxResult<String> {
+    let trailing_slash = path.ends_with('/');
+    let follow_last = follow_last || trailing_slash;
+    let components = |path: &str| {
+        path.split('/')
+            .rev()
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    // The components left to walk, the next one last. The path is not
+    // canonicalized, so that ".." after a link walks up from its target.
+    let mut pending = if path.starts_with('/') {
+        components(path)
+    } else {
+        components(&(CURRENT_DIR_PATH.lock().clone() + path))
+    };
+    let mut resolved = String::new();
+    let mut links = 0;
+    while let Some(name) = pending.pop() {
+        match name.as_str() {
+            "." => continue,
+            ".." => {
+                // The component before must be an existing directory.
+                if !resolved.is_empty() && !lookup(None, &resolved)?.get_attr()?.is_dir() {
+                    return Err(LinuxError::ENOTDIR);
+                }
+                resolved.truncate(resolved.rfind('/').unwrap_or(0));
+                continue;
+            }
+            _ => {}
+        }
+        let next = format!("{resolved}/{name}");
+        if let Some(target) = (follow_last || !pending.is_empty())
+            .then(|| read_link(&next).ok())
+            .flatten()
+        {
+            links += 1;
+            if links > MAX_SYMLINKS {
+                return Err(LinuxError::ELOOP);
+            }
+            if target.starts_with('/') {
+                resolved.clear();
+            }
+            pending.extend(components(&target));
+            continue;
+        }
+        resolved = next;
+    }
+    if resolved.is_empty() || trailing_slash {
+        resolved.push('/');
+    }
+    Ok(resolved)
+}
+
+pub(crate) fn same_filesystem(path: &str, other: &str) -> AxResult<bool> {
+    ROOT_DIR.same_fs(&absolute_path(path)?, &absolute_path(other)?)
+}
+
 pub(crate) fn rename(old: &str, new: &str) -> AxResult {
     if parent_node_of(None, new).lookup(new).is_ok() {
         warn!("dst file already exist, now remove it");
@@ -45,6 +45,52 @@ impl TrapFrame {
     pub const fn arg5(&self) -> usize {
         self.r[5] as _
     }
+
+    /// Whether the trap is from userspace.
+    pub const fn is_user(&self) -> bool {
+        // SPSR_EL1.M[3:0] is `EL0t` (0b0000) for exceptions taken from EL0.
+        self.spsr & 0b1111 == 0
+    }
+
+    /// Gets the instruction pointer.
+    pub const fn get_ip(&self) -> usize {
+        self.elr as _
+    }
+
+    /// Sets the instruction pointer.
+    pub const fn set_ip(&mut self, pc: usize) {
+        self.elr = pc as _;
+    }
+
+    /// Gets the stack pointer.
+    pub const fn get_sp(&self) -> usize {
+        self.usp as _
+    }
+
+    /// Sets the stack pointer.
+    pub const fn set_sp(&mut self, sp: usize) {
+        self.usp = sp as _;
+    }
+
+    /// Sets the 0th function argument.
+    pub const fn set_arg0(&mut self, arg: usize) {
+        self.r[0] = arg as _;
+    }
+
+    /// Sets the 1st function argument.
+    pub const fn set_arg1(&mut self, arg: usize) {
+        self.r[1] = arg as _;
+    }
+
+    /// Sets the 2nd function argument.
+    pub const fn set_arg2(&mut self, arg: usize) {
+        self.r[2] = arg as _;
+    }
+
+    /// Sets the return address register.
+    pub const fn set_ra(&mut self, ra: usize) {
+        self.r[30] = ra as _;
+    }
 }
 
 /// Context to enter user space.
@@ -177,6 +223,16 @@ impl FpState {
     fn switch_to(&mut self, next_fpstate: &FpState) {
         unsafe { fpstate_switch(self, next_fpstate) }
     }
+
+    /// Saves the FP/SIMD registers of the current CPU.
+    pub fn save(&mut self) {
+        unsafe { fpstate_save(self) }
+    }
+
+    /// Loads the FP/SIMD registers of the current CPU.
+    pub fn restore(&self) {
+        unsafe { fpstate_restore(self) }
+    }
 }
 
 /// Saved hardware states of a task.
@@ -348,3 +404,63 @@ unsafe extern "C" fn fpstate_switch(_current_fpstate: &mut FpState, _next_fpstat
         ret",
     )
 }
+
+#[naked]
+#[cfg(feature = "fp_simd")]
+unsafe extern "C" fn fpstate_save(_fpstate: &mut FpState) {
+    naked_asm!(
+        "
+        mrs     x9, fpcr
+        mrs     x10, fpsr
+        stp     q0, q1, [x0, 0 * 16]
+        stp     q2, q3, [x0, 2 * 16]
+        stp     q4, q5, [x0, 4 * 16]
+        stp     q6, q7, [x0, 6 * 16]
+        stp     q8, q9, [x0, 8 * 16]
+        stp     q10, q11, [x0, 10 * 16]
+        stp     q12, q13, [x0, 12 * 16]
+        stp     q14, q15, [x0, 14 * 16]
+        stp     q16, q17, [x0, 16 * 16]
+        stp     q18, q19, [x0, 18 * 16]
+        stp     q20, q21, [x0, 20 * 16]
+        stp     q22, q23, [x0, 22 * 16]
+        stp     q24, q25, [x0, 24 * 16]
+        stp     q26, q27, [x0, 26 * 16]
+        stp     q28, q29, [x0, 28 * 16]
+        stp     q30, q31, [x0, 30 * 16]
+        str     w9, [x0, 64 * 8]
+        str     w10, [x0, 64 * 8 + 4]
+        ret",
+    )
+}
+
+#[naked]
+#[cfg(feature = "fp_simd")]
+unsafe extern "C" fn fpstate_restore(_fpstate: &FpState) {
+    naked_asm!(
+        "
+        ldp     q0, q1, [x0, 0 * 16]
+        ldp     q2, q3, [x0, 2 * 16]
+        ldp     q4, q5, [x0, 4 * 16]
+        ldp     q6, q7, [x0, 6 * 16]
+        ldp     q8, q9, [x0, 8 * 16]
+        ldp     q10, q11, [x0, 10 * 16]
+        ldp     q12, q13, [x0, 12 * 16]
+        ldp     q14, q15, [x0, 14 * 16]
+        ldp     q16, q17, [x0, 16 * 16]
+        ldp     q18, q19, [x0, 18 * 16]
+        ldp     q20, q21, [x0, 20 * 16]
+        ldp     q22, q23, [x0, 22 * 16]
+        ldp     q24, q25, [x0, 24 * 16]
+        ldp     q26, q27, [x0, 26 * 16]
+        ldp     q28, q29, [x0, 28 * 16]
+        ldp     q30, q31, [x0, 30 * 16]
+        ldr     w9, [x0, 64 * 8]
+        ldr     w10, [x0, 64 * 8 + 4]
+        msr     fpcr, x9
+        msr     fpsr, x10
+
+        isb
+        ret",
+    )
+}
@@ -5,9 +5,31 @@ use page_table_entry::MappingFlags;
 use tock_registers::interfaces::Readable;
 
 use super::TrapFrame;
+#[cfg(feature = "uspace")]
+use crate::trap::UserException;
 
 global_asm!(include_str!("trap.S"), cache_current_task_ptr = sym crate::cpu::cache_current_task_ptr);
 
+// `__copy_user(dst, src, len)` returns the number of bytes not copied. A fault
+// resumes at `__copy_user_fixup`, with the count left in `x2`.
+#[cfg(feature = "uspace")]
+global_asm!(
+    "
+    .section .text
+    .global __copy_user
+    .global __copy_user_fixup
+__copy_user:
+    cbz     x2, __copy_user_fixup
+1:
+    ldrb    w3, [x1], #1
+    strb    w3, [x0], #1
+    sub     x2, x2, #1
+    cbnz    x2, 1b
+__copy_user_fixup:
+    mov     x0, x2
+    ret"
+);
+
 #[repr(u8)]
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -37,8 +59,12 @@ fn invalid_exception(tf: &TrapFrame, kind: TrapKind, source: TrapSource) {
 }
 
 #[unsafe(no_mangle)]
-fn handle_irq_exception(_tf: &TrapFrame) {
+fn handle_irq_exception(tf: &mut TrapFrame) {
     handle_trap!(IRQ, 0);
+    #[cfg(feature = "uspace")]
+    if tf.is_user() {
+        crate::trap::dealwith_signal(tf);
+    }
 }
 
 fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
@@ -48,6 +74,11 @@ fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
     let vaddr = va!(FAR_EL1.get() as usize);
 
+    #[cfg(feature = "uspace")]
+    if is_user && !matches!(iss & 0b111100, 0b0100 | 0b1100) {
+        crate::trap::handle_user_exception(tf, UserException::AccessFault, vaddr.as_usize());
+        return;
+    }
     // Only handle Translation fault and Permission fault
     if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
         || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
@@ -64,7 +95,7 @@ fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
 }
 
-fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
+fn handle_data_abort(tf: &mut TrapFrame, iss: u64, is_user: bool) {
     let wnr = (iss & (1 << 6)) != 0; // WnR: Write not Read
     let cm = (iss & (1 << 8)) != 0; // CM: Cache maintenance
     let mut access_flags = if wnr & !cm {
@@ -77,10 +108,26 @@ fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
     let vaddr = va!(FAR_EL1.get() as usize);
 
+    #[cfg(feature = "uspace")]
+    if is_user && !matches!(iss & 0b111100, 0b0100 | 0b1100) {
+        // DFSC 0b100001: Alignment fault
+        let kind = if iss & 0b111111 == 0b100001 {
+            UserException::Misaligned
+        } else {
+            UserException::AccessFault
+        };
+        crate::trap::handle_user_exception(tf, kind, vaddr.as_usize());
+        return;
+    }
     // Only handle Translation fault and Permission fault
     if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
         || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
     {
+        #[cfg(feature = "uspace")]
+        if let Some(fixup) = crate::trap::user_fault_fixup(tf.elr as _) {
+            tf.elr = fixup as _;
+            return;
+        }
         panic!(
             "Unhandled {} Data Abort @ {:#x}, fault_vaddr={:#x}, ISS=0b{:08b} ({:?}):\n{:#x?}",
             if is_user { "EL0" } else { "EL1" },
@@ -110,6 +157,22 @@ fn handle_sync_exception(tf: &mut TrapFrame) {
             debug!("BRK #{:#x} @ {:#x} ", iss, tf.elr);
             tf.elr += 4;
         }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::Unknown | ESR_EL1::EC::Value::IllegalExecutionState)
+            if tf.is_user() =>
+        {
+            crate::trap::handle_user_exception(tf, UserException::IllegalInstruction, tf.elr as _)
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::PCAlignmentFault | ESR_EL1::EC::Value::SPAlignmentFault)
+            if tf.is_user() =>
+        {
+            crate::trap::handle_user_exception(tf, UserException::Misaligned, FAR_EL1.get() as _)
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::TrappedFP64) if tf.is_user() => {
+            crate::trap::handle_user_exception(tf, UserException::FloatingPoint, tf.elr as _)
+        }
         _ => {
             panic!(
                 "Unhandled synchronous exception @ {:#x}: ESR={:#x} (EC {:#08b}, ISS {:#x})",
@@ -120,5 +183,8 @@ fn handle_sync_exception(tf: &mut TrapFrame) {
             );
         }
     }
-    // crate::trap::dealwith_signal();
+    #[cfg(feature = "uspace")]
+    if tf.is_user() {
+        crate::trap::dealwith_signal(tf);
+    }
 }
@@ -1,4 +1,6 @@
 use super::context::TrapFrame;
+#[cfg(feature = "uspace")]
+use crate::trap::UserException;
 use loongArch64::register::{
     badv,
     estat::{self, Exception, Trap},
@@ -11,17 +13,44 @@ core::arch::global_asm!(
     trapframe_size = const (core::mem::size_of::<TrapFrame>()),
 );
 
+// `__copy_user(dst, src, len)` returns the number of bytes not copied. A fault
+// resumes at `__copy_user_fixup`, with the count left in `$a2`.
+#[cfg(feature = "uspace")]
+core::arch::global_asm!(
+    "
+    .section .text
+    .global __copy_user
+    .global __copy_user_fixup
+__copy_user:
+    beqz    $a2, __copy_user_fixup
+1:
+    ld.b    $t0, $a1, 0
+    st.b    $t0, $a0, 0
+    addi.d  $a0, $a0, 1
+    addi.d  $a1, $a1, 1
+    addi.d  $a2, $a2, -1
+    bnez    $a2, 1b
+__copy_user_fixup:
+    move    $a0, $a2
+    jr      $ra"
+);
+
 fn handle_breakpoint(era: &mut usize) {
     debug!("Exception(Breakpoint) @ {:#x} ", era);
     *era += 4;
 }
 
-fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
+fn handle_page_fault(tf: &mut TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
     if is_user {
         access_flags |= MappingFlags::USER;
     }
     let vaddr = va!(badv::read().raw());
     if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+        #[cfg(feature = "uspace")]
+        if let Some(fixup) = crate::trap::user_fault_fixup(tf.era) {
+            tf.era = fixup;
+            return;
+        }
         panic!(
             "Unhandled {} Page Fault @ {:#x}, fault_vaddr={:#x} ({:?}):\n{:#x?}",
             if is_user { "PLV3" } else { "PLV0" },
@@ -56,6 +85,26 @@ fn loongarch64_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             handle_page_fault(tf, MappingFlags::EXECUTE, from_user);
         }
         Trap::Exception(Exception::Breakpoint) => handle_breakpoint(&mut tf.era),
+        #[cfg(feature = "uspace")]
+        Trap::Exception(Exception::InstructionNotExist) if from_user => {
+            crate::trap::handle_user_exception(tf, UserException::IllegalInstruction, tf.era)
+        }
+        #[cfg(feature = "uspace")]
+        Trap::Exception(Exception::InstructionPrivilegeIllegal) if from_user => {
+            crate::trap::handle_user_exception(tf, UserException::PrivilegedInstruction, tf.era)
+        }
+        #[cfg(feature = "uspace")]
+        Trap::Exception(Exception::AddressNotAligned) if from_user => {
+            crate::trap::handle_user_exception(tf, UserException::Misaligned, badv::read().raw())
+        }
+        #[cfg(feature = "uspace")]
+        Trap::Exception(
+            Exception::FetchInstructionAddressError
+            | Exception::MemoryAccessAddressError
+            | Exception::PagePrivilegeIllegal,
+        ) if from_user => {
+            crate::trap::handle_user_exception(tf, UserException::AccessFault, badv::read().raw())
+        }
         Trap::Interrupt(_) => {
             let irq_num: usize = estat.is().trailing_zeros() as usize;
             handle_trap!(IRQ, irq_num);
@@ -69,5 +118,8 @@ fn loongarch64_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             );
         }
     }
-    // crate::trap::dealwith_signal();
+    #[cfg(feature = "uspace")]
+    if from_user {
+        crate::trap::dealwith_signal(tf);
+    }
 }
@@ -81,6 +81,46 @@ impl TrapFrame {
     pub const fn arg5(&self) -> usize {
         self.regs.a5
     }
+
+    /// Gets the instruction pointer.
+    pub const fn get_ip(&self) -> usize {
+        self.sepc
+    }
+
+    /// Sets the instruction pointer.
+    pub const fn set_ip(&mut self, pc: usize) {
+        self.sepc = pc;
+    }
+
+    /// Gets the stack pointer.
+    pub const fn get_sp(&self) -> usize {
+        self.regs.sp
+    }
+
+    /// Sets the stack pointer.
+    pub const fn set_sp(&mut self, sp: usize) {
+        self.regs.sp = sp;
+    }
+
+    /// Sets the 0th function argument.
+    pub const fn set_arg0(&mut self, arg: usize) {
+        self.regs.a0 = arg;
+    }
+
+    /// Sets the 1st function argument.
+    pub const fn set_arg1(&mut self, arg: usize) {
+        self.regs.a1 = arg;
+    }
+
+    /// Sets the 2nd function argument.
+    pub const fn set_arg2(&mut self, arg: usize) {
+        self.regs.a2 = arg;
+    }
+
+    /// Sets the return address register.
+    pub const fn set_ra(&mut self, ra: usize) {
+        self.regs.ra = ra;
+    }
 }
 
 /// Context to enter user space.
@@ -141,6 +181,11 @@ impl UspaceContext {
         self.0.regs.a0 = a0;
     }
 
+    /// Sets the thread pointer register (`tp`) of the user context.
+    pub const fn set_tls(&mut self, tls: usize) {
+        self.0.regs.tp = tls;
+    }
+
     /// Enters user space.
     ///
     /// It restores the user registers and jumps to the user entry point
@@ -4,6 +4,8 @@ use riscv::interrupt::supervisor::{Exception as E, Interrupt as I};
 use riscv::register::{scause, stval};
 
 use super::TrapFrame;
+#[cfg(feature = "uspace")]
+use crate::trap::UserException;
 
 core::arch::global_asm!(
     include_asm_macros!(),
@@ -11,17 +13,44 @@ core::arch::global_asm!(
     trapframe_size = const core::mem::size_of::<TrapFrame>(),
 );
 
+// `__copy_user(dst, src, len)` returns the number of bytes not copied. A fault
+// resumes at `__copy_user_fixup`, with the count left in `a2`.
+#[cfg(feature = "uspace")]
+core::arch::global_asm!(
+    "
+    .section .text
+    .global __copy_user
+    .global __copy_user_fixup
+__copy_user:
+    beqz    a2, __copy_user_fixup
+1:
+    lb      t0, 0(a1)
+    sb      t0, 0(a0)
+    addi    a0, a0, 1
+    addi    a1, a1, 1
+    addi    a2, a2, -1
+    bnez    a2, 1b
+__copy_user_fixup:
+    mv      a0, a2
+    ret"
+);
+
 fn handle_breakpoint(sepc: &mut usize) {
     debug!("Exception(Breakpoint) @ {:#x} ", sepc);
     *sepc += 2
 }
 
-fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
+fn handle_page_fault(tf: &mut TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
     if is_user {
         access_flags |= MappingFlags::USER;
     }
     let vaddr = va!(stval::read());
     if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+        #[cfg(feature = "uspace")]
+        if let Some(fixup) = crate::trap::user_fault_fixup(tf.sepc) {
+            tf.sepc = fixup;
+            return;
+        }
         panic!(
             "Unhandled {} Page Fault @ {:#x}, fault_vaddr={:#x} ({:?}):\n{:#x?}",
             if is_user { "User" } else { "Supervisor" },
@@ -53,6 +82,20 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
                 handle_page_fault(tf, MappingFlags::EXECUTE, from_user)
             }
             Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
+            #[cfg(feature = "uspace")]
+            Trap::Exception(E::IllegalInstruction) if from_user => {
+                crate::trap::handle_user_exception(tf, UserException::IllegalInstruction, tf.sepc)
+            }
+            #[cfg(feature = "uspace")]
+            Trap::Exception(
+                E::InstructionMisaligned | E::LoadMisaligned | E::StoreMisaligned,
+            ) if from_user => {
+                crate::trap::handle_user_exception(tf, UserException::Misaligned, stval::read())
+            }
+            #[cfg(feature = "uspace")]
+            Trap::Exception(E::InstructionFault | E::LoadFault | E::StoreFault) if from_user => {
+                crate::trap::handle_user_exception(tf, UserException::AccessFault, stval::read())
+            }
             Trap::Interrupt(_) => {
                 handle_trap!(IRQ, scause.bits());
             }
@@ -68,5 +111,8 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             tf
         );
     }
-    // crate::trap::dealwith_signal();
+    #[cfg(feature = "uspace")]
+    if from_user {
+        crate::trap::dealwith_signal(tf);
+    }
 }
@@ -68,6 +68,41 @@ impl TrapFrame {
     pub const fn is_user(&self) -> bool {
         self.cs & 0b11 == 3
     }
+
+    /// Gets the instruction pointer.
+    pub const fn get_ip(&self) -> usize {
+        self.rip as _
+    }
+
+    /// Sets the instruction pointer.
+    pub const fn set_ip(&mut self, pc: usize) {
+        self.rip = pc as _;
+    }
+
+    /// Gets the stack pointer.
+    pub const fn get_sp(&self) -> usize {
+        self.rsp as _
+    }
+
+    /// Sets the stack pointer.
+    pub const fn set_sp(&mut self, sp: usize) {
+        self.rsp = sp as _;
+    }
+
+    /// Sets the 0th function argument.
+    pub const fn set_arg0(&mut self, arg: usize) {
+        self.rdi = arg as _;
+    }
+
+    /// Sets the 1st function argument.
+    pub const fn set_arg1(&mut self, arg: usize) {
+        self.rsi = arg as _;
+    }
+
+    /// Sets the 2nd function argument.
+    pub const fn set_arg2(&mut self, arg: usize) {
+        self.rdx = arg as _;
+    }
 }
 
 /// Context to enter user space.
@@ -194,7 +229,7 @@ struct ContextSwitchFrame {
 /// See <https://www.felixcloutier.com/x86/fxsave> for more details.
 #[allow(missing_docs)]
 #[repr(C, align(16))]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct FxsaveArea {
     pub fcw: u16,
     pub fsw: u16,
@@ -211,6 +246,37 @@ pub struct FxsaveArea {
 
 static_assertions::const_assert_eq!(core::mem::size_of::<FxsaveArea>(), 512);
 
+impl Default for FxsaveArea {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FxsaveArea {
+    /// Creates the initial state, as after `FNINIT` with the default `MXCSR`.
+    pub const fn new() -> Self {
+        let mut area: FxsaveArea = unsafe { core::mem::MaybeUninit::zeroed().assume_init() };
+        area.fcw = 0x37f;
+        area.ftw = 0xffff;
+        area.mxcsr = 0x1f80;
+        area
+    }
+
+    /// Saves the FP/SIMD registers of the current CPU.
+    #[inline]
+    pub fn save(&mut self) {
+        unsafe { core::arch::x86_64::_fxsave64(self as *mut _ as *mut u8) }
+    }
+
+    /// Loads the FP/SIMD registers of the current CPU.
+    ///
+    /// The reserved bits of `MXCSR` must be clear, or it raises #GP.
+    #[inline]
+    pub fn restore(&self) {
+        unsafe { core::arch::x86_64::_fxrstor64(self as *const _ as *const u8) }
+    }
+}
+
 /// Extended state of a task, such as FP/SIMD states.
 pub struct ExtendedState {
     /// Memory region for the FXSAVE/FXRSTOR instruction.
@@ -221,20 +287,18 @@ pub struct ExtendedState {
 impl ExtendedState {
     #[inline]
     fn save(&mut self) {
-        unsafe { core::arch::x86_64::_fxsave64(&mut self.fxsave_area as *mut _ as *mut u8) }
+        self.fxsave_area.save()
     }
 
     #[inline]
     fn restore(&self) {
-        unsafe { core::arch::x86_64::_fxrstor64(&self.fxsave_area as *const _ as *const u8) }
+        self.fxsave_area.restore()
     }
 
     const fn default() -> Self {
-        let mut area: FxsaveArea = unsafe { core::mem::MaybeUninit::zeroed().assume_init() };
-        area.fcw = 0x37f;
-        area.ftw = 0xffff;
-        area.mxcsr = 0x1f80;
-        Self { fxsave_area: area }
+        Self {
+            fxsave_area: FxsaveArea::new(),
+        }
     }
 }
 
@@ -14,9 +14,22 @@ core::arch::global_asm!(
     tss_rsp0_offset = const core::mem::offset_of!(TaskStateSegment, privilege_stack_table),
 );
 
+/// Handles a syscall, returning whether `sysretq` can return to user space.
+///
+/// `sysretq` loads `rcx` and `r11` with `rip` and `rflags`, so it is exact
+/// only if the trap frame holds the same values in them, as after a syscall.
+/// Otherwise, e.g. after `rt_sigreturn` restores a context interrupted at any
+/// instruction, or a signal handler is set up, returns through `iretq`.
 #[unsafe(no_mangle)]
-pub(super) fn x86_syscall_handler(tf: &mut TrapFrame) {
+pub(super) fn x86_syscall_handler(tf: &mut TrapFrame) -> bool {
     tf.rax = crate::trap::handle_syscall(tf, tf.rax as usize) as u64;
+    crate::trap::dealwith_signal(tf);
+    if tf.rcx == tf.rip && tf.r11 == tf.rflags {
+        return true;
+    }
+    tf.cs = GdtStruct::UCODE64_SELECTOR.0 as _;
+    tf.ss = GdtStruct::UDATA_SELECTOR.0 as _;
+    false
 }
 
 /// Initializes syscall support and setups the syscall handler.
@@ -3,20 +3,43 @@ use x86::{controlregs::cr2, irq::*};
 use x86_64::structures::idt::PageFaultErrorCode;
 
 use super::context::TrapFrame;
+#[cfg(feature = "uspace")]
+use crate::trap::UserException;
 
 core::arch::global_asm!(include_str!("trap.S"));
 
+// `__copy_user(dst, src, len)` returns the number of bytes not copied. A fault
+// resumes at `__copy_user_fixup`, as `rep movsb` leaves the count in `rcx`.
+#[cfg(feature = "uspace")]
+core::arch::global_asm!(
+    "
+    .section .text
+    .global __copy_user
+    .global __copy_user_fixup
+__copy_user:
+    mov     rcx, rdx
+    rep movsb
+__copy_user_fixup:
+    mov     rax, rcx
+    ret"
+);
+
 #[cfg(feature = "uspace")]
 const LEGACY_SYSCALL_VECTOR: u8 = 0x80;
 
 const IRQ_VECTOR_START: u8 = 0x20;
 const IRQ_VECTOR_END: u8 = 0xff;
 
-fn handle_page_fault(tf: &TrapFrame) {
+fn handle_page_fault(tf: &mut TrapFrame) {
     let access_flags = err_code_to_flags(tf.error_code)
         .unwrap_or_else(|e| panic!("Invalid #PF error code: {:#x}", e));
     let vaddr = va!(unsafe { cr2() });
     if !handle_trap!(PAGE_FAULT, vaddr, access_flags, tf.is_user()) {
+        #[cfg(feature = "uspace")]
+        if let Some(fixup) = crate::trap::user_fault_fixup(tf.rip as _) {
+            tf.rip = fixup as _;
+            return;
+        }
         panic!(
             "Unhandled {} #PF @ {:#x}, fault_vaddr={:#x}, error_code={:#x} ({:?}):\n{:#x?}",
             if tf.is_user() { "user" } else { "kernel" },
@@ -34,6 +57,27 @@ fn x86_trap_handler(tf: &mut TrapFrame) {
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => handle_page_fault(tf),
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
+        #[cfg(feature = "uspace")]
+        DIVIDE_ERROR_VECTOR if tf.is_user() => {
+            crate::trap::handle_user_exception(tf, UserException::DivideByZero, tf.rip as _)
+        }
+        #[cfg(feature = "uspace")]
+        INVALID_OPCODE_VECTOR if tf.is_user() => {
+            crate::trap::handle_user_exception(tf, UserException::IllegalInstruction, tf.rip as _)
+        }
+        #[cfg(feature = "uspace")]
+        X87_FPU_VECTOR | SIMD_FLOATING_POINT_VECTOR if tf.is_user() => {
+            crate::trap::handle_user_exception(tf, UserException::FloatingPoint, tf.rip as _)
+        }
+        #[cfg(feature = "uspace")]
+        ALIGNMENT_CHECK_VECTOR if tf.is_user() => {
+            crate::trap::handle_user_exception(tf, UserException::Misaligned, tf.rip as _)
+        }
+        #[cfg(feature = "uspace")]
+        GENERAL_PROTECTION_FAULT_VECTOR if tf.is_user() => {
+            // The faulting address is unknown, as on Linux.
+            crate::trap::handle_user_exception(tf, UserException::AccessFault, 0)
+        }
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
                 "#GP @ {:#x}, error_code={:#x}:\n{:#x?}",
@@ -41,7 +85,9 @@ fn x86_trap_handler(tf: &mut TrapFrame) {
             );
         }
         #[cfg(feature = "uspace")]
-        LEGACY_SYSCALL_VECTOR => super::syscall::x86_syscall_handler(tf),
+        LEGACY_SYSCALL_VECTOR => {
+            super::syscall::x86_syscall_handler(tf);
+        }
         IRQ_VECTOR_START..=IRQ_VECTOR_END => {
             handle_trap!(IRQ, tf.vector as _);
         }
@@ -56,7 +102,11 @@ fn x86_trap_handler(tf: &mut TrapFrame) {
             );
         }
     }
-    // crate::trap::dealwith_signal();
+    // The syscall handler has already handled the pending signals.
+    #[cfg(feature = "uspace")]
+    if tf.is_user() && tf.vector as u8 != LEGACY_SYSCALL_VECTOR {
+        crate::trap::dealwith_signal(tf);
+    }
 }
 
 fn vec_to_str(vec: u64) -> &'static str {
@@ -31,6 +31,17 @@ impl From<MemRegionFlags> for MappingFlags {
     }
 }
 
+/// Flushes the TLB entry of `vaddr`, or the entire TLB if it is `None`, on
+/// all the CPUs, and waits until they are all flushed.
+///
+/// It is needed when a mapping loses permissions while other CPUs may be
+/// running in the same address space.
+pub fn flush_tlb_all_cpus(vaddr: Option<VirtAddr>) {
+    crate::arch::flush_tlb(vaddr);
+    #[cfg(feature = "smp")]
+    crate::platform::mp::flush_tlb_others(vaddr);
+}
+
 /// Implementation of [`PagingHandler`], to provide physical memory manipulation to
 /// the [page_table_multiarch] crate.
 pub struct PagingHandlerImpl;
@@ -1,3 +1,5 @@
+pub use crate::platform::aarch64_common::flush_tlb_others;
+
 use crate::mem::{PhysAddr, virt_to_phys};
 
 /// Hart number of bsta1000b board
@@ -9,3 +9,15 @@ pub mod gic;
 
 #[cfg(not(platform_family = "aarch64-bsta1000b"))]
 pub mod pl011;
+
+/// Flushes the TLB entry of `vaddr`, or the entire TLB if it is `None`, on the
+/// other CPUs, and waits until they are flushed.
+///
+/// [`crate::arch::flush_tlb`] already broadcasts the flush of an entry to the
+/// inner shareable domain, but only flushes the entire TLB of the current CPU.
+#[cfg(feature = "smp")]
+pub fn flush_tlb_others(vaddr: Option<crate::mem::VirtAddr>) {
+    if vaddr.is_none() {
+        unsafe { core::arch::asm!("tlbi vmalle1is; dsb sy; isb") };
+    }
+}
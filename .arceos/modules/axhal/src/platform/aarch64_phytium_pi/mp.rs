@@ -1,3 +1,5 @@
+pub use crate::platform::aarch64_common::flush_tlb_others;
+
 use axconfig::devices::CPU_ID_LIST;
 
 use crate::mem::{PhysAddr, virt_to_phys};
@@ -1,3 +1,5 @@
+pub use crate::platform::aarch64_common::flush_tlb_others;
+
 use crate::mem::{PhysAddr, phys_to_virt, virt_to_phys};
 
 static mut SECONDARY_STACK_TOP: usize = 0;
@@ -25,6 +25,9 @@ pub mod misc {
 pub mod mp {
     /// Starts the given secondary CPU with its boot stack.
     pub fn start_secondary_cpu(cpu_id: usize, stack_top: crate::mem::PhysAddr) {}
+
+    /// Flushes the TLBs of the other CPUs.
+    pub fn flush_tlb_others(vaddr: Option<crate::mem::VirtAddr>) {}
 }
 
 pub mod mem {
@@ -1,8 +1,25 @@
 use loongArch64::ipi::{csr_mail_send, send_ipi_single};
 
-use crate::mem::phys_to_virt;
+use crate::mem::{VirtAddr, phys_to_virt};
 
 const ACTION_BOOT_CPU: u32 = 1;
+const ACTION_FLUSH_TLB: u32 = 2;
+
+const IOCSR_IPI_STATUS: usize = 0x1000;
+const IOCSR_IPI_EN: usize = 0x1004;
+const IOCSR_IPI_CLEAR: usize = 0x100c;
+const IOCSR_IPI_SEND: usize = 0x1040;
+const IOCSR_IPI_SEND_BLOCKING: u32 = 1 << 31;
+
+fn iocsr_read(reg: usize) -> u32 {
+    let value: u32;
+    unsafe { core::arch::asm!("iocsrrd.w {}, {}", out(reg) value, in(reg) reg) };
+    value
+}
+
+fn iocsr_write(reg: usize, value: u32) {
+    unsafe { core::arch::asm!("iocsrwr.w {}, {}", in(reg) value, in(reg) reg) };
+}
 
 pub static mut SMP_BOOT_STACK_TOP: usize = 0;
 
@@ -18,3 +35,38 @@ pub fn start_secondary_cpu(cpu_id: usize, stack_top: crate::mem::PhysAddr) {
     csr_mail_send(_start_secondary as usize as _, cpu_id, 0);
     send_ipi_single(cpu_id, ACTION_BOOT_CPU);
 }
+
+#[cfg(feature = "irq")]
+fn handle_ipi() {
+    let status = iocsr_read(IOCSR_IPI_STATUS);
+    iocsr_write(IOCSR_IPI_CLEAR, status);
+    if status & (1 << ACTION_FLUSH_TLB) != 0 {
+        crate::platform::tlb_shootdown::handle_ipi();
+    }
+}
+
+/// Enables the IPIs of the TLB shootdowns on the current CPU.
+#[cfg(feature = "irq")]
+pub(super) fn init_ipi(primary: bool) {
+    use super::irq::{IPI_IRQ_NUM, register_handler, set_enable};
+    if primary {
+        register_handler(IPI_IRQ_NUM, handle_ipi);
+    } else {
+        set_enable(IPI_IRQ_NUM, true);
+    }
+    let enabled = iocsr_read(IOCSR_IPI_EN);
+    iocsr_write(IOCSR_IPI_EN, enabled | 1 << ACTION_FLUSH_TLB);
+    crate::platform::tlb_shootdown::cpu_online();
+}
+
+/// Flushes the TLBs of the other CPUs by IPIs, and waits until they are
+/// flushed. They flush their entire TLB, whatever `vaddr` is.
+pub fn flush_tlb_others(_vaddr: Option<VirtAddr>) {
+    #[cfg(feature = "irq")]
+    crate::platform::tlb_shootdown::shootdown(|cpu| {
+        iocsr_write(
+            IOCSR_IPI_SEND,
+            IOCSR_IPI_SEND_BLOCKING | (cpu as u32) << 16 | ACTION_FLUSH_TLB,
+        );
+    });
+}
@@ -6,6 +6,16 @@ cfg_if::cfg_if! {
     }
 }
 
+#[cfg(all(
+    feature = "smp",
+    feature = "irq",
+    any(
+        all(target_arch = "x86_64", platform_family = "x86-pc"),
+        all(target_arch = "loongarch64", platform_family = "loongarch64-qemu-virt")
+    )
+))]
+mod tlb_shootdown;
+
 cfg_if::cfg_if! {
     if #[cfg(all(target_arch = "x86_64", platform_family = "x86-pc"))] {
         mod x86_pc;
@@ -1,4 +1,4 @@
-use crate::mem::{PhysAddr, virt_to_phys};
+use crate::mem::{PAGE_SIZE_4K, PhysAddr, VirtAddr, virt_to_phys};
 
 /// Starts the given secondary CPU with its boot stack.
 pub fn start_secondary_cpu(hartid: usize, stack_top: PhysAddr) {
@@ -12,3 +12,14 @@ pub fn start_secondary_cpu(hartid: usize, stack_top: PhysAddr) {
     let entry = virt_to_phys(va!(_start_secondary as usize));
     sbi_rt::hart_start(hartid, entry.as_usize(), stack_top.as_usize());
 }
+
+/// Flushes the TLB entry of `vaddr`, or the entire TLB if it is `None`, on the
+/// other harts, and waits until they are flushed.
+pub fn flush_tlb_others(vaddr: Option<VirtAddr>) {
+    let (start, size) = match vaddr {
+        Some(vaddr) => (vaddr.as_usize(), PAGE_SIZE_4K),
+        None => (0, usize::MAX),
+    };
+    // The SBI implementation waits for the remote fences to complete.
+    let _ = sbi_rt::remote_sfence_vma(sbi_rt::HartMask::from_mask_base(0, usize::MAX), start, size);
+}
@@ -0,0 +1,44 @@
+//! TLB shootdowns by IPIs, for the platforms whose TLB maintenance
+//! instructions only act on the current CPU.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kspin::SpinNoPreempt;
+
+/// The CPUs handling the shootdown IPIs, as a bit mask.
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// The CPUs that have not flushed their TLB yet, as a bit mask.
+static PENDING_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// Serializes the shootdowns. IRQs stay enabled while waiting, so that a CPU
+/// waiting for the lock still handles the shootdown in progress.
+static SHOOTDOWN_LOCK: SpinNoPreempt<()> = SpinNoPreempt::new(());
+
+/// Marks the current CPU as handling the shootdown IPIs.
+pub(crate) fn cpu_online() {
+    ONLINE_CPUS.fetch_or(1 << crate::cpu::this_cpu_id(), Ordering::AcqRel);
+}
+
+/// Handles a shootdown IPI by flushing the entire TLB of the current CPU.
+pub(crate) fn handle_ipi() {
+    crate::arch::flush_tlb(None);
+    PENDING_CPUS.fetch_and(!(1 << crate::cpu::this_cpu_id()), Ordering::AcqRel);
+}
+
+/// Flushes the TLBs of the other CPUs, sending the IPI to each of them with
+/// `send_ipi`, and waits until they are all flushed.
+pub(crate) fn shootdown(send_ipi: impl Fn(usize)) {
+    let _guard = SHOOTDOWN_LOCK.lock();
+    let others = ONLINE_CPUS.load(Ordering::Acquire) & !(1 << crate::cpu::this_cpu_id());
+    if others == 0 {
+        return;
+    }
+    PENDING_CPUS.store(others, Ordering::Release);
+    for cpu in (0..usize::BITS as usize).filter(|cpu| others & (1 << cpu) != 0) {
+        send_ipi(cpu);
+    }
+    while PENDING_CPUS.load(Ordering::Acquire) != 0 {
+        core::hint::spin_loop();
+    }
+}
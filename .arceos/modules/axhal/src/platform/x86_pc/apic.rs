@@ -16,6 +16,7 @@ pub(super) mod vectors {
     pub const APIC_TIMER_VECTOR: u8 = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: u8 = 0xf1;
     pub const APIC_ERROR_VECTOR: u8 = 0xf2;
+    pub const TLB_SHOOTDOWN_VECTOR: u8 = 0xf3;
 }
 
 /// The maximum number of IRQs.
@@ -119,9 +120,18 @@ pub(super) fn init_primary() {
     info!("Initialize IO APIC...");
     let io_apic = unsafe { IoApic::new(phys_to_virt(IO_APIC_BASE).as_usize() as u64) };
     IO_APIC.init_once(SpinNoIrq::new(io_apic));
+
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    {
+        use crate::platform::tlb_shootdown;
+        register_handler(TLB_SHOOTDOWN_VECTOR as usize, tlb_shootdown::handle_ipi);
+        tlb_shootdown::cpu_online();
+    }
 }
 
 #[cfg(feature = "smp")]
 pub(super) fn init_secondary() {
     unsafe { local_apic().enable() };
+    #[cfg(feature = "irq")]
+    crate::platform::tlb_shootdown::cpu_online();
 }
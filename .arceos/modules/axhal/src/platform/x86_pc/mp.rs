@@ -1,4 +1,4 @@
-use crate::mem::{PAGE_SIZE_4K, PhysAddr, phys_to_virt};
+use crate::mem::{PAGE_SIZE_4K, PhysAddr, VirtAddr, phys_to_virt};
 use crate::time::{Duration, busy_wait};
 
 const START_PAGE_IDX: u8 = 6;
@@ -43,3 +43,14 @@ pub fn start_secondary_cpu(apic_id: usize, stack_top: PhysAddr) {
     busy_wait(Duration::from_micros(200)); // 200us
     unsafe { lapic.send_sipi(START_PAGE_IDX, apic_id) };
 }
+
+/// Flushes the TLBs of the other CPUs by IPIs, and waits until they are
+/// flushed. They flush their entire TLB, whatever `vaddr` is.
+pub fn flush_tlb_others(_vaddr: Option<VirtAddr>) {
+    #[cfg(feature = "irq")]
+    crate::platform::tlb_shootdown::shootdown(|cpu| {
+        let apic_id = super::apic::raw_apic_id(cpu as u8);
+        let vector = super::apic::vectors::TLB_SHOOTDOWN_VECTOR;
+        unsafe { super::apic::local_apic().send_ipi(vector, apic_id) };
+    });
+}
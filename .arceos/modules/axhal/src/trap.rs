@@ -17,8 +17,36 @@ pub static IRQ: [fn(usize) -> bool];
 #[def_trap_handler]
 pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 
+/// A user exception that is not a page fault, reported to the kernel so that it
+/// can raise the corresponding signal.
+#[cfg(feature = "uspace")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserException {
+    /// An illegal or undefined instruction.
+    IllegalInstruction,
+    /// A privileged instruction executed in user mode.
+    PrivilegedInstruction,
+    /// A misaligned instruction fetch or memory access.
+    Misaligned,
+    /// A memory access fault that is not a page fault.
+    AccessFault,
+    /// An integer division by zero.
+    DivideByZero,
+    /// A floating-point exception.
+    FloatingPoint,
+}
+
+/// A slice of user exception handler functions, called with the kind of the
+/// exception and the faulting address.
+#[cfg(feature = "uspace")]
 #[def_trap_handler]
-pub static DEAL_SIGNAL: [fn()];
+pub static USER_EXCEPTION: [fn(UserException, usize) -> bool];
+
+/// A slice of signal handler functions, called before returning to user space.
+#[cfg(feature = "uspace")]
+#[def_trap_handler]
+pub static DEAL_SIGNAL: [fn(&mut TrapFrame)];
+
 /// A slice of syscall handler functions.
 #[cfg(feature = "uspace")]
 #[def_trap_handler]
@@ -46,6 +74,57 @@ pub(crate) fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     SYSCALL[0](tf, syscall_num)
 }
 
-pub fn dealwith_signal(){
-    DEAL_SIGNAL[0]();
+/// Call the external user exception handler.
+#[cfg(feature = "uspace")]
+pub(crate) fn handle_user_exception(tf: &TrapFrame, kind: UserException, addr: usize) {
+    if !handle_trap!(USER_EXCEPTION, kind, addr) {
+        panic!(
+            "Unhandled user exception {:?} @ {:#x}, addr={:#x}:\n{:#x?}",
+            kind,
+            tf.get_ip(),
+            addr,
+            tf
+        );
+    }
+}
+
+/// Copies `len` bytes from `src` to `dst`, either of which may be in user
+/// memory.
+///
+/// A page fault that the [`PAGE_FAULT`] handlers cannot resolve stops the copy
+/// instead of panicking, and the number of bytes not copied is returned as the
+/// error.
+///
+/// # Safety
+///
+/// The kernel memory in the ranges must be valid.
+#[cfg(feature = "uspace")]
+pub unsafe fn copy_user(dst: *mut u8, src: *const u8, len: usize) -> Result<(), usize> {
+    unsafe extern "C" {
+        fn __copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    }
+    match unsafe { __copy_user(dst, src, len) } {
+        0 => Ok(()),
+        left => Err(left),
+    }
+}
+
+/// Returns where to resume after a page fault at `pc` in kernel mode that
+/// could not be resolved, if it faulted inside [`copy_user`].
+#[cfg(feature = "uspace")]
+pub(crate) fn user_fault_fixup(pc: usize) -> Option<usize> {
+    unsafe extern "C" {
+        fn __copy_user();
+        fn __copy_user_fixup();
+    }
+    let fixup = __copy_user_fixup as usize;
+    (__copy_user as usize..fixup).contains(&pc).then_some(fixup)
+}
+
+/// Call the external signal handler before returning to user space.
+#[cfg(feature = "uspace")]
+pub(crate) fn dealwith_signal(tf: &mut TrapFrame) {
+    if let Some(func) = DEAL_SIGNAL.iter().next() {
+        func(tf);
+    }
 }
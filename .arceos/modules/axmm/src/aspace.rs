@@ -1,15 +1,18 @@
+use alloc::sync::Arc;
 use core::fmt;
 
 use axerrno::{AxError, AxResult, ax_err};
 use axhal::mem::phys_to_virt;
-use axhal::paging::{MappingFlags, PageTable, PagingError};
+use axhal::paging::{MappingFlags, PageSize, PageTable, PagingError};
 use memory_addr::{
     MemoryAddr, PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, is_aligned_4k,
 };
 use memory_set::{MemoryArea, MemorySet};
 
-use crate::backend::Backend;
+use crate::backend::{Backend, PageCache, SharedMemory, write_protect_page};
+use crate::frame::share_frame;
 use crate::mapping_err_to_ax_err;
+use crate::stats::{self, FORK_SHARES};
 
 /// The virtual memory address space.
 pub struct AddrSpace {
@@ -161,28 +164,166 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Add a new file mapping, which maps `start` to `offset` in the file
+    /// cached by `cache`.
+    ///
+    /// See [`Backend`] for more details about the mapping backends.
+    ///
+    /// A shared mapping can only be writable, now or by [`AddrSpace::protect`]
+    /// later, if `may_write` is `true`, i.e. the file is open for writing.
+    ///
+    /// Returns an error if the address range is out of the address space or
+    /// not aligned, if `offset` is not aligned, or if `flags` of a shared
+    /// mapping contain [`MappingFlags::WRITE`] but `may_write` is `false`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn map_file(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        cache: Arc<PageCache>,
+        offset: u64,
+        shared: bool,
+        may_write: bool,
+    ) -> AxResult {
+        self.validate_region(start, size)?;
+        if !is_aligned_4k(offset as usize) {
+            return ax_err!(InvalidInput, "offset not aligned");
+        }
+        if shared && !may_write && flags.contains(MappingFlags::WRITE) {
+            return ax_err!(PermissionDenied, "file not open for writing");
+        }
+
+        let area = MemoryArea::new(
+            start,
+            size,
+            flags,
+            Backend::new_file(cache, start, offset, shared, may_write),
+        );
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Add a new shared memory mapping of `memory`, starting at its beginning.
+    ///
+    /// See [`Backend`] for more details about the mapping backends.
+    ///
+    /// Returns an error if the address range is out of the address space or
+    /// not aligned, or if it is larger than the region.
+    pub fn map_shared(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        memory: Arc<SharedMemory>,
+    ) -> AxResult {
+        self.validate_region(start, size)?;
+        if size > memory.size() {
+            return ax_err!(InvalidInput, "mapping larger than the shared memory");
+        }
+
+        let area = MemoryArea::new(start, size, flags, Backend::new_shared(memory, start));
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Writes back the dirty pages of the shared file mappings within the
+    /// specified virtual address range.
+    ///
+    /// Unmapped holes in the range are skipped.
+    pub fn sync(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        let end = start + size;
+        for area in self.areas.iter() {
+            if area.end() <= start || area.start() >= end {
+                continue;
+            }
+            if let Backend::File {
+                cache,
+                start: file_start,
+                offset,
+                shared: true,
+                ..
+            } = area.backend()
+            {
+                let first = Backend::file_page_index(area.start().max(start), *file_start, *offset);
+                let last = Backend::file_page_index(
+                    (area.end().min(end) - 1).align_down_4k(),
+                    *file_start,
+                    *offset,
+                );
+                cache.sync_locked(first..last + 1, &mut self.pt)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write-protects `frame` mapped at `vaddr`, after the page cache holding
+    /// it has written it back, without flushing the TLB.
+    ///
+    /// Returns `false` if it is mapped there but cannot be write-protected.
+    pub fn write_protect(&mut self, vaddr: VirtAddr, frame: PhysAddr) -> bool {
+        write_protect_page(&mut self.pt, vaddr, frame)
+    }
+
+    /// Returns whether `vaddr` lies in a file mapping, but past the end of the
+    /// file, where accesses should be reported as bus errors.
+    pub fn is_past_file_end(&self, vaddr: VirtAddr) -> bool {
+        match self.areas.find(vaddr).map(|area| area.backend()) {
+            Some(Backend::File {
+                cache,
+                start,
+                offset,
+                ..
+            }) => cache.is_past_eof(Backend::file_page_index(vaddr, *start, *offset)),
+            _ => false,
+        }
+    }
+
     /// Populates the area with physical frames, returning false if the area
     /// contains unmapped area.
-    pub fn populate_area(&mut self, mut start: VirtAddr, size: usize) -> AxResult {
+    ///
+    /// If `access_flags` contains [`MappingFlags::WRITE`], pages shared by
+    /// copy-on-write in writable areas are also copied, so that the kernel can
+    /// write to them directly.
+    pub fn populate_area(
+        &mut self,
+        mut start: VirtAddr,
+        size: usize,
+        access_flags: MappingFlags,
+    ) -> AxResult {
         self.validate_region(start, size)?;
         let end = start + size;
 
         while let Some(area) = self.areas.find(start) {
             let backend = area.backend();
-            if let Backend::Alloc { populate } = backend {
-                if !*populate {
-                    for addr in PageIter4K::new(start, area.end().min(end)).unwrap() {
-                        match self.pt.query(addr) {
-                            Ok(_) => {}
-                            // If the page is not mapped, try map it.
-                            Err(PagingError::NotMapped) => {
-                                if !backend.handle_page_fault(addr, area.flags(), &mut self.pt) {
-                                    return Err(AxError::NoMemory);
-                                }
+            let write = access_flags.contains(MappingFlags::WRITE)
+                && area.flags().contains(MappingFlags::WRITE);
+            let fault_in = match backend {
+                Backend::Linear { .. } => false,
+                Backend::Alloc { populate } => !*populate || write,
+                Backend::File { .. } | Backend::Shared { .. } => true,
+            };
+            if fault_in {
+                for addr in PageIter4K::new(start, area.end().min(end)).unwrap() {
+                    match self.pt.query(addr) {
+                        Ok((_, flags, _)) if write && !flags.contains(MappingFlags::WRITE) => {
+                            if !backend.handle_page_fault(addr, area.flags(), &mut self.pt) {
+                                return Err(AxError::NoMemory);
+                            }
+                        }
+                        Ok(_) => {}
+                        // If the page is not mapped, try map it.
+                        Err(PagingError::NotMapped) => {
+                            if !backend.handle_page_fault(addr, area.flags(), &mut self.pt) {
+                                return Err(AxError::NoMemory);
                             }
-                            Err(_) => return Err(AxError::BadAddress),
-                        };
-                    }
+                        }
+                        Err(_) => return Err(AxError::BadAddress),
+                    };
                 }
             }
             start = area.end();
@@ -303,6 +444,10 @@ impl AddrSpace {
     ///
     /// * `start_vaddr` - The start virtual address to write.
     /// * `buf` - The buffer to write to the address space.
+    ///
+    /// The data is written through the physical frames, so pages shared by
+    /// copy-on-write must be populated for writing first (see
+    /// [`AddrSpace::populate_area`]).
     pub fn write(&self, start: VirtAddr, buf: &[u8]) -> AxResult {
         self.process_area_data(start, buf.len(), |dst, offset, write_size| unsafe {
             core::ptr::copy_nonoverlapping(buf.as_ptr().add(offset), dst.as_mut_ptr(), write_size);
@@ -312,10 +457,30 @@ impl AddrSpace {
     /// Updates mapping within the specified virtual address range.
     ///
     /// Returns an error if the address range is out of the address space or not
-    /// aligned.
+    /// aligned, or if it makes a shared mapping of a file that is not open for
+    /// writing writable.
     pub fn protect(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
         // Populate the area first, which also checks the address range for us.
-        self.populate_area(start, size)?;
+        self.populate_area(start, size, MappingFlags::empty())?;
+
+        if flags.contains(MappingFlags::WRITE) {
+            let end = start + size;
+            let denied = self.areas.iter().any(|area| {
+                area.start() < end
+                    && area.end() > start
+                    && matches!(
+                        area.backend(),
+                        Backend::File {
+                            shared: true,
+                            may_write: false,
+                            ..
+                        }
+                    )
+            });
+            if denied {
+                return ax_err!(PermissionDenied, "file not open for writing");
+            }
+        }
 
         self.areas
             .protect(start, size, |_| Some(flags), &mut self.pt)
@@ -381,52 +546,75 @@ impl AddrSpace {
         false
     }
 
-    /// Clone a [`AddrSpace`] by re-mapping all [`MemoryArea`]s in a new page table and copying data in user space.
+    /// Clone a [`AddrSpace`] by re-mapping all [`MemoryArea`]s in a new page table.
+    ///
+    /// The frames of allocation mappings, and the private copies of file
+    /// mappings, are shared rather than copied. They are mapped read-only in
+    /// both address spaces, and copied on the first write fault
+    /// (copy-on-write). Shared mappings keep mapping the same frames.
     pub fn clone_or_err(&mut self) -> AxResult<Self> {
         let mut new_aspace = Self::new_empty(self.base(), self.size())?;
+        let mut write_protected = false;
 
         for area in self.areas.iter() {
-            let backend = area.backend();
+            let backend = match area.backend() {
+                // Populated areas are not populated again, as all their
+                // frames are shared below.
+                Backend::Alloc { .. } => Backend::new_alloc(false),
+                backend => backend.clone(),
+            };
             // Remap the memory area in the new address space.
-            let new_area =
-                MemoryArea::new(area.start(), area.size(), area.flags(), backend.clone());
+            let new_area = MemoryArea::new(area.start(), area.size(), area.flags(), backend);
             new_aspace
                 .areas
                 .map(new_area, &mut new_aspace.pt, false)
                 .map_err(mapping_err_to_ax_err)?;
-            // Copy data from old memory area to new memory area.
+            match area.backend() {
+                // Linear mappings share the same physical frames.
+                Backend::Linear { .. } => continue,
+                // Shared file pages are faulted in again from the page cache.
+                Backend::File { shared: true, .. } => continue,
+                // Shared memory is faulted in again from the same region.
+                Backend::Shared { .. } => continue,
+                _ => {}
+            }
+            // Share the mapped frames between the two address spaces.
             for vaddr in
                 PageIter4K::new(area.start(), area.end()).expect("Failed to create page iterator")
             {
-                let addr = match self.pt.query(vaddr) {
-                    Ok((paddr, _, _)) => paddr,
+                let (frame, flags) = match self.pt.query(vaddr) {
+                    Ok((paddr, flags, _)) => (paddr, flags),
                     // If the page is not mapped, skip it.
                     Err(PagingError::NotMapped) => continue,
                     Err(_) => return Err(AxError::BadAddress),
                 };
-                let new_addr = match new_aspace.pt.query(vaddr) {
-                    Ok((paddr, _, _)) => paddr,
-                    // If the page is not mapped, try map it.
-                    Err(PagingError::NotMapped) => {
-                        if !backend.handle_page_fault(vaddr, area.flags(), &mut new_aspace.pt) {
-                            return Err(AxError::NoMemory);
-                        }
-                        match new_aspace.pt.query(vaddr) {
-                            Ok((paddr, _, _)) => paddr,
-                            Err(_) => return Err(AxError::BadAddress),
-                        }
-                    }
-                    Err(_) => return Err(AxError::BadAddress),
-                };
-                unsafe {
-                    core::ptr::copy_nonoverlapping(
-                        phys_to_virt(addr).as_ptr(),
-                        phys_to_virt(new_addr).as_mut_ptr(),
-                        PAGE_SIZE_4K,
-                    )
-                };
+                // So are the pages of private file mappings not copied yet.
+                if area.backend().is_cached_frame(vaddr, frame) {
+                    continue;
+                }
+                let shared_flags = flags - MappingFlags::WRITE;
+                if flags.contains(MappingFlags::WRITE) {
+                    self.pt
+                        .protect(vaddr, shared_flags)
+                        .map(|(_, tlb)| tlb.ignore())
+                        .map_err(|_| AxError::BadState)?;
+                    write_protected = true;
+                }
+                new_aspace
+                    .pt
+                    .map(vaddr, frame, PageSize::Size4K, shared_flags)
+                    .map_err(|_| AxError::BadState)?
+                    .ignore();
+                share_frame(frame);
+                stats::record(&FORK_SHARES);
             }
         }
+        // Other CPUs running threads of this address space may still cache the
+        // writable mappings. On failure, the frames are unshared again when the
+        // new address space is dropped, so stale writable entries are harmless.
+        if write_protected {
+            axhal::paging::flush_tlb_all_cpus(None);
+        }
         Ok(new_aspace)
     }
 }
@@ -1,11 +1,13 @@
 use axalloc::global_allocator;
 use axhal::mem::{phys_to_virt, virt_to_phys};
 use axhal::paging::{MappingFlags, PageSize, PageTable};
-use memory_addr::{PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr};
 
 use super::Backend;
+use crate::frame::{is_shared, release_frame};
+use crate::stats::{self, COW_COPIES, COW_REUSES, LAZY_ALLOCS};
 
-fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
+pub(super) fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
     let vaddr = VirtAddr::from(global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()?);
     if zeroed {
         unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, PAGE_SIZE_4K) };
@@ -14,7 +16,7 @@ fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
     Some(paddr)
 }
 
-fn dealloc_frame(frame: PhysAddr) {
+pub(super) fn dealloc_frame(frame: PhysAddr) {
     let vaddr = phys_to_virt(frame);
     global_allocator().dealloc_pages(vaddr.as_usize(), 1);
 }
@@ -71,7 +73,11 @@ impl Backend {
                     return false;
                 }
                 tlb.flush();
-                dealloc_frame(frame);
+                // Frames still shared by copy-on-write are kept for the other
+                // owners.
+                if release_frame(frame) {
+                    dealloc_frame(frame);
+                }
             } else {
                 // Deallocation is needn't if the page is not mapped.
             }
@@ -79,23 +85,125 @@ impl Backend {
         true
     }
 
+    pub(crate) fn protect_alloc(
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        debug!(
+            "protect_alloc: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let Ok((frame, _, _)) = pt.query(addr) else {
+                continue; // Unmapped pages get the new flags on the first fault.
+            };
+            // Shared frames stay read-only, so that the first write still
+            // triggers a copy-on-write fault.
+            let flags = if is_shared(frame) {
+                new_flags - MappingFlags::WRITE
+            } else {
+                new_flags
+            };
+            match pt.protect(addr, flags) {
+                Ok((_, tlb)) => tlb.flush(),
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
     pub(crate) fn handle_page_fault_alloc(
         vaddr: VirtAddr,
         orig_flags: MappingFlags,
         pt: &mut PageTable,
         populate: bool,
     ) -> bool {
-        if populate {
-            false // Populated mappings should not trigger page faults.
-        } else if let Some(frame) = alloc_frame(true) {
-            // Allocate a physical frame lazily and map it to the fault address.
-            // `vaddr` does not need to be aligned. It will be automatically
-            // aligned during `pt.map` regardless of the page size.
-            pt.map(vaddr, frame, PageSize::Size4K, orig_flags)
-                .map(|tlb| tlb.flush())
-                .is_ok()
-        } else {
-            false
+        match pt.query(vaddr) {
+            // A write to a page shared by copy-on-write.
+            Ok((frame, flags, _))
+                if orig_flags.contains(MappingFlags::WRITE)
+                    && !flags.contains(MappingFlags::WRITE) =>
+            {
+                Self::handle_cow_fault(vaddr, frame, orig_flags, pt)
+            }
+            // The page is already mapped with the proper flags, so the fault
+            // came from a stale TLB entry.
+            Ok(_) => {
+                axhal::arch::flush_tlb(Some(vaddr.align_down_4k()));
+                true
+            }
+            Err(_) if populate => false, // Populated mappings should not trigger page faults.
+            Err(_) => {
+                let Some(frame) = alloc_frame(true) else {
+                    return false;
+                };
+                // Allocate a physical frame lazily and map it to the fault address.
+                // `vaddr` does not need to be aligned. It will be automatically
+                // aligned during `pt.map` regardless of the page size.
+                let mapped = pt
+                    .map(vaddr, frame, PageSize::Size4K, orig_flags)
+                    .map(|tlb| tlb.flush())
+                    .is_ok();
+                if mapped {
+                    stats::record(&LAZY_ALLOCS);
+                } else {
+                    dealloc_frame(frame);
+                }
+                mapped
+            }
+        }
+    }
+
+    /// Resolves a write fault on a page mapped read-only for copy-on-write.
+    ///
+    /// If other address spaces still map the frame, the data is copied to a
+    /// new frame owned by this mapping. Otherwise the frame is made writable
+    /// again in place.
+    pub(super) fn handle_cow_fault(
+        vaddr: VirtAddr,
+        frame: PhysAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let vaddr = vaddr.align_down_4k();
+        if !is_shared(frame) {
+            return match pt.protect(vaddr, orig_flags) {
+                Ok((_, tlb)) => {
+                    tlb.flush();
+                    stats::record(&COW_REUSES);
+                    true
+                }
+                Err(_) => false,
+            };
         }
+
+        let Some(new_frame) = alloc_frame(false) else {
+            return false;
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                phys_to_virt(frame).as_ptr(),
+                phys_to_virt(new_frame).as_mut_ptr(),
+                PAGE_SIZE_4K,
+            )
+        };
+        match pt.remap(vaddr, new_frame, orig_flags) {
+            Ok((_, tlb)) => tlb.flush(),
+            Err(_) => {
+                dealloc_frame(new_frame);
+                return false;
+            }
+        }
+        // Another owner may have copied the frame meanwhile, leaving this
+        // mapping as the last one.
+        if release_frame(frame) {
+            dealloc_frame(frame);
+        }
+        stats::record(&COW_COPIES);
+        true
     }
 }
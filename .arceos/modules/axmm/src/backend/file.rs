@@ -0,0 +1,494 @@
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{mem, ops::Range};
+
+use axerrno::AxResult;
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageSize, PageTable};
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr};
+
+use super::Backend;
+use super::alloc::{alloc_frame, dealloc_frame};
+use crate::frame::{is_shared, release_frame};
+use crate::stats::{self, COW_COPIES};
+
+/// The file operations a [`PageCache`] reads its pages from and writes them
+/// back with.
+pub trait FileIo: Send + Sync {
+    /// Reads data at the given offset, returning the number of bytes read.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize>;
+    /// Writes data at the given offset, returning the number of bytes written.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize>;
+    /// Returns the current size of the file.
+    fn size(&self) -> AxResult<u64>;
+}
+
+/// Write-protects the pages of the page caches in the address spaces mapping
+/// them, after they are written back.
+pub trait WriteProtect: Send + Sync {
+    /// Write-protects `frame` mapped at `vaddr` in the address space whose page
+    /// table is rooted at `root`, without flushing the TLB.
+    ///
+    /// Returns `false` if the address space cannot be accessed now. The page
+    /// being no longer mapped there counts as success.
+    fn write_protect(&self, root: PhysAddr, vaddr: VirtAddr, frame: PhysAddr) -> bool;
+}
+
+static WRITE_PROTECT: LazyInit<&'static dyn WriteProtect> = LazyInit::new();
+
+/// Registers how to write-protect the pages of the page caches in the address
+/// spaces other than the one being synced.
+///
+/// Until it is registered, the pages mapped writable elsewhere stay dirty.
+pub fn set_write_protect(write_protect: &'static dyn WriteProtect) {
+    WRITE_PROTECT.call_once(|| write_protect);
+}
+
+struct CachedPage {
+    frame: PhysAddr,
+    dirty: bool,
+    /// The page table roots and addresses of the writable mappings of the
+    /// page, which must be write-protected once it is written back.
+    writers: Vec<(PhysAddr, VirtAddr)>,
+}
+
+/// The cached pages of a file, shared by every mapping of it.
+///
+/// Pages are read from the file on first access. Pages written through shared
+/// mappings are marked dirty, and written back by [`PageCache::sync`] and when
+/// the cache is dropped. Writing a page back write-protects it again, so that
+/// the next write marks it dirty. Writes to the file made otherwise are copied
+/// into the cached pages by [`PageCache::write_through`] and
+/// [`PageCache::truncate`].
+pub struct PageCache {
+    file: Arc<dyn FileIo>,
+    pages: SpinNoIrq<BTreeMap<u64, CachedPage>>,
+}
+
+impl PageCache {
+    /// Creates an empty page cache of the given file.
+    pub fn new(file: Arc<dyn FileIo>) -> Self {
+        Self {
+            file,
+            pages: SpinNoIrq::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns whether the page at `index` lies entirely past the end of the
+    /// file.
+    pub fn is_past_eof(&self, index: u64) -> bool {
+        self.file
+            .size()
+            .is_ok_and(|size| index * PAGE_SIZE_4K as u64 >= size)
+    }
+
+    /// Returns the frame caching the page at `index`, if it has been read.
+    fn frame(&self, index: u64) -> Option<PhysAddr> {
+        self.pages.lock().get(&index).map(|page| page.frame)
+    }
+
+    /// Marks the page at `index` dirty, as it is mapped writable at `vaddr` in
+    /// the page table rooted at `root`.
+    fn mark_dirty(&self, index: u64, root: PhysAddr, vaddr: VirtAddr) {
+        if let Some(page) = self.pages.lock().get_mut(&index) {
+            page.dirty = true;
+            if !page.writers.contains(&(root, vaddr)) {
+                page.writers.push((root, vaddr));
+            }
+        }
+    }
+
+    /// Records that the page at `index` stays mapped writable at `vaddr` in the
+    /// page table rooted at `root`, if it is dirty.
+    ///
+    /// Returns `false` if it is clean, so it must be mapped read-only.
+    fn keep_writable(&self, index: u64, root: PhysAddr, vaddr: VirtAddr) -> bool {
+        match self.pages.lock().get_mut(&index) {
+            Some(page) if page.dirty => {
+                if !page.writers.contains(&(root, vaddr)) {
+                    page.writers.push((root, vaddr));
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Records that the page at `index` is no longer mapped writable at `vaddr`
+    /// in the page table rooted at `root`.
+    fn remove_writer(&self, index: u64, root: PhysAddr, vaddr: VirtAddr) {
+        if let Some(page) = self.pages.lock().get_mut(&index) {
+            page.writers.retain(|&writer| writer != (root, vaddr));
+        }
+    }
+
+    /// Returns the frame caching the page at `index`, reading it from the file
+    /// on first access.
+    ///
+    /// Returns `None` if the page is past the end of the file, or cannot be
+    /// read. The part of the last page past the end of the file is zeroed.
+    fn get_or_read(&self, index: u64) -> Option<PhysAddr> {
+        if let Some(frame) = self.frame(index) {
+            return Some(frame);
+        }
+        if self.is_past_eof(index) {
+            return None;
+        }
+        let frame = alloc_frame(true)?;
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K)
+        };
+        let offset = index * PAGE_SIZE_4K as u64;
+        let mut read = 0;
+        while read < PAGE_SIZE_4K {
+            match self.file.read_at(offset + read as u64, &mut buf[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(_) => {
+                    dealloc_frame(frame);
+                    return None;
+                }
+            }
+        }
+        // Another mapping may have read the same page meanwhile.
+        let mut pages = self.pages.lock();
+        if let Some(page) = pages.get(&index) {
+            let cached = page.frame;
+            drop(pages);
+            dealloc_frame(frame);
+            return Some(cached);
+        }
+        pages.insert(
+            index,
+            CachedPage {
+                frame,
+                dirty: false,
+                writers: Vec::new(),
+            },
+        );
+        Some(frame)
+    }
+
+    /// Writes the dirty pages with indices in `range` back to the file.
+    ///
+    /// The file is not extended: data past its current end is discarded.
+    pub fn sync(&self, range: Range<u64>) -> AxResult {
+        self.write_back(range, None)
+    }
+
+    /// Writes the dirty pages with indices in `range` back to the file, like
+    /// [`PageCache::sync`], while the address space of `pt` is locked.
+    pub(crate) fn sync_locked(&self, range: Range<u64>, pt: &mut PageTable) -> AxResult {
+        self.write_back(range, Some(pt))
+    }
+
+    fn write_back(&self, range: Range<u64>, mut pt: Option<&mut PageTable>) -> AxResult {
+        // The pages are marked clean and write-protected before they are
+        // written back, so that a write made meanwhile either is written back
+        // or marks them dirty again.
+        let dirty: Vec<_> = self
+            .pages
+            .lock()
+            .range_mut(range)
+            .filter(|(_, page)| page.dirty)
+            .map(|(&index, page)| {
+                page.dirty = false;
+                (index, page.frame, mem::take(&mut page.writers))
+            })
+            .collect();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        let mut protected = false;
+        for (index, frame, writers) in &dirty {
+            for &(root, vaddr) in writers {
+                let done = match pt.as_deref_mut() {
+                    Some(pt) if pt.root_paddr() == root => write_protect_page(pt, vaddr, *frame),
+                    _ => {
+                        WRITE_PROTECT.is_inited()
+                            && WRITE_PROTECT.write_protect(root, vaddr, *frame)
+                    }
+                };
+                if done {
+                    protected = true;
+                } else {
+                    self.mark_dirty(*index, root, vaddr);
+                }
+            }
+        }
+        if protected {
+            axhal::paging::flush_tlb_all_cpus(None);
+        }
+        let size = self.file.size();
+        for (i, &(index, frame, _)) in dirty.iter().enumerate() {
+            let offset = index * PAGE_SIZE_4K as u64;
+            let written = size.and_then(|size| {
+                if offset >= size {
+                    return Ok(());
+                }
+                let len = (size - offset).min(PAGE_SIZE_4K as u64) as usize;
+                let buf = unsafe { core::slice::from_raw_parts(phys_to_virt(frame).as_ptr(), len) };
+                self.file.write_at(offset, buf).map(|_| ())
+            });
+            if let Err(e) = written {
+                // Keep the pages not written back dirty.
+                let mut pages = self.pages.lock();
+                for (index, ..) in &dirty[i..] {
+                    if let Some(page) = pages.get_mut(index) {
+                        page.dirty = true;
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PageCache {
+    /// Copies `data`, just written to the file at `offset`, into the cached
+    /// pages it overlaps, so that the mappings see it.
+    pub fn write_through(&self, offset: u64, data: &[u8]) {
+        let page_size = PAGE_SIZE_4K as u64;
+        let end = offset + data.len() as u64;
+        let pages = self.pages.lock();
+        for (&index, page) in pages.range(offset / page_size..end.div_ceil(page_size)) {
+            let page_start = index * page_size;
+            let start = offset.max(page_start);
+            let stop = end.min(page_start + page_size);
+            let src = &data[(start - offset) as usize..(stop - offset) as usize];
+            let dst = phys_to_virt(page.frame).as_mut_ptr();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    src.as_ptr(),
+                    dst.add((start - page_start) as usize),
+                    src.len(),
+                );
+            }
+        }
+    }
+
+    /// Zeroes the cached data past `size`, after the file is truncated to it,
+    /// so that it reads as a hole if the file grows again.
+    pub fn truncate(&self, size: u64) {
+        let page_size = PAGE_SIZE_4K as u64;
+        let pages = self.pages.lock();
+        for (&index, page) in pages.range(size / page_size..) {
+            let start = size.saturating_sub(index * page_size) as usize;
+            let dst = phys_to_virt(page.frame).as_mut_ptr();
+            unsafe { core::ptr::write_bytes(dst.add(start), 0, PAGE_SIZE_4K - start) };
+        }
+    }
+}
+
+impl Drop for PageCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.sync(0..u64::MAX) {
+            warn!("Failed to write back the page cache: {:?}", e);
+        }
+        for page in self.pages.get_mut().values() {
+            dealloc_frame(page.frame);
+        }
+    }
+}
+
+/// Write-protects `frame` mapped at `vaddr` in `pt`, without flushing the TLB.
+///
+/// Returns `false` if it is mapped there but cannot be write-protected.
+pub(crate) fn write_protect_page(pt: &mut PageTable, vaddr: VirtAddr, frame: PhysAddr) -> bool {
+    match pt.query(vaddr) {
+        Ok((mapped, flags, _)) if mapped == frame && flags.contains(MappingFlags::WRITE) => pt
+            .protect(vaddr, flags - MappingFlags::WRITE)
+            .map(|(_, tlb)| tlb.ignore())
+            .is_ok(),
+        _ => true,
+    }
+}
+
+impl Backend {
+    /// Creates a new file mapping backend, mapping `start` to `offset` in the
+    /// file cached by `cache`.
+    pub fn new_file(
+        cache: Arc<PageCache>,
+        start: VirtAddr,
+        offset: u64,
+        shared: bool,
+        may_write: bool,
+    ) -> Self {
+        Self::File {
+            cache,
+            start,
+            offset,
+            shared,
+            may_write,
+        }
+    }
+
+    /// Returns the index of the file page mapped at `vaddr`, for a file
+    /// mapping that maps `start` to `offset` in the file.
+    pub(crate) fn file_page_index(vaddr: VirtAddr, start: VirtAddr, offset: u64) -> u64 {
+        (offset + (vaddr.align_down_4k().as_usize() - start.as_usize()) as u64)
+            / PAGE_SIZE_4K as u64
+    }
+
+    /// Returns whether `frame` mapped at `vaddr` belongs to the page cache of
+    /// this file mapping, rather than being a private copy.
+    pub(crate) fn is_cached_frame(&self, vaddr: VirtAddr, frame: PhysAddr) -> bool {
+        match *self {
+            Self::File {
+                ref cache,
+                start,
+                offset,
+                ..
+            } => cache.frame(Self::file_page_index(vaddr, start, offset)) == Some(frame),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn unmap_file(
+        start: VirtAddr,
+        size: usize,
+        pt: &mut PageTable,
+        cache: &PageCache,
+        first_index: u64,
+    ) -> bool {
+        debug!("unmap_file: [{:#x}, {:#x})", start, start + size);
+        for (index, addr) in (first_index..).zip(PageIter4K::new(start, start + size).unwrap()) {
+            if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
+                if page_size.is_huge() {
+                    return false;
+                }
+                tlb.flush();
+                // Cached frames are owned by the page cache, while private
+                // copies are freed like anonymous pages.
+                if cache.frame(index) == Some(frame) {
+                    cache.remove_writer(index, pt.root_paddr(), addr);
+                } else if release_frame(frame) {
+                    dealloc_frame(frame);
+                }
+            }
+        }
+        true
+    }
+
+    pub(crate) fn protect_file(
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+        cache: &PageCache,
+        first_index: u64,
+        shared: bool,
+    ) -> bool {
+        debug!(
+            "protect_file: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        for (index, addr) in (first_index..).zip(PageIter4K::new(start, start + size).unwrap()) {
+            let Ok((frame, _, _)) = pt.query(addr) else {
+                continue;
+            };
+            // Pages stay read-only until a write fault marks them dirty, or
+            // copies them for a private mapping.
+            let writable = if !shared {
+                cache.frame(index) != Some(frame) && !is_shared(frame)
+            } else if new_flags.contains(MappingFlags::WRITE) {
+                cache.keep_writable(index, pt.root_paddr(), addr)
+            } else {
+                cache.remove_writer(index, pt.root_paddr(), addr);
+                false
+            };
+            let flags = if writable {
+                new_flags
+            } else {
+                new_flags - MappingFlags::WRITE
+            };
+            match pt.protect(addr, flags) {
+                Ok((_, tlb)) => tlb.flush(),
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    pub(crate) fn handle_page_fault_file(
+        vaddr: VirtAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+        cache: &PageCache,
+        index: u64,
+        shared: bool,
+    ) -> bool {
+        let vaddr = vaddr.align_down_4k();
+        match pt.query(vaddr) {
+            // The first write to a page mapped read-only.
+            Ok((frame, flags, _))
+                if orig_flags.contains(MappingFlags::WRITE)
+                    && !flags.contains(MappingFlags::WRITE) =>
+            {
+                if shared {
+                    cache.mark_dirty(index, pt.root_paddr(), vaddr);
+                    pt.protect(vaddr, orig_flags)
+                        .map(|(_, tlb)| tlb.flush())
+                        .is_ok()
+                } else if cache.frame(index) == Some(frame) {
+                    Self::copy_cached_page(vaddr, frame, orig_flags, pt)
+                } else {
+                    // A private copy shared with another address space.
+                    Self::handle_cow_fault(vaddr, frame, orig_flags, pt)
+                }
+            }
+            // The page is already mapped with the proper flags, so the fault
+            // came from a stale TLB entry.
+            Ok(_) => {
+                axhal::arch::flush_tlb(Some(vaddr));
+                true
+            }
+            Err(_) => {
+                let Some(frame) = cache.get_or_read(index) else {
+                    return false;
+                };
+                // Map the page read-only first, so that the first write can be
+                // tracked (shared mappings) or copied (private mappings).
+                let flags = orig_flags - MappingFlags::WRITE;
+                pt.map(vaddr, frame, PageSize::Size4K, flags)
+                    .map(|tlb| tlb.flush())
+                    .is_ok()
+            }
+        }
+    }
+
+    /// Copies a cached page into a private frame on the first write to a
+    /// private mapping.
+    fn copy_cached_page(
+        vaddr: VirtAddr,
+        frame: PhysAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let Some(new_frame) = alloc_frame(false) else {
+            return false;
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                phys_to_virt(frame).as_ptr(),
+                phys_to_virt(new_frame).as_mut_ptr(),
+                PAGE_SIZE_4K,
+            )
+        };
+        match pt.remap(vaddr, new_frame, orig_flags) {
+            Ok((_, tlb)) => {
+                tlb.flush();
+                stats::record(&COW_COPIES);
+                true
+            }
+            Err(_) => {
+                dealloc_frame(new_frame);
+                false
+            }
+        }
+    }
+}
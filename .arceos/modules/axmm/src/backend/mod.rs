@@ -1,20 +1,32 @@
 //! Memory mapping backends.
 
+use ::alloc::sync::Arc;
+
 use axhal::paging::{MappingFlags, PageTable};
 use memory_addr::VirtAddr;
 use memory_set::MappingBackend;
 
 mod alloc;
+mod file;
 mod linear;
+mod shared;
+
+pub(crate) use self::file::write_protect_page;
+pub use self::file::{FileIo, PageCache, WriteProtect, set_write_protect};
+pub use self::shared::SharedMemory;
 
 /// A unified enum type for different memory mapping backends.
 ///
-/// Currently, two backends are implemented:
+/// Currently, four backends are implemented:
 ///
 /// - **Linear**: used for linear mappings. The target physical frames are
 ///   contiguous and their addresses should be known when creating the mapping.
 /// - **Allocation**: used in general, or for lazy mappings. The target physical
 ///   frames are obtained from the global allocator.
+/// - **File**: used for file mappings. The target physical frames are the
+///   pages of a [`PageCache`], read from the file on demand.
+/// - **Shared**: used for shared anonymous mappings. The target physical
+///   frames belong to a [`SharedMemory`] region, shared by all its mappings.
 #[derive(Clone)]
 pub enum Backend {
     /// Linear mapping backend.
@@ -36,6 +48,41 @@ pub enum Backend {
         /// Whether to populate the physical frames when creating the mapping.
         populate: bool,
     },
+    /// File mapping backend.
+    ///
+    /// Pages are faulted in lazily from the [`PageCache`] of the file. Writes
+    /// to a shared mapping go to the cached pages, which are written back to
+    /// the file later. A private mapping copies a page on the first write to
+    /// it. Accessing a page entirely past the end of the file fails.
+    File {
+        /// The cached pages of the mapped file.
+        cache: Arc<PageCache>,
+        /// The virtual address mapped to `offset` in the file.
+        ///
+        /// It is kept when the mapping is split, so it may be below the start
+        /// of the memory area.
+        start: VirtAddr,
+        /// The file offset mapped at `start`, aligned to the page size.
+        offset: u64,
+        /// Whether the mapping is shared with the file and other mappings.
+        shared: bool,
+        /// Whether a shared mapping may be made writable, i.e. the file was
+        /// open for writing when mapped.
+        may_write: bool,
+    },
+    /// Shared memory mapping backend.
+    ///
+    /// Pages are faulted in lazily from the [`SharedMemory`] region, which
+    /// owns the frames. Cloning the address space keeps them shared.
+    Shared {
+        /// The shared memory region.
+        memory: Arc<SharedMemory>,
+        /// The virtual address mapped to the beginning of `memory`.
+        ///
+        /// It is kept when the mapping is split, so it may be below the start
+        /// of the memory area.
+        start: VirtAddr,
+    },
 }
 
 impl MappingBackend for Backend {
@@ -46,6 +93,10 @@ impl MappingBackend for Backend {
         match *self {
             Self::Linear { pa_va_offset } => Self::map_linear(start, size, flags, pt, pa_va_offset),
             Self::Alloc { populate } => Self::map_alloc(start, size, flags, pt, populate),
+            // File pages are mapped on demand in `handle_page_fault_file`.
+            Self::File { .. } => true,
+            // Shared pages are mapped on demand in `handle_page_fault_shared`.
+            Self::Shared { .. } => true,
         }
     }
 
@@ -53,6 +104,16 @@ impl MappingBackend for Backend {
         match *self {
             Self::Linear { pa_va_offset } => Self::unmap_linear(start, size, pt, pa_va_offset),
             Self::Alloc { populate } => Self::unmap_alloc(start, size, pt, populate),
+            Self::File {
+                ref cache,
+                start: file_start,
+                offset,
+                ..
+            } => {
+                let first_index = Self::file_page_index(start, file_start, offset);
+                Self::unmap_file(start, size, pt, cache, first_index)
+            }
+            Self::Shared { .. } => Self::unmap_shared(start, size, pt),
         }
     }
 
@@ -63,10 +124,31 @@ impl MappingBackend for Backend {
         new_flags: Self::Flags,
         page_table: &mut Self::PageTable,
     ) -> bool {
-        page_table
-            .protect_region(start, size, new_flags, true)
-            .map(|tlb| tlb.ignore())
-            .is_ok()
+        match *self {
+            Self::Linear { .. } | Self::Shared { .. } => page_table
+                .protect_region(start, size, new_flags, true)
+                .map(|tlb| tlb.ignore())
+                .is_ok(),
+            Self::Alloc { .. } => Self::protect_alloc(start, size, new_flags, page_table),
+            Self::File {
+                ref cache,
+                start: file_start,
+                offset,
+                shared,
+                ..
+            } => {
+                let first_index = Self::file_page_index(start, file_start, offset);
+                Self::protect_file(
+                    start,
+                    size,
+                    new_flags,
+                    page_table,
+                    cache,
+                    first_index,
+                    shared,
+                )
+            }
+        }
     }
 }
 
@@ -82,6 +164,19 @@ impl Backend {
             Self::Alloc { populate } => {
                 Self::handle_page_fault_alloc(vaddr, orig_flags, page_table, populate)
             }
+            Self::File {
+                ref cache,
+                start,
+                offset,
+                shared,
+                ..
+            } => {
+                let index = Self::file_page_index(vaddr, start, offset);
+                Self::handle_page_fault_file(vaddr, orig_flags, page_table, cache, index, shared)
+            }
+            Self::Shared { ref memory, start } => {
+                Self::handle_page_fault_shared(vaddr, orig_flags, page_table, memory, start)
+            }
         }
     }
 }
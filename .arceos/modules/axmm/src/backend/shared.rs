@@ -0,0 +1,107 @@
+use alloc::{collections::BTreeMap, sync::Arc};
+
+use axhal::paging::{MappingFlags, PageSize, PageTable};
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr};
+
+use super::Backend;
+use super::alloc::{alloc_frame, dealloc_frame};
+use crate::stats::{self, LAZY_ALLOCS};
+
+/// Anonymous memory shared by every mapping of it.
+///
+/// Frames are allocated zeroed on first access, and owned by the region
+/// rather than by any mapping, so they stay shared across
+/// [`AddrSpace::clone_or_err`](crate::AddrSpace::clone_or_err). They are freed
+/// when the region is dropped.
+pub struct SharedMemory {
+    size: usize,
+    frames: SpinNoIrq<BTreeMap<usize, PhysAddr>>,
+}
+
+impl SharedMemory {
+    /// Creates a shared memory region of the given size, rounded up to the
+    /// page size.
+    pub fn new(size: usize) -> Self {
+        Self {
+            size: size.align_up_4k(),
+            frames: SpinNoIrq::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the size of the region.
+    pub const fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the frame backing the page at `index`, allocating it on first
+    /// access.
+    ///
+    /// Returns `None` if the page is out of the region or no memory is left.
+    fn get_or_alloc(&self, index: usize) -> Option<PhysAddr> {
+        if index >= self.size / PAGE_SIZE_4K {
+            return None;
+        }
+        let mut frames = self.frames.lock();
+        if let Some(&frame) = frames.get(&index) {
+            return Some(frame);
+        }
+        let frame = alloc_frame(true)?;
+        frames.insert(index, frame);
+        stats::record(&LAZY_ALLOCS);
+        Some(frame)
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        for &frame in self.frames.get_mut().values() {
+            dealloc_frame(frame);
+        }
+    }
+}
+
+impl Backend {
+    /// Creates a new shared memory mapping backend, mapping `start` to the
+    /// beginning of `memory`.
+    pub fn new_shared(memory: Arc<SharedMemory>, start: VirtAddr) -> Self {
+        Self::Shared { memory, start }
+    }
+
+    pub(crate) fn unmap_shared(start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
+        debug!("unmap_shared: [{:#x}, {:#x})", start, start + size);
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            if let Ok((_, page_size, tlb)) = pt.unmap(addr) {
+                if page_size.is_huge() {
+                    return false;
+                }
+                // The frame is still owned by the shared memory region.
+                tlb.flush();
+            }
+        }
+        true
+    }
+
+    pub(crate) fn handle_page_fault_shared(
+        vaddr: VirtAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+        memory: &SharedMemory,
+        start: VirtAddr,
+    ) -> bool {
+        let vaddr = vaddr.align_down_4k();
+        if pt.query(vaddr).is_ok() {
+            // The page is already mapped, so the fault came from a stale TLB
+            // entry.
+            axhal::arch::flush_tlb(Some(vaddr));
+            return true;
+        }
+        let index = (vaddr.as_usize() - start.as_usize()) / PAGE_SIZE_4K;
+        let Some(frame) = memory.get_or_alloc(index) else {
+            return false;
+        };
+        pt.map(vaddr, frame, PageSize::Size4K, orig_flags)
+            .map(|tlb| tlb.flush())
+            .is_ok()
+    }
+}
@@ -0,0 +1,38 @@
+//! Reference counts of the physical frames shared between address spaces.
+//!
+//! Only frames with more than one owner are tracked. A frame missing from the
+//! table is owned by a single mapping.
+
+use alloc::collections::BTreeMap;
+
+use kspin::SpinNoIrq;
+use memory_addr::PhysAddr;
+
+static SHARED_FRAMES: SpinNoIrq<BTreeMap<PhysAddr, usize>> = SpinNoIrq::new(BTreeMap::new());
+
+/// Adds a reference to the given frame.
+pub(crate) fn share_frame(frame: PhysAddr) {
+    *SHARED_FRAMES.lock().entry(frame).or_insert(1) += 1;
+}
+
+/// Drops a reference to the given frame.
+///
+/// Returns `true` if it was the last reference, and the frame can be freed.
+pub(crate) fn release_frame(frame: PhysAddr) -> bool {
+    let mut frames = SHARED_FRAMES.lock();
+    match frames.get_mut(&frame) {
+        Some(count) => {
+            *count -= 1;
+            if *count == 1 {
+                frames.remove(&frame);
+            }
+            false
+        }
+        None => true,
+    }
+}
+
+/// Returns whether the given frame is mapped by more than one owner.
+pub(crate) fn is_shared(frame: PhysAddr) -> bool {
+    SHARED_FRAMES.lock().contains_key(&frame)
+}
@@ -8,9 +8,14 @@ extern crate alloc;
 
 mod aspace;
 mod backend;
+mod frame;
+mod stats;
 
 pub use self::aspace::AddrSpace;
-pub use self::backend::Backend;
+pub use self::backend::{
+    Backend, FileIo, PageCache, SharedMemory, WriteProtect, set_write_protect,
+};
+pub use self::stats::{PageFaultStats, page_fault_stats};
 
 use axerrno::{AxError, AxResult};
 use axhal::mem::phys_to_virt;
@@ -0,0 +1,43 @@
+//! Statistics of the page faults handled by the allocation backend.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub(crate) static LAZY_ALLOCS: AtomicUsize = AtomicUsize::new(0);
+pub(crate) static COW_COPIES: AtomicUsize = AtomicUsize::new(0);
+pub(crate) static COW_REUSES: AtomicUsize = AtomicUsize::new(0);
+pub(crate) static FORK_SHARES: AtomicUsize = AtomicUsize::new(0);
+
+/// A snapshot of the page fault statistics.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageFaultStats {
+    /// Frames allocated on the first access to a lazy mapping.
+    pub lazy_allocs: usize,
+    /// Shared frames copied on a write fault.
+    pub cow_copies: usize,
+    /// Frames made writable again without copying, as they were no longer
+    /// shared when written to.
+    pub cow_reuses: usize,
+    /// Frames shared instead of copied when cloning an address space.
+    pub fork_shares: usize,
+}
+
+impl PageFaultStats {
+    /// Returns the number of frame copies avoided by copy-on-write.
+    pub const fn copies_saved(&self) -> usize {
+        self.fork_shares.saturating_sub(self.cow_copies)
+    }
+}
+
+pub(crate) fn record(counter: &AtomicUsize) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the page fault statistics since boot.
+pub fn page_fault_stats() -> PageFaultStats {
+    PageFaultStats {
+        lazy_allocs: LAZY_ALLOCS.load(Ordering::Relaxed),
+        cow_copies: COW_COPIES.load(Ordering::Relaxed),
+        cow_reuses: COW_REUSES.load(Ordering::Relaxed),
+        fork_shares: FORK_SHARES.load(Ordering::Relaxed),
+    }
+}
@@ -509,6 +509,11 @@ impl TcpSocket {
                 }
                 match f() {
                     Ok(t) => return Ok(t),
+                    // Give up if the task is asked to, as a non-blocking
+                    // socket would.
+                    Err(AxError::WouldBlock) if axtask::current().is_interrupted() => {
+                        return Err(AxError::WouldBlock);
+                    }
                     Err(AxError::WouldBlock) => axtask::yield_now(),
                     Err(e) => return Err(e),
                 }
@@ -273,6 +273,11 @@ impl UdpSocket {
                 }
                 match f() {
                     Ok(t) => return Ok(t),
+                    // Give up if the task is asked to, as a non-blocking
+                    // socket would.
+                    Err(AxError::WouldBlock) if axtask::current().is_interrupted() => {
+                        return Err(AxError::WouldBlock);
+                    }
                     Err(AxError::WouldBlock) => axtask::yield_now(),
                     Err(e) => return Err(e),
                 }
@@ -295,6 +300,11 @@ impl UdpSocket {
                 }
                 match f() {
                     Ok(t) => return Ok(t),
+                    // Give up if the task is asked to, as a non-blocking
+                    // socket would.
+                    Err(AxError::WouldBlock) if axtask::current().is_interrupted() => {
+                        return Err(AxError::WouldBlock);
+                    }
                     Err(AxError::WouldBlock) => axtask::yield_now(),
                     Err(e) => return Err(e),
                 }
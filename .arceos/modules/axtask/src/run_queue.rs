@@ -440,6 +440,15 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
             .lock()
             .set_priority(self.current_task.as_task_ref(), prio)
     }
+
+    /// Sets the priority of `task`, which may be ready or running on another
+    /// CPU.
+    ///
+    /// The schedulers keep the priority in the task itself, without reordering
+    /// their ready queues, so the run queue of this CPU can set it.
+    pub fn set_task_priority(&mut self, task: &AxTaskRef, prio: isize) -> bool {
+        self.inner.scheduler.lock().set_priority(task, prio)
+    }
 }
 
 impl AxRunQueue {
@@ -55,6 +55,9 @@ pub struct TaskInner {
     /// Mark whether the task is in the wait queue.
     in_wait_queue: AtomicBool,
 
+    /// Mark whether the task is asked to give up its blocking operations.
+    interrupted: AtomicBool,
+
     /// Used to indicate whether the task is running on a CPU.
     #[cfg(feature = "smp")]
     on_cpu: AtomicBool,
@@ -234,6 +237,19 @@ impl TaskInner {
     pub fn exit_code(&self) -> i32 {
         self.exit_code.load(Ordering::Acquire)
     }
+
+    /// Asks the task to give up the blocking operation it is performing, and
+    /// the ones it may start later, e.g. because it is about to exit.
+    ///
+    /// Blocking operations that poll in a loop check [`Self::is_interrupted`].
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Release);
+    }
+
+    /// Returns whether the task is asked to give up its blocking operations.
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Acquire)
+    }
 }
 
 // private methods
@@ -249,6 +265,7 @@ impl TaskInner {
             // By default, the task is allowed to run on all CPUs.
             cpumask: SpinNoIrq::new(AxCpuMask::full()),
             in_wait_queue: AtomicBool::new(false),
+            interrupted: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             timer_ticket_id: AtomicU64::new(0),
             #[cfg(feature = "smp")]
@@ -420,8 +437,14 @@ impl TaskInner {
         self.wait_for_exit.notify_all(false);
     }
 
+    /// Returns a raw pointer to the saved context of the task.
+    ///
+    /// # Safety
+    ///
+    /// The context is written by context switches. It may only be modified
+    /// by the task itself, e.g. to change its page table root.
     #[inline]
-    pub(crate) const unsafe fn ctx_mut_ptr(&self) -> *mut TaskContext {
+    pub const unsafe fn ctx_mut_ptr(&self) -> *mut TaskContext {
         self.ctx.get()
     }
 
@@ -49,9 +49,10 @@ basic_testlist=(
     "/$LIBC/basic/write"
     "/$LIBC/basic/openat"
     "/$LIBC/basic/getdents"
-    "/$LIBC/basic/mount"
-    "/$LIBC/basic/umount"
 )
+# The `mount` and `umount` testcases mount `/dev/vda2`, but the test image is
+# attached as the single disk `/dev/vda` with no partitions, so they are not
+# run until a spare disk is attached for them.
 busybox_testlist=("/$LIBC/busybox sh /$LIBC/busybox_testcode.sh")
 iozone_testlist=("/$LIBC/busybox sh /$LIBC/iozone_testcode.sh")
 lua_testlist=("/$LIBC/busybox sh /$LIBC/lua_testcode.sh")
@@ -2,6 +2,7 @@
 
 pub const UTIME_NOW:usize = 0x3fffffff; 
 pub const UTIME_OMIT:usize = 0x3FFFFFFE; 
+use arceos_posix_api::ctypes::timeval;
 use bitflags::*;
 bitflags! {
     /// 用于 sys_clone 的选项
@@ -52,8 +53,12 @@ bitflags! {
         const WNOHANG = 1 << 0;
         /// 报告已执行结束的用户进程的状态
         const WIMTRACED = 1 << 1;
+        /// 报告已退出的子进程，sys_waitid 使用
+        const WEXITED = 1 << 2;
         /// 报告还未结束的用户进程的状态
         const WCONTINUED = 1 << 3;
+        /// 只报告状态，不回收子进程，也不清除其状态
+        const WNOWAIT = 1 << 24;
         /// Wait for any child
         const WALL = 1 << 30;
         /// Wait for cloned process
@@ -73,11 +78,14 @@ pub struct SigsetT {
 
 
 // 定义 struct sigaction
+//
+// RISC-V and LoongArch have no `sa_restorer` field in the kernel `struct sigaction`.
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct SigAction {
     pub sa_handler: usize,                    // void (*)(int)
     pub sa_flags: usize,                        // int
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
     pub sa_restorer: usize,
     pub sa_mask: usize,                       // sigset_t
 }
@@ -85,21 +93,124 @@ pub struct SigAction {
 impl Default for SigAction {
     fn default() -> Self {
         Self {
-            sa_handler: 0,
+            sa_handler: SIG_DFL,
             sa_flags: 0,
+            #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
             sa_restorer: 0,
             sa_mask: 0,
         }
     }
 }
 
-// siginfo_t 的简化定义（根据需要扩展）
+/// 默认信号处理函数
+pub const SIG_DFL: usize = 0;
+/// 忽略信号
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// sigaction 的 sa_flags
+    #[derive(Debug, Clone, Copy)]
+    pub struct SigActionFlags: usize {
+        /// 子进程停止时不发送 SIGCHLD
+        const SA_NOCLDSTOP = 1;
+        /// 子进程退出时不变成僵尸进程
+        const SA_NOCLDWAIT = 2;
+        /// 处理函数接收三个参数 (signo, siginfo, ucontext)
+        const SA_SIGINFO = 4;
+        /// 使用 sa_restorer 作为处理函数的返回地址
+        const SA_RESTORER = 0x0400_0000;
+        /// 在备用信号栈上执行处理函数
+        const SA_ONSTACK = 0x0800_0000;
+        /// 被信号打断的系统调用自动重启
+        const SA_RESTART = 0x1000_0000;
+        /// 处理函数执行期间不屏蔽当前信号
+        const SA_NODEFER = 0x4000_0000;
+        /// 处理函数执行一次后恢复默认行为
+        const SA_RESETHAND = 0x8000_0000;
+    }
+}
+
+/// 由 kill 或 raise 发送的信号
+pub const SI_USER: i32 = 0;
+/// 由内核发送的信号
+pub const SI_KERNEL: i32 = 0x80;
+/// 由 sigqueue 发送的信号
+pub const SI_QUEUE: i32 = -1;
+/// 由消息队列到达新消息发送的信号
+pub const SI_MESGQ: i32 = -3;
+/// 由 tkill 或 tgkill 发送的信号
+pub const SI_TKILL: i32 = -6;
+
+/// SIGILL 的 si_code: 非法操作码
+pub const ILL_ILLOPC: i32 = 1;
+/// SIGILL 的 si_code: 特权指令
+pub const ILL_PRVOPC: i32 = 5;
+/// SIGFPE 的 si_code: 整数除零
+pub const FPE_INTDIV: i32 = 1;
+/// SIGFPE 的 si_code: 未知的浮点异常
+pub const FPE_FLTUNK: i32 = 14;
+/// SIGSEGV 的 si_code: 地址未映射
+pub const SEGV_MAPERR: i32 = 1;
+/// SIGSEGV 的 si_code: 访问权限不足
+pub const SEGV_ACCERR: i32 = 2;
+/// SIGBUS 的 si_code: 地址未对齐
+pub const BUS_ADRALN: i32 = 1;
+/// SIGBUS 的 si_code: 物理地址不存在（如访问超出文件末尾的映射）
+pub const BUS_ADRERR: i32 = 2;
+
+/// SIGCHLD 的 si_code: 子进程正常退出
+pub const CLD_EXITED: i32 = 1;
+/// SIGCHLD 的 si_code: 子进程被信号终止
+pub const CLD_KILLED: i32 = 2;
+/// SIGCHLD 的 si_code: 子进程被信号终止并产生 core dump
+pub const CLD_DUMPED: i32 = 3;
+/// SIGCHLD 的 si_code: 子进程被信号暂停
+pub const CLD_STOPPED: i32 = 5;
+/// SIGCHLD 的 si_code: 子进程被 SIGCONT 恢复
+pub const CLD_CONTINUED: i32 = 6;
+
+/// siginfo_t，共 128 字节
 #[repr(C)]
+#[derive(Clone, Copy, Debug)]
 pub struct SiginfoT {
-    si_signo: i32,
-    si_errno: i32,
-    si_code: i32,
-    // 其他字段根据具体需求添加，例如 si_pid, si_uid 等
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad: i32,
+    /// `_sifields` 联合体
+    pub si_fields: [u64; 14],
+}
+
+impl SiginfoT {
+    pub fn new(signo: usize, code: i32) -> Self {
+        Self {
+            si_signo: signo as i32,
+            si_errno: 0,
+            si_code: code,
+            _pad: 0,
+            si_fields: [0; 14],
+        }
+    }
+
+    /// 设置发送者的 pid 与 uid (`si_pid`, `si_uid`)
+    pub fn set_sender(&mut self, pid: u32, uid: u32) {
+        self.si_fields[0] = pid as u64 | (uid as u64) << 32;
+    }
+
+    /// 设置引发故障的地址 (`si_addr`)
+    pub fn set_addr(&mut self, addr: usize) {
+        self.si_fields[0] = addr as u64;
+    }
+
+    /// 设置随信号传递的数据 (`si_value`)
+    pub fn set_value(&mut self, value: usize) {
+        self.si_fields[1] = value as u64;
+    }
+
+    /// 设置 SIGCHLD 携带的子进程状态 (`si_status`)
+    pub fn set_status(&mut self, status: i32) {
+        self.si_fields[1] = status as u32 as u64;
+    }
 }
 
 pub const VAILD_SIGNAL: usize = 64;
@@ -204,15 +315,291 @@ bitflags! {
     }
 }
 
-/// sys_wait4 的返回值
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum WaitStatus {
-    /// 子任务正常退出
-    Exited,
-    /// 子任务正在运行
-    Running,
-    /// 找不到对应的子任务
-    NotExist,
+impl SignalFlags {
+    /// Returns the flag of the given signal number (1-based).
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if signum == 0 || signum > VAILD_SIGNAL {
+            return None;
+        }
+        Some(Self::from_bits_retain(1 << (signum - 1)))
+    }
+
+    /// Returns the lowest signal number in the set.
+    pub fn lowest_signum(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.bits().trailing_zeros() as usize + 1)
+        }
+    }
+}
+
+numeric_enum_macro::numeric_enum! {
+    #[repr(u32)]
+    #[allow(non_camel_case_types)]
+    #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    /// sys_waitid 中 id 的类型
+    pub enum WaitIdType {
+        /// 等待任意子进程
+        P_ALL = 0,
+        /// 等待进程号为 id 的子进程
+        P_PID = 1,
+        /// 等待进程组号为 id 的子进程
+        P_PGID = 2,
+        /// 等待 pidfd 为 id 的子进程
+        P_PIDFD = 3,
+    }
+}
+
+/// sys_wait4 与 sys_getrusage 返回的资源使用情况
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rusage {
+    /// 用户态执行时间
+    pub ru_utime: timeval,
+    /// 内核态执行时间
+    pub ru_stime: timeval,
+    /// 其余统计项，目前均为 0
+    pub ru_others: [isize; 14],
+}
+
+/// 文件模式中的 set-user-ID 位
+pub const S_ISUID: u32 = 0o4000;
+/// 文件模式中的 set-group-ID 位
+pub const S_ISGID: u32 = 0o2000;
+/// 文件模式中的同组用户执行权限位
+pub const S_IXGRP: u32 = 0o010;
+
+/// 不跟随路径最后一个分量的符号链接
+pub const AT_SYMLINK_NOFOLLOW: i32 = 0x100;
+/// linkat: 跟随 oldpath 最后一个分量的符号链接
+pub const AT_SYMLINK_FOLLOW: i32 = 0x400;
+/// 路径为空时操作 dirfd 本身
+pub const AT_EMPTY_PATH: i32 = 0x1000;
+
+/// ioctl: 将环回设备绑定到文件描述符对应的文件
+pub const LOOP_SET_FD: u32 = 0x4C00;
+/// ioctl: 解除环回设备与文件的绑定
+pub const LOOP_CLR_FD: u32 = 0x4C01;
+/// ioctl: 获取环回设备的状态
+pub const LOOP_GET_STATUS64: u32 = 0x4C05;
+/// 环回设备只读
+pub const LO_FLAGS_READ_ONLY: u32 = 1;
+
+/// LOOP_GET_STATUS64 使用的环回设备状态
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LoopInfo64 {
+    /// 后备文件所在设备号
+    pub lo_device: u64,
+    /// 后备文件的 inode 号
+    pub lo_inode: u64,
+    /// 环回设备的设备号
+    pub lo_rdevice: u64,
+    /// 数据在后备文件中的起始偏移
+    pub lo_offset: u64,
+    /// 数据大小上限，为 0 时使用整个文件
+    pub lo_sizelimit: u64,
+    /// 环回设备的编号 N，即 /dev/loopN
+    pub lo_number: u32,
+    pub lo_encrypt_type: u32,
+    pub lo_encrypt_key_size: u32,
+    /// LO_FLAGS_* 标志
+    pub lo_flags: u32,
+    /// 后备文件的路径
+    pub lo_file_name: [u8; 64],
+    pub lo_crypt_name: [u8; 64],
+    pub lo_encrypt_key: [u8; 32],
+    pub lo_init: [u64; 2],
+}
+
+/// 私有的 IPC 键，总是创建新的 IPC 对象
+pub const IPC_PRIVATE: i32 = 0;
+/// IPC 对象不存在时创建
+pub const IPC_CREAT: i32 = 0o1000;
+/// 与 IPC_CREAT 一起使用，IPC 对象已存在时报错
+pub const IPC_EXCL: i32 = 0o2000;
+/// 不阻塞等待
+pub const IPC_NOWAIT: i32 = 0o4000;
+/// 立即删除 IPC 对象
+pub const IPC_RMID: i32 = 0;
+/// 设置 IPC 对象的属主与权限
+pub const IPC_SET: i32 = 1;
+/// 获取 IPC 对象的信息
+pub const IPC_STAT: i32 = 2;
+/// 使用 64 位 IPC 结构的标志，可以忽略
+pub const IPC_64: i32 = 0x100;
+
+/// 以只读方式附加共享内存段
+pub const SHM_RDONLY: i32 = 0o10000;
+/// 将附加地址向下对齐到 SHMLBA
+pub const SHM_RND: i32 = 0o20000;
+/// 替换附加地址处已有的映射
+pub const SHM_REMAP: i32 = 0o40000;
+/// 允许执行共享内存段中的代码
+pub const SHM_EXEC: i32 = 0o100000;
+/// 共享内存段已被删除，在最后一次分离后销毁，仅出现在 shm_perm.mode 中
+pub const SHM_DEST: u32 = 0o1000;
+
+/// 信号量操作在进程退出时自动撤销
+pub const SEM_UNDO: i16 = 0x1000;
+/// semctl: 获取最后一次操作信号量的进程号
+pub const GETPID: i32 = 11;
+/// semctl: 获取信号量的值
+pub const GETVAL: i32 = 12;
+/// semctl: 获取信号量集中所有信号量的值
+pub const GETALL: i32 = 13;
+/// semctl: 获取等待信号量增加的进程数
+pub const GETNCNT: i32 = 14;
+/// semctl: 获取等待信号量变为 0 的进程数
+pub const GETZCNT: i32 = 15;
+/// semctl: 设置信号量的值
+pub const SETVAL: i32 = 16;
+/// semctl: 设置信号量集中所有信号量的值
+pub const SETALL: i32 = 17;
+
+/// msgrcv: 消息过长时截断而不报错
+pub const MSG_NOERROR: i32 = 0o10000;
+/// msgrcv: 接收第一条类型不等于 msgtyp 的消息
+pub const MSG_EXCEPT: i32 = 0o20000;
+
+/// System V IPC 对象的权限信息
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IpcPerm {
+    /// 创建时使用的键
+    pub key: i32,
+    /// 属主的用户号
+    pub uid: u32,
+    /// 属主的组号
+    pub gid: u32,
+    /// 创建者的用户号
+    pub cuid: u32,
+    /// 创建者的组号
+    pub cgid: u32,
+    /// 访问权限，低 9 位与文件权限相同
+    pub mode: u32,
+    /// 序号
+    pub seq: u16,
+    pub __pad: u16,
+    pub __unused: [u64; 2],
+}
+
+/// semop 中的一个信号量操作
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SemBuf {
+    /// 信号量在信号量集中的下标
+    pub sem_num: u16,
+    /// 为正时增加信号量，为负时等待并减少信号量，为 0 时等待信号量变为 0
+    pub sem_op: i16,
+    /// IPC_NOWAIT 与 SEM_UNDO 标志
+    pub sem_flg: i16,
+}
+
+/// semctl 使用的信号量集信息
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SemidDs {
+    /// 权限信息
+    pub sem_perm: IpcPerm,
+    /// 最后一次 semop 的时间
+    pub sem_otime: isize,
+    #[cfg(target_arch = "x86_64")]
+    pub __unused1: usize,
+    /// 最后一次修改的时间
+    pub sem_ctime: isize,
+    #[cfg(target_arch = "x86_64")]
+    pub __unused2: usize,
+    /// 信号量集中信号量的个数
+    pub sem_nsems: usize,
+    pub __unused: [usize; 2],
+}
+
+/// msgctl 使用的消息队列信息
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MsqidDs {
+    /// 权限信息
+    pub msg_perm: IpcPerm,
+    /// 最后一次发送的时间
+    pub msg_stime: isize,
+    /// 最后一次接收的时间
+    pub msg_rtime: isize,
+    /// 最后一次修改的时间
+    pub msg_ctime: isize,
+    /// 队列中消息的总字节数
+    pub msg_cbytes: usize,
+    /// 队列中消息的个数
+    pub msg_qnum: usize,
+    /// 队列允许的最大字节数
+    pub msg_qbytes: usize,
+    /// 最后一次发送的进程号
+    pub msg_lspid: i32,
+    /// 最后一次接收的进程号
+    pub msg_lrpid: i32,
+    pub __unused: [usize; 2],
+}
+
+/// shmctl 使用的共享内存段信息
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShmidDs {
+    /// 权限信息
+    pub shm_perm: IpcPerm,
+    /// 段的大小，单位为字节
+    pub shm_segsz: usize,
+    /// 最后一次附加的时间
+    pub shm_atime: isize,
+    /// 最后一次分离的时间
+    pub shm_dtime: isize,
+    /// 最后一次修改的时间
+    pub shm_ctime: isize,
+    /// 创建者的进程号
+    pub shm_cpid: i32,
+    /// 最后一次附加或分离的进程号
+    pub shm_lpid: i32,
+    /// 当前附加的次数
+    pub shm_nattch: usize,
+    pub __unused: [usize; 2],
+}
+
+/// POSIX 消息队列的最大优先级（不含）
+pub const MQ_PRIO_MAX: u32 = 32768;
+
+/// mq_getattr / mq_setattr 使用的消息队列属性
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MqAttr {
+    /// 队列描述符的标志，仅 O_NONBLOCK 有效
+    pub mq_flags: isize,
+    /// 队列中消息的最大个数
+    pub mq_maxmsg: isize,
+    /// 消息的最大字节数
+    pub mq_msgsize: isize,
+    /// 队列中当前的消息个数
+    pub mq_curmsgs: isize,
+    pub __reserved: [isize; 4],
+}
+
+/// 以信号通知
+pub const SIGEV_SIGNAL: i32 = 0;
+/// 不通知
+pub const SIGEV_NONE: i32 = 1;
+/// 在新线程中调用通知函数
+pub const SIGEV_THREAD: i32 = 2;
+
+/// 异步事件的通知方式
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SigEvent {
+    /// 随通知传递的数据 (`sigev_value`)
+    pub sigev_value: usize,
+    /// 通知使用的信号
+    pub sigev_signo: i32,
+    /// 通知方式，即 SIGEV_*
+    pub sigev_notify: i32,
+    pub __pad: [i32; 12],
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -235,46 +622,61 @@ impl TryFrom<i32> for SignalSet {
     }
 }
 
-#[repr(i32)]
-#[derive(Debug)]
-pub enum FluxStatus {
-    Wait,           // 0: FUTEX_WAIT
-    Wake,           // 1: FUTEX_WAKE
-    Requeue,        // 3: FUTEX_REQUEUE
-    CmpRequeue,     // 4: FUTEX_CMP_REQUEUE
-    WakeOp,         // 5: FUTEX_WAKE_OP
-    LockPi,         // 6: FUTEX_LOCK_PI
-    UnlockPi,       // 7: FUTEX_UNLOCK_PI
-    WaitBitset,     // 9: FUTEX_WAIT_BITSET
-    WakeBitset,     // 10: FUTEX_WAKE_BITSET
-    WaitPrivate = 128,    // 128: FUTEX_WAIT_PRIVATE
-    WakePrivate = 129,    // 129: FUTEX_WAKE_PRIVATE
-    NONE = -1,
-}
+/// futex 操作中的私有标志，表示 futex 只在本进程内共享
+pub const FUTEX_PRIVATE_FLAG: u32 = 128;
+/// futex 操作中的时钟标志，表示超时时间基于 CLOCK_REALTIME
+pub const FUTEX_CLOCK_REALTIME: u32 = 256;
+/// 匹配任意等待者的 bitset
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+/// PI futex 与 robust futex 中，表示存在等待者的位
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// robust futex 中，表示持有者已退出的位
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// PI futex 与 robust futex 中，持有者线程号所在的位
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
 
-// 实现 TryFrom<i32> 以支持 i32 到 FluxStatus 的转换
-impl TryFrom<i32> for FluxStatus {
-    type Error = &'static str;
-    fn try_from(value: i32) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(FluxStatus::Wait),
-            1 => Ok(FluxStatus::Wake),
-            3 => Ok(FluxStatus::Requeue),
-            4 => Ok(FluxStatus::CmpRequeue),
-            5 => Ok(FluxStatus::WakeOp),
-            6 => Ok(FluxStatus::LockPi),
-            7 => Ok(FluxStatus::UnlockPi),
-            9 => Ok(FluxStatus::WaitBitset),
-            10 => Ok(FluxStatus::WakeBitset),
-            128 => Ok(FluxStatus::WaitPrivate),
-            129 => Ok(FluxStatus::WakePrivate),
-            -1 => Ok(FluxStatus::NONE),
-            _ => Err("Invalid value for FluxStatus"),
-        }
+numeric_enum_macro::numeric_enum! {
+    #[repr(u32)]
+    #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    /// futex 的操作类型，不含私有标志与时钟标志
+    pub enum FutexOp {
+        /// 若 futex 的值等于 val，则睡眠等待
+        Wait = 0,
+        /// 唤醒至多 val 个等待者
+        Wake = 1,
+        /// 唤醒至多 val 个等待者，并将至多 val2 个等待者转移到 uaddr2
+        Requeue = 3,
+        /// 同 Requeue，但要求 futex 的值等于 val3
+        CmpRequeue = 4,
+        /// 修改 uaddr2 处的值，并按比较结果唤醒两个 futex 上的等待者
+        WakeOp = 5,
+        /// 同 Wait，但超时为绝对时间，并指定等待者的 bitset
+        WaitBitset = 9,
+        /// 获取 PI futex 锁，必要时睡眠等待，超时为 CLOCK_REALTIME 上的绝对时间
+        LockPi = 6,
+        /// 释放 PI futex 锁，并唤醒优先级最高的等待者
+        UnlockPi = 7,
+        /// 尝试获取 PI futex 锁，不睡眠
+        TrylockPi = 8,
+        /// 唤醒 bitset 与 val3 相交的至多 val 个等待者
+        WakeBitset = 10,
     }
 }
 
-
+/// robust futex 链表的表头，由 set_robust_list 注册
+///
+/// 链表中的每一项指向下一项，futex 位于每一项偏移 futex_offset 处。
+/// 指针的最低位置 1 表示该 futex 为 PI futex。
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RobustListHead {
+    /// 链表中的第一项，链表为空时指向表头自身
+    pub list: usize,
+    /// futex 相对于链表项的偏移
+    pub futex_offset: isize,
+    /// 正在加入或移出链表的项
+    pub list_op_pending: usize,
+}
 
 #[repr(C)]
 pub struct Tms {
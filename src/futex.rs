@@ -0,0 +1,601 @@
+//! Futexes.
+//!
+//! Waiters are kept in a hashed table of buckets. A private futex is keyed by
+//! its address space and user address, and a shared futex by the physical
+//! address of the futex word, so that it matches in every process mapping the
+//! same page. Each waiter sleeps on its own [`WaitQueue`].
+//!
+//! Priority-inheritance futexes hold the TID of their owner, which is boosted
+//! to the priority of its highest-priority waiter until it unlocks. Robust
+//! futexes still held by an exiting thread are marked with
+//! `FUTEX_OWNER_DIED`, and one of their waiters is woken up.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{TimeValue, monotonic_time};
+use axsync::{Mutex, MutexGuard};
+use axtask::{TaskExtRef, WaitQueue, current};
+use memory_addr::{MemoryAddr, VirtAddr};
+
+use crate::{
+    ctypes::{
+        FUTEX_BITSET_MATCH_ANY, FUTEX_OWNER_DIED, FUTEX_TID_MASK, FUTEX_WAITERS, RobustListHead,
+    },
+    ptr::{PtrWrapper, UserConstPtr, UserPtr},
+    task::{get_task_by_id, wait_interruptible},
+};
+
+const FUTEX_HASH_SIZE: usize = 256;
+
+/// The maximum number of entries walked in a robust list, which guards
+/// against circular lists.
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+type FutexQueue = VecDeque<Arc<FutexWaiter>>;
+
+static FUTEX_TABLE: [Mutex<FutexQueue>; FUTEX_HASH_SIZE] =
+    [const { Mutex::new(VecDeque::new()) }; FUTEX_HASH_SIZE];
+
+/// Serializes the boosts of the owners of PI futexes by new waiters with the
+/// recomputations of their boosts. Taken before the bucket locks.
+static PI_LOCK: Mutex<()> = Mutex::new(());
+
+/// Identifies a futex word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexKey {
+    /// A futex private to an address space.
+    Private { aspace: usize, uaddr: usize },
+    /// A futex that may be shared between address spaces.
+    Shared { paddr: usize },
+}
+
+impl FutexKey {
+    /// Returns the key of the futex word at `uaddr` in the current address
+    /// space.
+    pub fn new(uaddr: usize, shared: bool) -> LinuxResult<Self> {
+        if uaddr % 4 != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        // Make sure the word is accessible, and its page present.
+        UserConstPtr::<u32>::from(uaddr).get()?;
+        let curr = current();
+        let aspace = curr.task_ext().aspace();
+        if !shared {
+            return Ok(Self::Private {
+                aspace: Arc::as_ptr(&aspace) as usize,
+                uaddr,
+            });
+        }
+        let vaddr = VirtAddr::from(uaddr);
+        let (paddr, _, _) = aspace
+            .lock()
+            .page_table()
+            .query(vaddr.align_down_4k())
+            .map_err(|_| LinuxError::EFAULT)?;
+        Ok(Self::Shared {
+            paddr: paddr.as_usize() + vaddr.align_offset_4k(),
+        })
+    }
+
+    fn bucket(&self) -> &'static Mutex<FutexQueue> {
+        let hash = match *self {
+            Self::Private { aspace, uaddr } => (uaddr >> 2) ^ (aspace >> 6),
+            Self::Shared { paddr } => paddr >> 2,
+        };
+        &FUTEX_TABLE[hash % FUTEX_HASH_SIZE]
+    }
+}
+
+struct FutexWaiter {
+    /// The futex waited on. It changes when the waiter is requeued, with the
+    /// locks of both buckets held.
+    key: spin::Mutex<FutexKey>,
+    bitset: u32,
+    /// The scheduling priority of the waiting thread, used by PI futexes.
+    prio: isize,
+    /// The TID of the owner of the PI futex waited on, which this waiter
+    /// boosts, or 0.
+    pi_owner: AtomicU32,
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl FutexWaiter {
+    fn new(key: FutexKey, bitset: u32) -> Self {
+        Self {
+            key: spin::Mutex::new(key),
+            bitset,
+            prio: current().task_ext().prio(),
+            pi_owner: AtomicU32::new(0),
+            woken: AtomicBool::new(false),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    fn key(&self) -> FutexKey {
+        *self.key.lock()
+    }
+
+    fn wake(&self) {
+        self.woken.store(true, Ordering::Release);
+        self.wq.notify_one(false);
+    }
+
+    /// Removes the waiter from its bucket. Returns `false` if it was already
+    /// removed by a waker.
+    fn unqueue(self: &Arc<Self>) -> bool {
+        loop {
+            let key = self.key();
+            let mut queue = key.bucket().lock();
+            if self.key() != key {
+                // Requeued in the meantime.
+                continue;
+            }
+            return match queue.iter().position(|w| Arc::ptr_eq(w, self)) {
+                Some(index) => {
+                    queue.remove(index);
+                    true
+                }
+                None => false,
+            };
+        }
+    }
+}
+
+/// Wakes up at most `nr` waiters on `key` whose bitset intersects `bitset`.
+fn wake_waiters(queue: &mut FutexQueue, key: FutexKey, nr: usize, bitset: u32) -> usize {
+    let mut woken = 0;
+    queue.retain(|waiter| {
+        if woken >= nr || waiter.key() != key || waiter.bitset & bitset == 0 {
+            return true;
+        }
+        waiter.wake();
+        woken += 1;
+        false
+    });
+    woken
+}
+
+/// Returns the index of the highest-priority waiter on `key`, the earliest
+/// queued among equals.
+fn top_waiter(queue: &FutexQueue, key: FutexKey) -> Option<usize> {
+    queue
+        .iter()
+        .enumerate()
+        .filter(|(_, waiter)| waiter.key() == key)
+        .min_by_key(|(_, waiter)| waiter.prio)
+        .map(|(index, _)| index)
+}
+
+/// Makes the waiters on the PI futex `key` boost the thread `owner`, or none
+/// if it is 0. Returns their highest priority, or `isize::MAX` if there are
+/// none.
+fn set_pi_owner(queue: &FutexQueue, key: FutexKey, owner: u32) -> isize {
+    queue
+        .iter()
+        .filter(|waiter| waiter.key() == key)
+        .inspect(|waiter| waiter.pi_owner.store(owner, Ordering::Release))
+        .map(|waiter| waiter.prio)
+        .min()
+        .unwrap_or(isize::MAX)
+}
+
+/// Recomputes the priority boost of the thread `tid` from the waiters on the
+/// PI futexes it still holds, after some of them stop waiting.
+fn update_pi_boost(tid: u32) {
+    if tid == 0 {
+        return;
+    }
+    let _pi = PI_LOCK.lock();
+    let Some(task) = get_task_by_id(tid as usize) else {
+        return;
+    };
+    let prio = FUTEX_TABLE
+        .iter()
+        .filter_map(|bucket| {
+            let queue = bucket.lock();
+            queue
+                .iter()
+                .filter(|waiter| waiter.pi_owner.load(Ordering::Acquire) == tid)
+                .map(|waiter| waiter.prio)
+                .min()
+        })
+        .min()
+        .unwrap_or(isize::MAX);
+    task.task_ext().set_priority_boost(&task, prio);
+}
+
+/// Locks the buckets of two futexes in a fixed order. The second guard is
+/// `None` if both futexes hash to the same bucket.
+fn lock_buckets(
+    key1: FutexKey,
+    key2: FutexKey,
+) -> (
+    MutexGuard<'static, FutexQueue>,
+    Option<MutexGuard<'static, FutexQueue>>,
+) {
+    let (bucket1, bucket2) = (key1.bucket(), key2.bucket());
+    if core::ptr::eq(bucket1, bucket2) {
+        (bucket1.lock(), None)
+    } else if (bucket1 as *const _ as usize) < (bucket2 as *const _ as usize) {
+        let guard1 = bucket1.lock();
+        (guard1, Some(bucket2.lock()))
+    } else {
+        let guard2 = bucket2.lock();
+        (bucket1.lock(), Some(guard2))
+    }
+}
+
+fn read_futex(uaddr: usize) -> LinuxResult<u32> {
+    let ptr = UserConstPtr::<u32>::from(uaddr).get()?;
+    // SAFETY: the pointer is checked, and the word may be modified
+    // concurrently by user space.
+    Ok(unsafe { AtomicU32::from_ptr(ptr as *mut u32) }.load(Ordering::SeqCst))
+}
+
+/// Returns the futex word at `uaddr` for atomic updates.
+fn user_word(uaddr: usize) -> LinuxResult<&'static AtomicU32> {
+    if uaddr % 4 != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let ptr = UserPtr::<u32>::from(uaddr).get()?;
+    // SAFETY: the pointer is checked, and the word may be modified
+    // concurrently by user space.
+    Ok(unsafe { AtomicU32::from_ptr(ptr) })
+}
+
+/// Sleeps on the futex `key` at `uaddr` if it still holds `val`, until woken
+/// up with a bitset intersecting `bitset`, interrupted by a signal, or the
+/// monotonic clock reaches `deadline`.
+pub fn futex_wait(
+    uaddr: usize,
+    key: FutexKey,
+    val: u32,
+    bitset: u32,
+    deadline: Option<TimeValue>,
+) -> LinuxResult<isize> {
+    let waiter = Arc::new(FutexWaiter::new(key, bitset));
+    {
+        // Compare with the bucket locked, so that a wake-up between the
+        // check and the enqueue cannot be missed.
+        let mut queue = key.bucket().lock();
+        if read_futex(uaddr)? != val {
+            return Err(LinuxError::EAGAIN);
+        }
+        queue.push_back(waiter.clone());
+    }
+    let result = wait_interruptible(&waiter.wq, deadline, || {
+        waiter.woken.load(Ordering::Acquire)
+    });
+    if result.is_err() && !waiter.unqueue() {
+        // A waker got to it concurrently.
+        return Ok(0);
+    }
+    result.map(|_| 0)
+}
+
+/// Wakes up at most `nr` waiters on the futex `key` whose bitset intersects
+/// `bitset`, and returns the number woken.
+pub fn futex_wake(key: FutexKey, nr: usize, bitset: u32) -> usize {
+    wake_waiters(&mut key.bucket().lock(), key, nr, bitset)
+}
+
+/// Wakes up at most `nr_wake` waiters on `key`, and moves at most
+/// `nr_requeue` of the others to `key2`. If `cmp` is given, the futex at
+/// `cmp.0` must still hold `cmp.1`.
+///
+/// Returns the number of waiters woken or requeued.
+pub fn futex_requeue(
+    key: FutexKey,
+    key2: FutexKey,
+    nr_wake: usize,
+    nr_requeue: usize,
+    cmp: Option<(usize, u32)>,
+) -> LinuxResult<isize> {
+    let (mut queue, mut queue2) = lock_buckets(key, key2);
+    if let Some((uaddr, val)) = cmp {
+        if read_futex(uaddr)? != val {
+            return Err(LinuxError::EAGAIN);
+        }
+    }
+    let woken = wake_waiters(&mut queue, key, nr_wake, FUTEX_BITSET_MATCH_ANY);
+    let mut requeued = 0;
+    let mut index = 0;
+    while index < queue.len() && requeued < nr_requeue {
+        if queue[index].key() != key {
+            index += 1;
+            continue;
+        }
+        *queue[index].key.lock() = key2;
+        requeued += 1;
+        match queue2.as_mut() {
+            Some(queue2) => queue2.push_back(queue.remove(index).unwrap()),
+            None => index += 1,
+        }
+    }
+    Ok((woken + requeued) as isize)
+}
+
+const FUTEX_OP_SET: u32 = 0;
+const FUTEX_OP_ADD: u32 = 1;
+const FUTEX_OP_OR: u32 = 2;
+const FUTEX_OP_ANDN: u32 = 3;
+const FUTEX_OP_XOR: u32 = 4;
+const FUTEX_OP_OPARG_SHIFT: u32 = 8;
+
+const FUTEX_OP_CMP_EQ: u32 = 0;
+const FUTEX_OP_CMP_NE: u32 = 1;
+const FUTEX_OP_CMP_LT: u32 = 2;
+const FUTEX_OP_CMP_LE: u32 = 3;
+const FUTEX_OP_CMP_GT: u32 = 4;
+const FUTEX_OP_CMP_GE: u32 = 5;
+
+/// Sign-extends the 12-bit field at `shift` of `encoded`.
+fn op_field(encoded: u32, shift: u32) -> i32 {
+    (((encoded >> shift) << 20) as i32) >> 20
+}
+
+/// Applies the operation encoded in `encoded` to the futex at `uaddr2`, wakes
+/// up at most `nr_wake` waiters on `key`, and if the old value at `uaddr2`
+/// passes the encoded comparison, at most `nr_wake2` waiters on `key2`.
+///
+/// Returns the total number of waiters woken.
+pub fn futex_wake_op(
+    key: FutexKey,
+    key2: FutexKey,
+    uaddr2: usize,
+    nr_wake: usize,
+    nr_wake2: usize,
+    encoded: u32,
+) -> LinuxResult<isize> {
+    let op = (encoded >> 28) & 0xf;
+    let cmp = (encoded >> 24) & 0xf;
+    let mut oparg = op_field(encoded, 12);
+    let cmparg = op_field(encoded, 0);
+    if op & FUTEX_OP_OPARG_SHIFT != 0 {
+        oparg = 1 << (oparg & 31);
+    }
+    let apply = |old: i32| -> Option<i32> {
+        match op & !FUTEX_OP_OPARG_SHIFT {
+            FUTEX_OP_SET => Some(oparg),
+            FUTEX_OP_ADD => Some(old.wrapping_add(oparg)),
+            FUTEX_OP_OR => Some(old | oparg),
+            FUTEX_OP_ANDN => Some(old & !oparg),
+            FUTEX_OP_XOR => Some(old ^ oparg),
+            _ => None,
+        }
+    };
+    let compare = |old: i32| -> Option<bool> {
+        match cmp {
+            FUTEX_OP_CMP_EQ => Some(old == cmparg),
+            FUTEX_OP_CMP_NE => Some(old != cmparg),
+            FUTEX_OP_CMP_LT => Some(old < cmparg),
+            FUTEX_OP_CMP_LE => Some(old <= cmparg),
+            FUTEX_OP_CMP_GT => Some(old > cmparg),
+            FUTEX_OP_CMP_GE => Some(old >= cmparg),
+            _ => None,
+        }
+    };
+    if apply(0).is_none() || compare(0).is_none() {
+        return Err(LinuxError::ENOSYS);
+    }
+
+    let ptr = UserPtr::<i32>::from(uaddr2).get()?;
+    let (mut queue, mut queue2) = lock_buckets(key, key2);
+    // SAFETY: the pointer is checked, and the word may be modified
+    // concurrently by user space.
+    let old = unsafe { AtomicI32::from_ptr(ptr) }
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, apply)
+        .unwrap();
+    let mut woken = wake_waiters(&mut queue, key, nr_wake, FUTEX_BITSET_MATCH_ANY);
+    if compare(old) == Some(true) {
+        let queue2 = match queue2.as_deref_mut() {
+            Some(queue2) => queue2,
+            None => &mut *queue,
+        };
+        woken += wake_waiters(queue2, key2, nr_wake2, FUTEX_BITSET_MATCH_ANY);
+    }
+    Ok(woken as isize)
+}
+
+/// Tries to take the PI futex `word` for `tid`, with the bucket of `key`
+/// locked. Returns the TID of the owner if it is held by another thread, in
+/// which case `FUTEX_WAITERS` is set if `set_waiters` is.
+fn try_lock_pi(
+    word: &AtomicU32,
+    queue: &FutexQueue,
+    key: FutexKey,
+    tid: u32,
+    set_waiters: bool,
+) -> LinuxResult<Option<u32>> {
+    let mut old = word.load(Ordering::SeqCst);
+    loop {
+        let owner = old & FUTEX_TID_MASK;
+        let new = if owner == 0 {
+            // Keep the waiters bit while others wait, so that the unlock goes
+            // through the kernel.
+            let waiters = if queue.iter().any(|waiter| waiter.key() == key) {
+                FUTEX_WAITERS
+            } else {
+                0
+            };
+            tid | (old & FUTEX_OWNER_DIED) | waiters
+        } else if owner == tid {
+            return Err(LinuxError::EDEADLK);
+        } else if !set_waiters || old & FUTEX_WAITERS != 0 {
+            return Ok(Some(owner));
+        } else {
+            old | FUTEX_WAITERS
+        };
+        match word.compare_exchange(old, new, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) if owner == 0 => return Ok(None),
+            Ok(_) => return Ok(Some(owner)),
+            Err(current) => old = current,
+        }
+    }
+}
+
+/// Takes the PI futex `key` at `uaddr`, sleeping until it is unlocked or the
+/// monotonic clock reaches `deadline`. With `try_only`, fails with `EAGAIN`
+/// instead of sleeping.
+///
+/// The owner is boosted to the priority of the caller while it sleeps, and
+/// the caller to that of the other waiters once it takes the futex.
+pub fn futex_lock_pi(
+    uaddr: usize,
+    key: FutexKey,
+    deadline: Option<TimeValue>,
+    try_only: bool,
+) -> LinuxResult<isize> {
+    let word = user_word(uaddr)?;
+    let curr = current();
+    let tid = curr.task_ext().tid() as u32;
+    loop {
+        let waiter = Arc::new(FutexWaiter::new(key, FUTEX_BITSET_MATCH_ANY));
+        {
+            let _pi = PI_LOCK.lock();
+            let mut queue = key.bucket().lock();
+            let owner = match try_lock_pi(word, &queue, key, tid, !try_only)? {
+                None => {
+                    // The other waiters boost the new owner.
+                    let prio = set_pi_owner(&queue, key, tid);
+                    curr.task_ext().boost_priority(curr.as_task_ref(), prio);
+                    return Ok(0);
+                }
+                Some(_) if try_only => return Err(LinuxError::EAGAIN),
+                Some(owner) => owner,
+            };
+            waiter.pi_owner.store(owner, Ordering::Release);
+            let owner = get_task_by_id(owner as usize).ok_or(LinuxError::ESRCH)?;
+            owner.task_ext().boost_priority(&owner, waiter.prio);
+            queue.push_back(waiter.clone());
+        }
+        // Like a mutex, the wait is not interrupted by signals, which are
+        // handled once the futex is taken. Only the exit of the whole thread
+        // group ends it.
+        let woken = || waiter.woken.load(Ordering::Acquire) || curr.is_interrupted();
+        let timed_out = match deadline {
+            Some(deadline) => waiter
+                .wq
+                .wait_timeout_until(deadline.saturating_sub(monotonic_time()), woken),
+            None => {
+                waiter.wq.wait_until(woken);
+                false
+            }
+        };
+        if timed_out && waiter.unqueue() {
+            update_pi_boost(waiter.pi_owner.load(Ordering::Acquire));
+            return Err(LinuxError::ETIMEDOUT);
+        }
+        if curr.is_interrupted() && waiter.unqueue() {
+            update_pi_boost(waiter.pi_owner.load(Ordering::Acquire));
+            return Err(LinuxError::EINTR);
+        }
+        // Woken up by an unlock: try again, as the futex may have been taken
+        // by another thread in the meantime.
+    }
+}
+
+/// Releases the PI futex `key` at `uaddr`, held by the caller, and wakes up
+/// its highest-priority waiter.
+pub fn futex_unlock_pi(uaddr: usize, key: FutexKey) -> LinuxResult<isize> {
+    let word = user_word(uaddr)?;
+    let curr = current();
+    let tid = curr.task_ext().tid() as u32;
+    {
+        let mut queue = key.bucket().lock();
+        let top = top_waiter(&queue, key);
+        // The woken waiter takes the futex through the kernel, since user
+        // space cannot take it while the waiters bit is set.
+        let new = if top.is_some() { FUTEX_WAITERS } else { 0 };
+        let mut old = word.load(Ordering::SeqCst);
+        loop {
+            if old & FUTEX_TID_MASK != tid {
+                return Err(LinuxError::EPERM);
+            }
+            match word.compare_exchange(old, new, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => break,
+                Err(current) => old = current,
+            }
+        }
+        // The waiters left boost the thread taking the futex next.
+        set_pi_owner(&queue, key, 0);
+        if let Some(index) = top {
+            queue.remove(index).unwrap().wake();
+        }
+    }
+    update_pi_boost(tid);
+    Ok(0)
+}
+
+/// Wakes up one waiter on the futex at `uaddr`, whether it waits on it as a
+/// private or a shared futex. For a PI futex, the highest-priority waiter is
+/// woken.
+pub fn futex_wake_addr(uaddr: usize, pi: bool) {
+    for shared in [false, true] {
+        let Ok(key) = FutexKey::new(uaddr, shared) else {
+            continue;
+        };
+        let mut queue = key.bucket().lock();
+        if !pi {
+            wake_waiters(&mut queue, key, 1, FUTEX_BITSET_MATCH_ANY);
+        } else if let Some(index) = top_waiter(&queue, key) {
+            set_pi_owner(&queue, key, 0);
+            queue.remove(index).unwrap().wake();
+        }
+    }
+}
+
+/// Marks the robust futex at `uaddr` with `FUTEX_OWNER_DIED` if it is held by
+/// the exiting thread `tid`, and wakes up one of its waiters.
+fn handle_futex_death(uaddr: usize, pi: bool, tid: u32) {
+    let Ok(word) = user_word(uaddr) else {
+        return;
+    };
+    let mut old = word.load(Ordering::SeqCst);
+    loop {
+        if old & FUTEX_TID_MASK != tid {
+            return;
+        }
+        let new = (old & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
+        match word.compare_exchange(old, new, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => break,
+            Err(current) => old = current,
+        }
+    }
+    if old & FUTEX_WAITERS != 0 {
+        futex_wake_addr(uaddr, pi);
+    }
+}
+
+/// Walks the robust list registered at `head` by the exiting thread `tid`,
+/// and releases the futexes it still holds.
+pub fn exit_robust_list(head: usize, tid: u32) {
+    let Ok(ptr) = UserConstPtr::<RobustListHead>::from(head).get() else {
+        return;
+    };
+    let head_val = unsafe { *ptr };
+    // The lowest bit of a list pointer marks a PI futex.
+    let futex_of = |entry: usize| (entry & !1).wrapping_add_signed(head_val.futex_offset);
+    let pending = head_val.list_op_pending;
+    let mut entry = head_val.list;
+    for _ in 0..ROBUST_LIST_LIMIT {
+        if entry & !1 == head {
+            break;
+        }
+        let Ok(next) = UserConstPtr::<usize>::from(entry & !1).get() else {
+            return;
+        };
+        let next = unsafe { *next };
+        // The pending entry is handled last, whether or not it is linked.
+        if entry & !1 != pending & !1 {
+            handle_futex_death(futex_of(entry), entry & 1 != 0, tid);
+        }
+        entry = next;
+    }
+    if pending & !1 != 0 {
+        handle_futex_death(futex_of(pending), pending & 1 != 0, tid);
+    }
+}
@@ -0,0 +1,198 @@
+//! System V and POSIX inter-process communication.
+
+pub mod mqueue;
+pub mod msg;
+pub mod sem;
+pub mod shm;
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use core::any::Any;
+
+use axerrno::{LinuxError, LinuxResult};
+use axfs::sysvipc::SysvIpcFileSystem;
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+
+use crate::{
+    ctypes::{IPC_CREAT, IPC_EXCL, IPC_PRIVATE, IpcPerm},
+    task::Credentials,
+};
+
+/// Read permission for everyone, as requested by the IPC syscalls.
+pub const S_IRUGO: u32 = 0o444;
+/// Write permission for everyone, as requested by the IPC syscalls.
+pub const S_IWUGO: u32 = 0o222;
+
+/// A file of `/proc/sysvipc`, listing the IPC objects of one kind.
+struct ProcListing(fn() -> String);
+
+impl VfsNodeOps for ProcListing {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        // The listing is generated when read, so its size is unknown.
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o444),
+            VfsNodeType::File,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let listing = (self.0)();
+        let start = listing.len().min(offset as usize);
+        let len = buf.len().min(listing.len() - start);
+        buf[..len].copy_from_slice(&listing.as_bytes()[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Adds the listings of the IPC objects to `/proc/sysvipc`.
+pub(crate) fn init_proc_sysvipc() {
+    let dir = SysvIpcFileSystem::get().root();
+    let listings: [(&str, fn() -> String); 3] = [
+        ("shm", shm::proc_listing),
+        ("sem", sem::proc_listing),
+        ("msg", msg::proc_listing),
+    ];
+    for (name, listing) in listings {
+        if let Err(e) = dir.add(name, Arc::new(ProcListing(listing))) {
+            warn!("failed to add /proc/sysvipc/{}: {:?}", name, e);
+        }
+    }
+}
+
+/// Returns the current time in seconds, for the IPC timestamps.
+pub(crate) fn ipc_time() -> isize {
+    axhal::time::wall_time().as_secs() as isize
+}
+
+/// Creates the permissions of a new IPC object, owned and created by `cred`.
+pub(crate) fn new_perm(key: i32, flags: i32, cred: &Credentials) -> IpcPerm {
+    IpcPerm {
+        key,
+        uid: cred.euid,
+        gid: cred.egid,
+        cuid: cred.euid,
+        cgid: cred.egid,
+        mode: flags as u32 & 0o777,
+        ..Default::default()
+    }
+}
+
+/// Checks that `cred` is granted the permissions requested by the `rwx` bits
+/// in `flags`.
+///
+/// The owner bits apply to the owner and the creator, and the group bits to
+/// their groups. The superuser is granted everything.
+pub(crate) fn check_access(perm: &IpcPerm, flags: u32, cred: &Credentials) -> LinuxResult {
+    let requested = (flags >> 6 | flags >> 3 | flags) & 0o7;
+    let granted = if cred.euid == perm.uid || cred.euid == perm.cuid {
+        perm.mode >> 6
+    } else if cred.egid == perm.gid || cred.egid == perm.cgid {
+        perm.mode >> 3
+    } else {
+        perm.mode
+    };
+    if requested & !granted & 0o7 != 0 && cred.euid != 0 {
+        return Err(LinuxError::EACCES);
+    }
+    Ok(())
+}
+
+/// Checks that `cred` may change or remove an IPC object, which requires
+/// being its owner, its creator or the superuser.
+pub(crate) fn check_owner(perm: &IpcPerm, cred: &Credentials) -> LinuxResult {
+    if cred.euid != 0 && cred.euid != perm.uid && cred.euid != perm.cuid {
+        return Err(LinuxError::EPERM);
+    }
+    Ok(())
+}
+
+/// Applies the owner and permissions given to `IPC_SET`.
+pub(crate) fn set_perm(perm: &mut IpcPerm, new: &IpcPerm) {
+    perm.uid = new.uid;
+    perm.gid = new.gid;
+    perm.mode = (perm.mode & !0o777) | (new.mode & 0o777);
+}
+
+/// The IPC objects of one kind, indexed by identifier and by key.
+pub(crate) struct IpcIds<T> {
+    objects: BTreeMap<i32, Arc<T>>,
+    keys: BTreeMap<i32, i32>,
+    next_id: i32,
+}
+
+impl<T> IpcIds<T> {
+    pub const fn new() -> Self {
+        Self {
+            objects: BTreeMap::new(),
+            keys: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Returns the object with the given identifier.
+    pub fn get(&self, id: i32) -> LinuxResult<Arc<T>> {
+        self.objects.get(&id).cloned().ok_or(LinuxError::EINVAL)
+    }
+
+    /// Looks up the object with the given key, following the `IPC_CREAT` and
+    /// `IPC_EXCL` flags.
+    ///
+    /// Returns `None` if a new object should be created.
+    pub fn lookup(&self, key: i32, flags: i32) -> LinuxResult<Option<Arc<T>>> {
+        if key == IPC_PRIVATE {
+            return Ok(None);
+        }
+        match self.keys.get(&key) {
+            Some(_) if flags & (IPC_CREAT | IPC_EXCL) == IPC_CREAT | IPC_EXCL => {
+                Err(LinuxError::EEXIST)
+            }
+            Some(id) => Ok(Some(self.objects[id].clone())),
+            None if flags & IPC_CREAT != 0 => Ok(None),
+            None => Err(LinuxError::ENOENT),
+        }
+    }
+
+    /// Adds an object built from its new identifier.
+    pub fn insert(&mut self, key: i32, new: impl FnOnce(i32) -> T) -> Arc<T> {
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(0);
+        let object = Arc::new(new(id));
+        self.objects.insert(id, object.clone());
+        if key != IPC_PRIVATE {
+            self.keys.insert(key, id);
+        }
+        object
+    }
+
+    /// Makes the object unreachable by its key, while it can still be found
+    /// by its identifier.
+    pub fn unlink_key(&mut self, id: i32) {
+        self.keys.retain(|_, object_id| *object_id != id);
+    }
+
+    /// Returns the objects in the order of their identifiers.
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<T>> {
+        self.objects.values()
+    }
+
+    /// Removes the object with the given identifier.
+    pub fn remove(&mut self, id: i32) -> Option<Arc<T>> {
+        self.unlink_key(id);
+        self.objects.remove(&id)
+    }
+}
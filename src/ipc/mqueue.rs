@@ -0,0 +1,478 @@
+//! POSIX message queues.
+//!
+//! The queues are the files of the mqueue filesystem mounted on
+//! `/dev/mqueue`, and their descriptors live in the file descriptor table.
+
+use alloc::{
+    collections::VecDeque,
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+
+use arceos_posix_api::{self as api, FileLike, ctypes::stat};
+use axerrno::{LinuxError, LinuxResult};
+use axfs::mqueue::{MqueueDir, MqueueFileSystem};
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axhal::time::TimeValue;
+use axio::PollState;
+use axsync::Mutex;
+use axtask::{TaskExtRef, WaitQueue, current};
+
+use crate::{
+    ctypes::{MQ_PRIO_MAX, MqAttr, SI_MESGQ, SIGEV_NONE, SIGEV_SIGNAL, SigEvent, SiginfoT},
+    task::{Credentials, get_task_by_id, wait_interruptible},
+};
+
+/// The default maximum number of messages in a queue, which is also the
+/// limit for unprivileged users.
+const DFLT_MSGMAX: usize = 10;
+/// The maximum number of messages in a queue.
+const HARD_MSGMAX: usize = 65536;
+/// The default maximum size of a message, which is also the limit for
+/// unprivileged users.
+const DFLT_MSGSIZEMAX: usize = 8192;
+/// The maximum size of a message.
+const HARD_MSGSIZEMAX: usize = 16 * 1024 * 1024;
+/// The maximum length of a queue name.
+const NAME_MAX: usize = 255;
+
+struct Message {
+    prio: u32,
+    data: Vec<u8>,
+}
+
+/// A registration made by `mq_notify`.
+struct Notification {
+    pid: usize,
+    event: SigEvent,
+}
+
+struct MqState {
+    /// The messages, by decreasing priority and then in the order sent
+    messages: VecDeque<Message>,
+    /// The total size of the messages
+    qsize: usize,
+    notification: Option<Notification>,
+    /// The number of threads blocked receiving
+    receivers: usize,
+}
+
+/// A POSIX message queue, as a file of the mqueue filesystem.
+pub struct MessageQueue {
+    this: Weak<MessageQueue>,
+    maxmsg: usize,
+    msgsize: usize,
+    uid: u32,
+    gid: u32,
+    mode: u32,
+    state: Mutex<MqState>,
+    /// Bumped whenever a message is sent or received.
+    generation: AtomicUsize,
+    /// Woken up on every bump of `generation`.
+    wq: WaitQueue,
+}
+
+impl MessageQueue {
+    fn new(maxmsg: usize, msgsize: usize, mode: u32, cred: &Credentials) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            maxmsg,
+            msgsize,
+            uid: cred.euid,
+            gid: cred.egid,
+            mode: mode & 0o777,
+            state: Mutex::new(MqState {
+                messages: VecDeque::new(),
+                qsize: 0,
+                notification: None,
+                receivers: 0,
+            }),
+            generation: AtomicUsize::new(0),
+            wq: WaitQueue::new(),
+        })
+    }
+
+    fn notify_all(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.wq.notify_all(false);
+    }
+
+    /// Checks that `cred` may open the queue for reading and/or writing.
+    fn check_access(&self, read: bool, write: bool, cred: &Credentials) -> LinuxResult {
+        let requested = (read as u32) << 2 | (write as u32) << 1;
+        let granted = if cred.euid == self.uid {
+            self.mode >> 6
+        } else if cred.egid == self.gid {
+            self.mode >> 3
+        } else {
+            self.mode
+        };
+        if requested & !granted & 0o7 != 0 && cred.euid != 0 {
+            return Err(LinuxError::EACCES);
+        }
+        Ok(())
+    }
+
+    /// Returns the status line read from the file of the queue.
+    fn status(&self) -> String {
+        let state = self.state.lock();
+        let (notify, signo, pid) = match &state.notification {
+            Some(n) => (n.event.sigev_notify, n.event.sigev_signo, n.pid),
+            None => (0, 0, 0),
+        };
+        format!(
+            "QSIZE:{:<10} NOTIFY:{:<5} SIGNO:{:<5} NOTIFY_PID:{:<6}\n",
+            state.qsize, notify, signo, pid
+        )
+    }
+}
+
+impl VfsNodeOps for MessageQueue {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(self.mode as u16),
+            VfsNodeType::File,
+            self.status().len() as u64,
+            0,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let status = self.status();
+        let start = status.len().min(offset as usize);
+        let len = buf.len().min(status.len() - start);
+        buf[..len].copy_from_slice(&status.as_bytes()[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A message queue descriptor, as opened by `mq_open`.
+pub struct MqueueFd {
+    queue: Arc<MessageQueue>,
+    readable: bool,
+    writable: bool,
+    nonblocking: AtomicBool,
+    /// The offset of `read` in the status line
+    pos: AtomicU64,
+    /// The process that opened the descriptor
+    owner: usize,
+}
+
+impl MqueueFd {
+    /// Gets the message queue descriptor by `fd`.
+    pub fn from_fd(fd: i32) -> LinuxResult<Arc<Self>> {
+        api::get_file_like(fd)?
+            .into_any()
+            .downcast::<Self>()
+            .map_err(|_| LinuxError::EBADF)
+    }
+
+    /// Sends a message with priority `prio`, blocking until the queue has
+    /// room or `deadline` passes.
+    pub fn send(&self, data: Vec<u8>, prio: u32, deadline: Option<TimeValue>) -> LinuxResult {
+        if !self.writable {
+            return Err(LinuxError::EBADF);
+        }
+        if data.len() > self.queue.msgsize {
+            return Err(LinuxError::EMSGSIZE);
+        }
+        if prio >= MQ_PRIO_MAX {
+            return Err(LinuxError::EINVAL);
+        }
+        let queue = &self.queue;
+        let mut state = loop {
+            let state = queue.state.lock();
+            if state.messages.len() < queue.maxmsg {
+                break state;
+            }
+            if self.nonblocking.load(Ordering::Acquire) {
+                return Err(LinuxError::EAGAIN);
+            }
+            let seen = queue.generation.load(Ordering::Acquire);
+            drop(state);
+            wait_interruptible(&queue.wq, deadline, || {
+                queue.generation.load(Ordering::Acquire) != seen
+            })?;
+        };
+
+        state.qsize += data.len();
+        let index = state.messages.partition_point(|msg| msg.prio >= prio);
+        state.messages.insert(index, Message { prio, data });
+        // The registered process is notified of a message arriving in an
+        // empty queue, unless a receiver takes it.
+        let notification = if state.messages.len() == 1 && state.receivers == 0 {
+            state.notification.take()
+        } else {
+            None
+        };
+        drop(state);
+        queue.notify_all();
+
+        let notification = notification.filter(|n| n.event.sigev_notify == SIGEV_SIGNAL);
+        if let Some(Notification { pid, event }) = notification {
+            let curr = current();
+            let process = &curr.task_ext().process;
+            let mut info = SiginfoT::new(event.sigev_signo as usize, SI_MESGQ);
+            info.set_sender(process.pid() as u32, process.cred().uid);
+            info.set_value(event.sigev_value);
+            if let Some(task) = get_task_by_id(pid) {
+                task.task_ext().send_signal_to_process(info);
+            }
+        }
+        Ok(())
+    }
+
+    /// Receives the oldest message of the highest priority into a buffer of
+    /// `size` bytes, blocking until there is one or `deadline` passes.
+    ///
+    /// Returns the message and its priority.
+    pub fn receive(&self, size: usize, deadline: Option<TimeValue>) -> LinuxResult<(Vec<u8>, u32)> {
+        if !self.readable {
+            return Err(LinuxError::EBADF);
+        }
+        if size < self.queue.msgsize {
+            return Err(LinuxError::EMSGSIZE);
+        }
+        let queue = &self.queue;
+        loop {
+            let mut state = queue.state.lock();
+            if let Some(msg) = state.messages.pop_front() {
+                state.qsize -= msg.data.len();
+                drop(state);
+                queue.notify_all();
+                return Ok((msg.data, msg.prio));
+            }
+            if self.nonblocking.load(Ordering::Acquire) {
+                return Err(LinuxError::EAGAIN);
+            }
+            let seen = queue.generation.load(Ordering::Acquire);
+            state.receivers += 1;
+            drop(state);
+            let result = wait_interruptible(&queue.wq, deadline, || {
+                queue.generation.load(Ordering::Acquire) != seen
+            });
+            queue.state.lock().receivers -= 1;
+            result?;
+        }
+    }
+
+    /// Registers the current process for a notification of a message
+    /// arriving in the empty queue, or removes its registration if `event`
+    /// is `None`.
+    pub fn set_notification(&self, event: Option<SigEvent>) -> LinuxResult {
+        let pid = current().task_ext().proc_id();
+        let mut state = self.queue.state.lock();
+        let Some(event) = event else {
+            if state.notification.as_ref().is_some_and(|n| n.pid == pid) {
+                state.notification = None;
+            }
+            return Ok(());
+        };
+        match event.sigev_notify {
+            SIGEV_NONE => {}
+            SIGEV_SIGNAL if (1..=64).contains(&event.sigev_signo) => {}
+            _ => return Err(LinuxError::EINVAL),
+        }
+        if state.notification.is_some() {
+            return Err(LinuxError::EBUSY);
+        }
+        state.notification = Some(Notification { pid, event });
+        Ok(())
+    }
+
+    /// Returns the attributes of the queue and the flags of the descriptor.
+    pub fn attr(&self) -> MqAttr {
+        let flags = if self.nonblocking.load(Ordering::Acquire) {
+            api::ctypes::O_NONBLOCK as isize
+        } else {
+            0
+        };
+        MqAttr {
+            mq_flags: flags,
+            mq_maxmsg: self.queue.maxmsg as isize,
+            mq_msgsize: self.queue.msgsize as isize,
+            mq_curmsgs: self.queue.state.lock().messages.len() as isize,
+            ..Default::default()
+        }
+    }
+}
+
+impl Drop for MqueueFd {
+    fn drop(&mut self) {
+        // Closing the descriptor removes the registration of the process
+        // that opened it. The descriptor may be dropped by any task, e.g.
+        // along with the fd table of an exited task, so `current()` is not
+        // the owner.
+        let mut state = self.queue.state.lock();
+        if state
+            .notification
+            .as_ref()
+            .is_some_and(|n| n.pid == self.owner)
+        {
+            state.notification = None;
+        }
+    }
+}
+
+impl FileLike for MqueueFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let pos = self.pos.load(Ordering::Acquire);
+        let len = self.queue.read_at(pos, buf)?;
+        self.pos.fetch_add(len as u64, Ordering::AcqRel);
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<stat> {
+        Ok(stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o100000 | self.queue.mode, // S_IFREG
+            st_uid: self.queue.uid,
+            st_gid: self.queue.gid,
+            st_blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let len = self.queue.state.lock().messages.len();
+        Ok(PollState {
+            readable: len > 0,
+            writable: len < self.queue.maxmsg,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::ESPIPE)
+    }
+}
+
+/// Returns the directory of the queues.
+fn mqueue_dir() -> Arc<MqueueDir> {
+    MqueueFileSystem::get().root()
+}
+
+/// Checks a queue name, given without the leading `/`.
+fn check_name(name: &str) -> LinuxResult {
+    if name.is_empty() {
+        return Err(LinuxError::ENOENT);
+    }
+    if name.len() > NAME_MAX {
+        return Err(LinuxError::ENAMETOOLONG);
+    }
+    if name.contains('/') || name == "." || name == ".." {
+        return Err(LinuxError::EACCES);
+    }
+    Ok(())
+}
+
+/// Opens the queue `name`, creating it with `O_CREAT` if it is missing,
+/// with the attributes `attr` or the defaults.
+pub fn mq_open(name: &str, flags: u32, mode: u32, attr: Option<MqAttr>) -> LinuxResult<MqueueFd> {
+    use api::ctypes::{O_CREAT, O_EXCL, O_NONBLOCK, O_RDONLY, O_RDWR, O_WRONLY};
+
+    check_name(name)?;
+    let (readable, writable) = match flags & 0b11 {
+        O_RDONLY => (true, false),
+        O_WRONLY => (false, true),
+        O_RDWR => (true, true),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let curr = current();
+    let cred = curr.task_ext().process.cred();
+    let dir = mqueue_dir();
+    let queue = loop {
+        if let Some(node) = dir.get(name) {
+            if flags & (O_CREAT | O_EXCL) == O_CREAT | O_EXCL {
+                return Err(LinuxError::EEXIST);
+            }
+            let queue = node.as_any().downcast_ref::<MessageQueue>().unwrap();
+            queue.check_access(readable, writable, &cred)?;
+            break queue.this.upgrade().unwrap();
+        }
+        if flags & O_CREAT == 0 {
+            return Err(LinuxError::ENOENT);
+        }
+        let (maxmsg, msgsize) = match attr {
+            Some(attr) => {
+                let (maxmsg, msgsize) = (attr.mq_maxmsg, attr.mq_msgsize);
+                if maxmsg <= 0 || msgsize <= 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                let (maxmsg, msgsize) = (maxmsg as usize, msgsize as usize);
+                if maxmsg > HARD_MSGMAX || msgsize > HARD_MSGSIZEMAX {
+                    return Err(LinuxError::EINVAL);
+                }
+                if (maxmsg > DFLT_MSGMAX || msgsize > DFLT_MSGSIZEMAX) && cred.euid != 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                (maxmsg, msgsize)
+            }
+            None => (DFLT_MSGMAX, DFLT_MSGSIZEMAX),
+        };
+        let queue = MessageQueue::new(maxmsg, msgsize, mode, &cred);
+        match dir.add(name, queue.clone()) {
+            Ok(()) => break queue,
+            // Created meanwhile, so open that one instead.
+            Err(VfsError::AlreadyExists) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    };
+    Ok(MqueueFd {
+        queue,
+        readable,
+        writable,
+        nonblocking: AtomicBool::new(flags & O_NONBLOCK != 0),
+        pos: AtomicU64::new(0),
+        owner: curr.task_ext().proc_id(),
+    })
+}
+
+/// Removes the queue `name`, which lives on until its last descriptor is
+/// closed.
+pub fn mq_unlink(name: &str) -> LinuxResult {
+    check_name(name)?;
+    let cred = current().task_ext().process.cred();
+    let dir = mqueue_dir();
+    let node = dir.get(name).ok_or(LinuxError::ENOENT)?;
+    let queue = node.as_any().downcast_ref::<MessageQueue>().unwrap();
+    // The directory is sticky: only the owner may remove a queue.
+    if cred.euid != 0 && cred.euid != queue.uid {
+        return Err(LinuxError::EACCES);
+    }
+    dir.remove(name)?;
+    Ok(())
+}
@@ -0,0 +1,274 @@
+//! System V message queues.
+
+use alloc::{collections::VecDeque, format, string::String, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+use axtask::{TaskExtRef, WaitQueue, current};
+
+use super::{IpcIds, S_IRUGO, S_IWUGO, check_access, check_owner, ipc_time, new_perm, set_perm};
+use crate::{
+    ctypes::{IPC_NOWAIT, IpcPerm, MSG_EXCEPT, MSG_NOERROR, MsqidDs},
+    task::wait_interruptible,
+};
+
+/// The maximum size of a message.
+pub const MSGMAX: usize = 8192;
+/// The default maximum number of bytes in a queue.
+const MSGMNB: usize = 16384;
+
+struct Message {
+    mtype: isize,
+    text: Vec<u8>,
+}
+
+struct MsgState {
+    perm: IpcPerm,
+    messages: VecDeque<Message>,
+    /// The total size of the queued messages
+    cbytes: usize,
+    qbytes: usize,
+    stime: isize,
+    rtime: isize,
+    ctime: isize,
+    lspid: i32,
+    lrpid: i32,
+}
+
+impl MsgState {
+    /// Finds the first message selected by `msgtyp`, following `MSG_EXCEPT`.
+    fn find(&self, msgtyp: isize, flags: i32) -> Option<usize> {
+        if msgtyp == 0 {
+            return (!self.messages.is_empty()).then_some(0);
+        }
+        if msgtyp < 0 {
+            // The lowest type not above the absolute value, first come first.
+            return self
+                .messages
+                .iter()
+                .enumerate()
+                .filter(|(_, msg)| msg.mtype <= -msgtyp)
+                .min_by_key(|(_, msg)| msg.mtype)
+                .map(|(i, _)| i);
+        }
+        let except = flags & MSG_EXCEPT != 0;
+        self.messages
+            .iter()
+            .position(|msg| (msg.mtype == msgtyp) != except)
+    }
+}
+
+/// A System V message queue.
+pub struct MsgQueue {
+    id: i32,
+    state: Mutex<MsgState>,
+    /// Bumped whenever a message is sent or received, or the queue changes.
+    generation: AtomicUsize,
+    removed: AtomicBool,
+    /// Woken up on every bump of `generation`.
+    wq: WaitQueue,
+}
+
+impl MsgQueue {
+    fn notify(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.wq.notify_all(false);
+    }
+
+    /// Blocks until the queue changes, failing with `EIDRM` once it is
+    /// removed.
+    fn wait_change(&self, seen: usize) -> LinuxResult {
+        wait_interruptible(&self.wq, None, || {
+            self.generation.load(Ordering::Acquire) != seen
+        })?;
+        if self.removed.load(Ordering::Acquire) {
+            return Err(LinuxError::EIDRM);
+        }
+        Ok(())
+    }
+}
+
+static MSG_IDS: Mutex<IpcIds<MsgQueue>> = Mutex::new(IpcIds::new());
+
+/// Returns the queue with the given key, creating it if needed, and returns
+/// its identifier.
+pub fn msgget(key: i32, flags: i32) -> LinuxResult<i32> {
+    let cred = current().task_ext().process.cred();
+    let mut ids = MSG_IDS.lock();
+    if let Some(queue) = ids.lookup(key, flags)? {
+        check_access(&queue.state.lock().perm, flags as u32 & 0o777, &cred)?;
+        return Ok(queue.id);
+    }
+    let queue = ids.insert(key, |id| MsgQueue {
+        id,
+        state: Mutex::new(MsgState {
+            perm: new_perm(key, flags, &cred),
+            messages: VecDeque::new(),
+            cbytes: 0,
+            qbytes: MSGMNB,
+            stime: 0,
+            rtime: 0,
+            ctime: ipc_time(),
+            lspid: 0,
+            lrpid: 0,
+        }),
+        generation: AtomicUsize::new(0),
+        removed: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    Ok(queue.id)
+}
+
+/// Sends a message to the queue `id`, blocking while the queue is full.
+pub fn msgsnd(id: i32, mtype: isize, text: Vec<u8>, flags: i32) -> LinuxResult {
+    if mtype < 1 || text.len() > MSGMAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current();
+    let process = &curr.task_ext().process;
+    let queue = MSG_IDS.lock().get(id)?;
+    check_access(&queue.state.lock().perm, S_IWUGO, &process.cred())?;
+    loop {
+        let mut state = queue.state.lock();
+        if queue.removed.load(Ordering::Acquire) {
+            return Err(LinuxError::EIDRM);
+        }
+        if state.cbytes + text.len() <= state.qbytes {
+            state.cbytes += text.len();
+            state.messages.push_back(Message { mtype, text });
+            state.lspid = process.pid() as i32;
+            state.stime = ipc_time();
+            drop(state);
+            queue.notify();
+            return Ok(());
+        }
+        if flags & IPC_NOWAIT != 0 {
+            return Err(LinuxError::EAGAIN);
+        }
+        let seen = queue.generation.load(Ordering::Acquire);
+        drop(state);
+        queue.wait_change(seen)?;
+    }
+}
+
+/// Receives a message selected by `msgtyp` from the queue `id`, blocking
+/// until there is one.
+///
+/// Returns the type and the text of the message, truncated to `size` bytes
+/// with `MSG_NOERROR`.
+pub fn msgrcv(id: i32, size: usize, msgtyp: isize, flags: i32) -> LinuxResult<(isize, Vec<u8>)> {
+    let curr = current();
+    let process = &curr.task_ext().process;
+    let queue = MSG_IDS.lock().get(id)?;
+    check_access(&queue.state.lock().perm, S_IRUGO, &process.cred())?;
+    loop {
+        let mut state = queue.state.lock();
+        if queue.removed.load(Ordering::Acquire) {
+            return Err(LinuxError::EIDRM);
+        }
+        if let Some(index) = state.find(msgtyp, flags) {
+            if state.messages[index].text.len() > size && flags & MSG_NOERROR == 0 {
+                return Err(LinuxError::E2BIG);
+            }
+            let mut msg = state.messages.remove(index).unwrap();
+            state.cbytes -= msg.text.len();
+            state.lrpid = process.pid() as i32;
+            state.rtime = ipc_time();
+            drop(state);
+            queue.notify();
+            msg.text.truncate(size);
+            return Ok((msg.mtype, msg.text));
+        }
+        if flags & IPC_NOWAIT != 0 {
+            return Err(LinuxError::ENOMSG);
+        }
+        let seen = queue.generation.load(Ordering::Acquire);
+        drop(state);
+        queue.wait_change(seen)?;
+    }
+}
+
+/// Returns the information of the queue `id` for `IPC_STAT`.
+pub fn msg_stat(id: i32) -> LinuxResult<MsqidDs> {
+    let cred = current().task_ext().process.cred();
+    let queue = MSG_IDS.lock().get(id)?;
+    let state = queue.state.lock();
+    check_access(&state.perm, S_IRUGO, &cred)?;
+    Ok(MsqidDs {
+        msg_perm: state.perm,
+        msg_stime: state.stime,
+        msg_rtime: state.rtime,
+        msg_ctime: state.ctime,
+        msg_cbytes: state.cbytes,
+        msg_qnum: state.messages.len(),
+        msg_qbytes: state.qbytes,
+        msg_lspid: state.lspid,
+        msg_lrpid: state.lrpid,
+        ..Default::default()
+    })
+}
+
+/// Changes the owner, permissions and size limit of the queue `id` for
+/// `IPC_SET`.
+///
+/// Only the superuser may raise the limit above the default.
+pub fn msg_set(id: i32, ds: &MsqidDs) -> LinuxResult {
+    let cred = current().task_ext().process.cred();
+    let queue = MSG_IDS.lock().get(id)?;
+    let mut state = queue.state.lock();
+    check_owner(&state.perm, &cred)?;
+    if ds.msg_qbytes > MSGMNB && ds.msg_qbytes > state.qbytes && cred.euid != 0 {
+        return Err(LinuxError::EPERM);
+    }
+    set_perm(&mut state.perm, &ds.msg_perm);
+    state.qbytes = ds.msg_qbytes;
+    state.ctime = ipc_time();
+    drop(state);
+    // Blocked senders may fit now.
+    queue.notify();
+    Ok(())
+}
+
+/// Removes the queue `id` at once for `IPC_RMID`, failing the blocked sends
+/// and receives with `EIDRM`.
+pub fn msg_remove(id: i32) -> LinuxResult {
+    let cred = current().task_ext().process.cred();
+    let mut ids = MSG_IDS.lock();
+    let queue = ids.get(id)?;
+    check_owner(&queue.state.lock().perm, &cred)?;
+    ids.remove(id);
+    drop(ids);
+    queue.removed.store(true, Ordering::Release);
+    queue.notify();
+    Ok(())
+}
+
+/// Returns the `/proc/sysvipc/msg` listing.
+pub fn proc_listing() -> String {
+    let mut listing = String::from(
+        "       key      msqid perms      cbytes       qnum lspid lrpid   uid   gid  cuid  cgid      stime      rtime      ctime\n",
+    );
+    for queue in MSG_IDS.lock().iter() {
+        let state = queue.state.lock();
+        let perm = &state.perm;
+        listing += &format!(
+            "{:10} {:10}  {:4o}  {:10} {:10} {:5} {:5} {:5} {:5} {:5} {:5} {:10} {:10} {:10}\n",
+            perm.key,
+            queue.id,
+            perm.mode,
+            state.cbytes,
+            state.messages.len(),
+            state.lspid,
+            state.lrpid,
+            perm.uid,
+            perm.gid,
+            perm.cuid,
+            perm.cgid,
+            state.stime,
+            state.rtime,
+            state.ctime,
+        );
+    }
+    listing
+}
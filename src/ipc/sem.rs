@@ -0,0 +1,379 @@
+//! System V semaphore sets.
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::TimeValue;
+use axsync::Mutex;
+use axtask::{TaskExtRef, WaitQueue, current};
+
+use super::{IpcIds, S_IRUGO, S_IWUGO, check_access, check_owner, ipc_time, new_perm, set_perm};
+use crate::{
+    ctypes::{IPC_NOWAIT, IpcPerm, SEM_UNDO, SemBuf, SemidDs},
+    task::wait_interruptible,
+};
+
+/// The maximum number of semaphores in a set.
+const SEMMSL: usize = 32000;
+/// The maximum number of operations in one `semop` call.
+const SEMOPM: usize = 500;
+/// The maximum value of a semaphore.
+const SEMVMX: i32 = 32767;
+
+#[derive(Clone, Copy, Default)]
+struct Sem {
+    val: i32,
+    /// The process of the last operation
+    pid: i32,
+    /// The number of processes waiting for the value to increase
+    ncnt: usize,
+    /// The number of processes waiting for the value to become zero
+    zcnt: usize,
+}
+
+struct SemState {
+    perm: IpcPerm,
+    otime: isize,
+    ctime: isize,
+    sems: Vec<Sem>,
+    /// The undo lists holding adjustments for this set
+    undo_lists: Vec<Weak<SemUndoList>>,
+}
+
+/// A System V semaphore set.
+pub struct SemSet {
+    id: i32,
+    nsems: usize,
+    state: Mutex<SemState>,
+    /// Bumped whenever the values change or the set is removed.
+    generation: AtomicUsize,
+    removed: AtomicBool,
+    /// Woken up on every bump of `generation`.
+    wq: WaitQueue,
+}
+
+impl SemSet {
+    fn notify(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.wq.notify_all(false);
+    }
+
+    /// Clears the undo adjustments of the semaphores in `range`, whose
+    /// values are set explicitly.
+    fn clear_undo(&self, state: &mut SemState, range: core::ops::Range<usize>) {
+        state.undo_lists.retain(|list| {
+            let Some(list) = list.upgrade() else {
+                return false;
+            };
+            if let Some(adjs) = list.0.lock().get_mut(&self.id) {
+                adjs[range.clone()].fill(0);
+            }
+            true
+        });
+    }
+}
+
+static SEM_IDS: Mutex<IpcIds<SemSet>> = Mutex::new(IpcIds::new());
+
+/// The adjustments undone at exit for the operations done with `SEM_UNDO`,
+/// keyed by semaphore set.
+///
+/// It is shared by the processes cloned with `CLONE_SYSVSEM`.
+#[derive(Default)]
+pub struct SemUndoList(Mutex<BTreeMap<i32, Vec<i32>>>);
+
+impl SemUndoList {
+    /// Undoes the adjustments once the last process sharing the list, `pid`,
+    /// exits.
+    pub fn apply(self, pid: i32) {
+        for (id, adjs) in self.0.into_inner() {
+            // The set may have been removed meanwhile.
+            let Ok(set) = SEM_IDS.lock().get(id) else {
+                continue;
+            };
+            let mut state = set.state.lock();
+            for (sem, adj) in state.sems.iter_mut().zip(adjs) {
+                if adj != 0 {
+                    sem.val = sem.val.saturating_add(adj).clamp(0, SEMVMX);
+                    sem.pid = pid;
+                }
+            }
+            drop(state);
+            set.notify();
+        }
+    }
+}
+
+/// Returns the set with the given key, creating it with `nsems` semaphores
+/// if needed, and returns its identifier.
+pub fn semget(key: i32, nsems: usize, flags: i32) -> LinuxResult<i32> {
+    let cred = current().task_ext().process.cred();
+    let mut ids = SEM_IDS.lock();
+    if let Some(set) = ids.lookup(key, flags)? {
+        check_access(&set.state.lock().perm, flags as u32 & 0o777, &cred)?;
+        if nsems > set.nsems {
+            return Err(LinuxError::EINVAL);
+        }
+        return Ok(set.id);
+    }
+    if nsems == 0 || nsems > SEMMSL {
+        return Err(LinuxError::EINVAL);
+    }
+    let set = ids.insert(key, |id| SemSet {
+        id,
+        nsems,
+        state: Mutex::new(SemState {
+            perm: new_perm(key, flags, &cred),
+            otime: 0,
+            ctime: ipc_time(),
+            sems: vec![Sem::default(); nsems],
+            undo_lists: Vec::new(),
+        }),
+        generation: AtomicUsize::new(0),
+        removed: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    Ok(set.id)
+}
+
+/// Applies the operations to the values if none of them blocks, and the ones
+/// with `SEM_UNDO` to the undo adjustments `adjs`.
+///
+/// Returns the index of the first blocking operation otherwise, leaving the
+/// values untouched. The adjustments are limited to ±`SEMVMX`, like the values.
+fn try_semop(sems: &mut [Sem], sops: &[SemBuf], adjs: &mut [i32]) -> LinuxResult<Option<usize>> {
+    let mut vals: Vec<i32> = sems.iter().map(|sem| sem.val).collect();
+    for (i, op) in sops.iter().enumerate() {
+        let val = &mut vals[op.sem_num as usize];
+        let new = *val + op.sem_op as i32;
+        if (op.sem_op == 0 && *val != 0) || new < 0 {
+            return Ok(Some(i));
+        }
+        if new > SEMVMX {
+            return Err(LinuxError::ERANGE);
+        }
+        *val = new;
+        if op.sem_flg & SEM_UNDO != 0 {
+            let adj = &mut adjs[op.sem_num as usize];
+            *adj -= op.sem_op as i32;
+            if !(-SEMVMX..=SEMVMX).contains(adj) {
+                return Err(LinuxError::ERANGE);
+            }
+        }
+    }
+    for (sem, val) in sems.iter_mut().zip(vals) {
+        sem.val = val;
+    }
+    Ok(None)
+}
+
+/// Performs the operations on the set `id` atomically, blocking until all of
+/// them can be done or `deadline` passes.
+pub fn semop(id: i32, sops: &[SemBuf], deadline: Option<TimeValue>) -> LinuxResult {
+    if sops.is_empty() {
+        return Err(LinuxError::EINVAL);
+    }
+    if sops.len() > SEMOPM {
+        return Err(LinuxError::E2BIG);
+    }
+    let curr = current();
+    let process = &curr.task_ext().process;
+    let pid = process.pid() as i32;
+    let set = SEM_IDS.lock().get(id)?;
+    if sops.iter().any(|op| op.sem_num as usize >= set.nsems) {
+        return Err(LinuxError::EFBIG);
+    }
+    let access = if sops.iter().any(|op| op.sem_op != 0) {
+        S_IWUGO
+    } else {
+        S_IRUGO
+    };
+    check_access(&set.state.lock().perm, access, &process.cred())?;
+    let undo_list = sops
+        .iter()
+        .any(|op| op.sem_flg & SEM_UNDO != 0)
+        .then(|| process.sem_undo.lock().clone());
+
+    loop {
+        let mut state = set.state.lock();
+        if set.removed.load(Ordering::Acquire) {
+            return Err(LinuxError::EIDRM);
+        }
+        let mut adjs = undo_list
+            .as_ref()
+            .and_then(|undo_list| undo_list.0.lock().get(&id).cloned())
+            .unwrap_or_else(|| vec![0; set.nsems]);
+        let Some(blocking) = try_semop(&mut state.sems, sops, &mut adjs)? else {
+            for op in sops {
+                state.sems[op.sem_num as usize].pid = pid;
+            }
+            state.otime = ipc_time();
+            if let Some(undo_list) = &undo_list {
+                let mut lists = undo_list.0.lock();
+                if !lists.contains_key(&id) {
+                    state.undo_lists.push(Arc::downgrade(undo_list));
+                }
+                lists.insert(id, adjs);
+            }
+            drop(state);
+            set.notify();
+            return Ok(());
+        };
+
+        let op = sops[blocking];
+        if op.sem_flg & IPC_NOWAIT as i16 != 0 {
+            return Err(LinuxError::EAGAIN);
+        }
+        let sem = op.sem_num as usize;
+        let waiting_zero = op.sem_op == 0;
+        if waiting_zero {
+            state.sems[sem].zcnt += 1;
+        } else {
+            state.sems[sem].ncnt += 1;
+        }
+        let seen = set.generation.load(Ordering::Acquire);
+        drop(state);
+
+        let result = wait_interruptible(&set.wq, deadline, || {
+            set.generation.load(Ordering::Acquire) != seen
+        });
+
+        let mut state = set.state.lock();
+        if waiting_zero {
+            state.sems[sem].zcnt -= 1;
+        } else {
+            state.sems[sem].ncnt -= 1;
+        }
+        drop(state);
+        match result {
+            Ok(()) => {}
+            Err(LinuxError::ETIMEDOUT) => return Err(LinuxError::EAGAIN),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Returns the information of the set `id` for `IPC_STAT`.
+pub fn sem_stat(id: i32) -> LinuxResult<SemidDs> {
+    let cred = current().task_ext().process.cred();
+    let set = SEM_IDS.lock().get(id)?;
+    let state = set.state.lock();
+    check_access(&state.perm, S_IRUGO, &cred)?;
+    Ok(SemidDs {
+        sem_perm: state.perm,
+        sem_otime: state.otime,
+        sem_ctime: state.ctime,
+        sem_nsems: set.nsems,
+        ..Default::default()
+    })
+}
+
+/// Changes the owner and permissions of the set `id` for `IPC_SET`.
+pub fn sem_set(id: i32, ds: &SemidDs) -> LinuxResult {
+    let cred = current().task_ext().process.cred();
+    let set = SEM_IDS.lock().get(id)?;
+    let mut state = set.state.lock();
+    check_owner(&state.perm, &cred)?;
+    set_perm(&mut state.perm, &ds.sem_perm);
+    state.ctime = ipc_time();
+    Ok(())
+}
+
+/// Removes the set `id` at once for `IPC_RMID`, failing the blocked
+/// operations with `EIDRM`.
+pub fn sem_remove(id: i32) -> LinuxResult {
+    let cred = current().task_ext().process.cred();
+    let mut ids = SEM_IDS.lock();
+    let set = ids.get(id)?;
+    check_owner(&set.state.lock().perm, &cred)?;
+    ids.remove(id);
+    drop(ids);
+    set.removed.store(true, Ordering::Release);
+    set.notify();
+    Ok(())
+}
+
+/// Returns the number of semaphores in the set `id`.
+pub fn sem_count(id: i32) -> LinuxResult<usize> {
+    Ok(SEM_IDS.lock().get(id)?.nsems)
+}
+
+/// Returns the value, the last process, and the numbers of processes waiting
+/// for an increase and for zero of the semaphore `num` in the set `id`.
+pub fn sem_info(id: i32, num: usize) -> LinuxResult<(i32, i32, usize, usize)> {
+    let cred = current().task_ext().process.cred();
+    let set = SEM_IDS.lock().get(id)?;
+    let state = set.state.lock();
+    check_access(&state.perm, S_IRUGO, &cred)?;
+    let sem = state.sems.get(num).ok_or(LinuxError::EINVAL)?;
+    Ok((sem.val, sem.pid, sem.ncnt, sem.zcnt))
+}
+
+/// Returns the values of all the semaphores in the set `id`.
+pub fn sem_get_all(id: i32) -> LinuxResult<Vec<u16>> {
+    let cred = current().task_ext().process.cred();
+    let set = SEM_IDS.lock().get(id)?;
+    let state = set.state.lock();
+    check_access(&state.perm, S_IRUGO, &cred)?;
+    Ok(state.sems.iter().map(|sem| sem.val as u16).collect())
+}
+
+/// Sets the values of the semaphores from `first` in the set `id`, clearing
+/// their undo adjustments.
+pub fn sem_set_values(id: i32, first: usize, vals: &[i32]) -> LinuxResult {
+    let curr = current();
+    let process = &curr.task_ext().process;
+    let set = SEM_IDS.lock().get(id)?;
+    let mut state = set.state.lock();
+    check_access(&state.perm, S_IWUGO, &process.cred())?;
+    let end = first
+        .checked_add(vals.len())
+        .filter(|&end| end <= set.nsems)
+        .ok_or(LinuxError::EINVAL)?;
+    let range = first..end;
+    if vals.iter().any(|val| !(0..=SEMVMX).contains(val)) {
+        return Err(LinuxError::ERANGE);
+    }
+    for (sem, &val) in state.sems[range.clone()].iter_mut().zip(vals) {
+        sem.val = val;
+        sem.pid = process.pid() as i32;
+    }
+    set.clear_undo(&mut state, range);
+    state.ctime = ipc_time();
+    drop(state);
+    set.notify();
+    Ok(())
+}
+
+/// Returns the `/proc/sysvipc/sem` listing.
+pub fn proc_listing() -> String {
+    let mut listing = String::from(
+        "       key      semid perms      nsems   uid   gid  cuid  cgid      otime      ctime\n",
+    );
+    for set in SEM_IDS.lock().iter() {
+        let state = set.state.lock();
+        let perm = &state.perm;
+        listing += &format!(
+            "{:10} {:10}  {:4o} {:10} {:5} {:5} {:5} {:5} {:10} {:10}\n",
+            perm.key,
+            set.id,
+            perm.mode,
+            set.nsems,
+            perm.uid,
+            perm.gid,
+            perm.cuid,
+            perm.cgid,
+            state.otime,
+            state.ctime,
+        );
+    }
+    listing
+}
@@ -0,0 +1,295 @@
+//! System V shared memory segments.
+
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
+use axmm::SharedMemory;
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use memory_addr::{PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
+
+use super::{IpcIds, S_IRUGO, S_IWUGO, check_access, check_owner, ipc_time, new_perm, set_perm};
+use crate::ctypes::{
+    IPC_PRIVATE, IpcPerm, SHM_DEST, SHM_EXEC, SHM_RDONLY, SHM_REMAP, SHM_RND, ShmidDs,
+};
+
+/// The alignment of the attach addresses, rounded down to with `SHM_RND`.
+const SHMLBA: usize = PAGE_SIZE_4K;
+
+struct ShmState {
+    perm: IpcPerm,
+    atime: isize,
+    dtime: isize,
+    ctime: isize,
+    lpid: i32,
+    nattch: usize,
+    /// Removed by `IPC_RMID`, and destroyed at the last detach.
+    removed: bool,
+}
+
+/// A System V shared memory segment.
+pub struct ShmSegment {
+    id: i32,
+    size: usize,
+    cpid: i32,
+    memory: Arc<SharedMemory>,
+    state: Mutex<ShmState>,
+}
+
+impl ShmSegment {
+    /// Records an attach or a detach by the process `pid`.
+    ///
+    /// The segment is destroyed once it is removed and no longer attached.
+    fn update_nattch(&self, attach: bool, pid: i32) {
+        let mut state = self.state.lock();
+        state.lpid = pid;
+        if attach {
+            state.nattch += 1;
+            state.atime = ipc_time();
+            return;
+        }
+        state.nattch -= 1;
+        state.dtime = ipc_time();
+        let destroy = state.removed && state.nattch == 0;
+        drop(state);
+        if destroy {
+            SHM_IDS.lock().remove(self.id);
+        }
+    }
+}
+
+static SHM_IDS: Mutex<IpcIds<ShmSegment>> = Mutex::new(IpcIds::new());
+
+/// The shared memory segments attached by a process, keyed by their
+/// attach addresses.
+#[derive(Default)]
+pub struct ShmAttachments(BTreeMap<VirtAddr, Arc<ShmSegment>>);
+
+impl ShmAttachments {
+    /// Copies the attachments for a child process `pid`, whose copy of the
+    /// address space maps the same segments.
+    pub fn fork(&self, pid: i32) -> Self {
+        for segment in self.0.values() {
+            segment.update_nattch(true, pid);
+        }
+        Self(self.0.clone())
+    }
+
+    /// Detaches all the segments when the process `pid` exits or executes a
+    /// new program. The mappings go away with the address space.
+    pub fn detach_all(&mut self, pid: i32) {
+        for segment in core::mem::take(&mut self.0).into_values() {
+            segment.update_nattch(false, pid);
+        }
+    }
+}
+
+/// Returns the segment with the given key, creating it if needed, and
+/// returns its identifier.
+pub fn shmget(key: i32, size: usize, flags: i32) -> LinuxResult<i32> {
+    let cred = current().task_ext().process.cred();
+    let mut ids = SHM_IDS.lock();
+    if let Some(segment) = ids.lookup(key, flags)? {
+        check_access(&segment.state.lock().perm, flags as u32 & 0o777, &cred)?;
+        if size > segment.size {
+            return Err(LinuxError::EINVAL);
+        }
+        return Ok(segment.id);
+    }
+    if size == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let now = ipc_time();
+    let segment = ids.insert(key, |id| ShmSegment {
+        id,
+        size,
+        cpid: current().task_ext().proc_id() as i32,
+        memory: Arc::new(SharedMemory::new(size)),
+        state: Mutex::new(ShmState {
+            perm: new_perm(key, flags, &cred),
+            atime: 0,
+            dtime: 0,
+            ctime: now,
+            lpid: 0,
+            nattch: 0,
+            removed: false,
+        }),
+    });
+    Ok(segment.id)
+}
+
+/// Attaches the segment `id` to the address space of the current process,
+/// at `addr` or at an address chosen by the kernel if it is null.
+///
+/// Returns the attach address.
+pub fn shmat(id: i32, addr: usize, flags: i32) -> LinuxResult<usize> {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let segment = SHM_IDS.lock().get(id)?;
+    let mut access = S_IRUGO;
+    let mut map_flags = MappingFlags::USER | MappingFlags::READ;
+    if flags & SHM_RDONLY == 0 {
+        access |= S_IWUGO;
+        map_flags |= MappingFlags::WRITE;
+    }
+    if flags & SHM_EXEC != 0 {
+        map_flags |= MappingFlags::EXECUTE;
+    }
+    check_access(&segment.state.lock().perm, access, &task_ext.process.cred())?;
+
+    let size = segment.memory.size();
+    let aspace = task_ext.aspace();
+    let mut aspace = aspace.lock();
+    let start = if addr == 0 {
+        aspace
+            .find_free_area(
+                aspace.base(),
+                size,
+                VirtAddrRange::new(aspace.base(), aspace.end()),
+            )
+            .ok_or(LinuxError::ENOMEM)?
+    } else {
+        let addr = if flags & SHM_RND != 0 {
+            memory_addr::align_down(addr, SHMLBA)
+        } else if !memory_addr::is_aligned(addr, SHMLBA) {
+            return Err(LinuxError::EINVAL);
+        } else {
+            addr
+        };
+        let start = VirtAddr::from(addr);
+        if !aspace.contains_range(start, size) {
+            return Err(LinuxError::EINVAL);
+        }
+        if flags & SHM_REMAP != 0 {
+            aspace.unmap(start, size)?;
+        } else if aspace.find_free_area(start, size, VirtAddrRange::from_start_size(start, size))
+            != Some(start)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        start
+    };
+    aspace.map_shared(start, size, map_flags, segment.memory.clone())?;
+    drop(aspace);
+
+    segment.update_nattch(true, task_ext.proc_id() as i32);
+    task_ext
+        .process
+        .shm_attachments
+        .lock()
+        .0
+        .insert(start, segment);
+    Ok(start.as_usize())
+}
+
+/// Detaches the segment attached at `addr` from the current process.
+pub fn shmdt(addr: usize) -> LinuxResult {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let start = VirtAddr::from(addr);
+    let segment = task_ext
+        .process
+        .shm_attachments
+        .lock()
+        .0
+        .remove(&start)
+        .ok_or(LinuxError::EINVAL)?;
+    task_ext
+        .aspace()
+        .lock()
+        .unmap(start, segment.memory.size())?;
+    axhal::arch::flush_tlb(None);
+    segment.update_nattch(false, task_ext.proc_id() as i32);
+    Ok(())
+}
+
+/// Returns the information of the segment `id` for `IPC_STAT`.
+pub fn shm_stat(id: i32) -> LinuxResult<ShmidDs> {
+    let cred = current().task_ext().process.cred();
+    let segment = SHM_IDS.lock().get(id)?;
+    let state = segment.state.lock();
+    check_access(&state.perm, S_IRUGO, &cred)?;
+    let mut perm = state.perm;
+    if state.removed {
+        perm.mode |= SHM_DEST;
+    }
+    Ok(ShmidDs {
+        shm_perm: perm,
+        shm_segsz: segment.size,
+        shm_atime: state.atime,
+        shm_dtime: state.dtime,
+        shm_ctime: state.ctime,
+        shm_cpid: segment.cpid,
+        shm_lpid: state.lpid,
+        shm_nattch: state.nattch,
+        ..Default::default()
+    })
+}
+
+/// Changes the owner and permissions of the segment `id` for `IPC_SET`.
+pub fn shm_set(id: i32, ds: &ShmidDs) -> LinuxResult {
+    let cred = current().task_ext().process.cred();
+    let segment = SHM_IDS.lock().get(id)?;
+    let mut state = segment.state.lock();
+    check_owner(&state.perm, &cred)?;
+    set_perm(&mut state.perm, &ds.shm_perm);
+    state.ctime = ipc_time();
+    Ok(())
+}
+
+/// Removes the segment `id` for `IPC_RMID`.
+///
+/// The key is released at once, while the segment is destroyed after the
+/// last detach.
+pub fn shm_remove(id: i32) -> LinuxResult {
+    let cred = current().task_ext().process.cred();
+    let mut ids = SHM_IDS.lock();
+    let segment = ids.get(id)?;
+    let mut state = segment.state.lock();
+    check_owner(&state.perm, &cred)?;
+    state.removed = true;
+    state.perm.key = IPC_PRIVATE;
+    if state.nattch == 0 {
+        ids.remove(id);
+    } else {
+        ids.unlink_key(id);
+    }
+    Ok(())
+}
+
+/// Returns the `/proc/sysvipc/shm` listing.
+pub fn proc_listing() -> String {
+    let mut listing = String::from(
+        "       key      shmid perms                  size  cpid  lpid nattch   uid   gid  cuid  cgid      atime      dtime      ctime                   rss                  swap\n",
+    );
+    for segment in SHM_IDS.lock().iter() {
+        let state = segment.state.lock();
+        let perm = &state.perm;
+        let mode = if state.removed {
+            perm.mode | SHM_DEST
+        } else {
+            perm.mode
+        };
+        listing += &format!(
+            "{:10} {:10}  {:4o} {:21} {:5} {:5}  {:5} {:5} {:5} {:5} {:5} {:10} {:10} {:10} {:21} {:21}\n",
+            perm.key,
+            segment.id,
+            mode,
+            segment.size,
+            segment.cpid,
+            state.lpid,
+            state.nattch,
+            perm.uid,
+            perm.gid,
+            perm.cuid,
+            perm.cgid,
+            state.atime,
+            state.dtime,
+            state.ctime,
+            segment.memory.size(),
+            0,
+        );
+    }
+    listing
+}
@@ -7,9 +7,12 @@ extern crate log;
 extern crate alloc;
 use axstd::println;
 mod ctypes;
+mod futex;
+mod ipc;
 
 mod mm;
 mod ptr;
+mod signal;
 mod syscall_imp;
 mod task;
 
@@ -23,7 +26,7 @@ use axhal::arch::UspaceContext;
 use axmm::{AddrSpace, kernel_aspace};
 use axsync::Mutex;
 use hashbrown::HashMap;
-use memory_addr::VirtAddr;
+use memory_addr::{VirtAddr, VirtAddrRange};
 use task::TASK_ALL;
 
 fn new_user_aspace_empty() -> AxResult<AddrSpace> {
@@ -45,6 +48,63 @@ fn copy_from_kernel(aspace: &mut AddrSpace) -> AxResult {
     Ok(())
 }
 
+/// Unmaps the kernel portion copied by [`copy_from_kernel`], so that dropping
+/// the address space does not free the page tables it shares with the kernel
+/// address space.
+fn clear_kernel_mappings(aspace: &mut AddrSpace) {
+    if !cfg!(target_arch = "aarch64") && !cfg!(target_arch = "loongarch64") {
+        let kernel = kernel_aspace().lock();
+        aspace.clear_mappings(VirtAddrRange::from_start_size(kernel.base(), kernel.size()));
+    }
+}
+
+/// A reference to a user address space, whose kernel portion is cleared when
+/// the last reference is dropped through it.
+///
+/// It guards the address spaces between [`copy_from_kernel`] and their handover
+/// to a task, and the ones a task stops using.
+struct UserAspace(Option<Arc<Mutex<AddrSpace>>>);
+
+impl UserAspace {
+    /// Creates an empty user address space, with the kernel portion copied in.
+    fn new() -> AxResult<Self> {
+        let mut aspace = new_user_aspace_empty()?;
+        copy_from_kernel(&mut aspace)?;
+        Ok(Self::from(aspace))
+    }
+
+    /// Returns the guarded address space.
+    fn get(&self) -> &Arc<Mutex<AddrSpace>> {
+        self.0.as_ref().unwrap()
+    }
+
+    /// Hands the address space over, without clearing it.
+    fn into_inner(mut self) -> Arc<Mutex<AddrSpace>> {
+        self.0.take().unwrap()
+    }
+}
+
+impl From<AddrSpace> for UserAspace {
+    fn from(aspace: AddrSpace) -> Self {
+        Self(Some(Arc::new(Mutex::new(aspace))))
+    }
+}
+
+impl From<Arc<Mutex<AddrSpace>>> for UserAspace {
+    fn from(aspace: Arc<Mutex<AddrSpace>>) -> Self {
+        Self(Some(aspace))
+    }
+}
+
+impl Drop for UserAspace {
+    fn drop(&mut self) {
+        // Other threads may still use the address space.
+        if let Some(aspace) = self.0.take().filter(|it| Arc::strong_count(it) == 1) {
+            clear_kernel_mappings(&mut aspace.lock());
+        }
+    }
+}
+
 fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
     let mut uspace = new_user_aspace_empty()
         .and_then(|mut it| {
@@ -55,7 +115,7 @@ fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
 
     let path = arceos_posix_api::FilePath::new(&args[0]).expect("Invalid file path");
     axfs::api::set_current_dir(path.parent().unwrap()).expect("Failed to set current dir");
-    let (entry_vaddr, ustack_top) = mm::load_user_app(&mut uspace, args, envs)
+    let (entry_vaddr, ustack_top) = mm::load_user_app(&mut uspace, &args[0], args, envs)
         .unwrap_or_else(|e| panic!("Failed to load user app: {}", e));
     let user_task = task::spawn_user_task(
         Arc::new(Mutex::new(uspace)),
@@ -68,6 +128,9 @@ fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
 #[unsafe(no_mangle)]
 fn main() {
     TASK_ALL.init_once(Mutex::new(HashMap::new()));
+    mm::init_page_caches();
+    ipc::init_proc_sysvipc();
+    task::spawn_init_task();
     println!("#### OS COMP TEST GROUP START basic-glibc ####");
     println!("#### OS COMP TEST GROUP START basic-musl ####");
     let testcases = option_env!("AX_TESTCASES_LIST")
@@ -93,4 +156,10 @@ fn main() {
             println!("#### OS COMP TEST GROUP END libctest-glibc ####");
         }
     }
+    let stats = axmm::page_fault_stats();
+    info!(
+        "Page faults: {:?}, {} page copies saved by copy-on-write",
+        stats,
+        stats.copies_saved()
+    );
 }
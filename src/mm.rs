@@ -1,28 +1,110 @@
 use core::ffi::CStr;
 
-use alloc::{string::String, vec};
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
 
-use axerrno::{AxError, AxResult};
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
 use axhal::{
     paging::MappingFlags,
     trap::{PAGE_FAULT, register_trap_handler},
 };
 
-use axmm::AddrSpace;
+use axfs::fops::{File, OpenOptions};
+use axmm::{AddrSpace, FileIo, PageCache};
+use axstd::io::Read;
+use axsync::Mutex;
 use axtask::TaskExtRef;
 use kernel_elf_parser::{AuxvEntry, ELFParser, app_stack_region};
-use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PhysAddr, VirtAddr, VirtAddrRange};
 use xmas_elf::{ElfFile, program::SegmentData};
 
+use crate::{
+    ctypes::{BUS_ADRERR, SEGV_ACCERR, SEGV_MAPERR},
+    signal::{SIGBUS, SIGSEGV, force_signal},
+};
+
+/// Reads `len` bytes at `offset` in `file`, or less at the end of the file.
+fn read_file_at(file: &File, offset: u64, len: usize) -> AxResult<Vec<u8>> {
+    let mut data = vec![0; len];
+    let mut read = 0;
+    while read < len {
+        match file.read_at(offset + read as u64, &mut data[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    data.truncate(read);
+    Ok(data)
+}
+
+/// Reads the start of the ELF file, up to the end of its program headers and
+/// of the path of its interpreter.
+///
+/// Fails with `InvalidData` if they lie past the end of the file.
+fn read_elf_headers(file: &File) -> AxResult<Vec<u8>> {
+    let size = file.get_attr()?.size();
+    let mut data = read_file_at(file, 0, PAGE_SIZE_4K)?;
+    loop {
+        let elf = ElfFile::new(&data).map_err(|_| AxError::InvalidData)?;
+        let pt2 = &elf.header.pt2;
+        let mut end = (pt2.ph_count() as u64)
+            .checked_mul(pt2.ph_entry_size() as u64)
+            .and_then(|len| pt2.ph_offset().checked_add(len))
+            .ok_or(AxError::InvalidData)?;
+        // The program headers are only read once they are all there.
+        let interp = (end <= data.len() as u64)
+            .then(|| {
+                elf.program_iter()
+                    .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Interp))
+            })
+            .flatten();
+        if let Some(interp) = interp {
+            let interp_end = interp
+                .offset()
+                .checked_add(interp.file_size())
+                .ok_or(AxError::InvalidData)?;
+            end = end.max(interp_end);
+        }
+        if end > size {
+            return Err(AxError::InvalidData);
+        }
+        if end <= data.len() as u64 {
+            return Ok(data);
+        }
+        let len = data.len();
+        data = read_file_at(file, 0, end as usize)?;
+        if data.len() <= len {
+            return Err(AxError::InvalidData);
+        }
+    }
+}
+
 /// Map the elf file to the user address space.
 ///
+/// The pages of the segments are faulted in on demand from the page cache of
+/// the file, and copied when written. The page holding the end of the file
+/// data of a segment is copied at once, since the rest of it is zeroed, and
+/// the `.bss` pages past it are allocated on demand.
+///
 /// # Arguments
 /// - `uspace`: The address space of the user app.
-/// - `elf`: The elf file.
+/// - `elf`: The headers of the elf file.
+/// - `file`: The elf file.
+/// - `cache`: The page cache of the elf file.
 ///
 /// # Returns
 /// - The entry point of the user app.
-fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEntry; 16])> {
+fn map_elf(
+    uspace: &mut AddrSpace,
+    elf: &ElfFile,
+    file: &File,
+    cache: Arc<PageCache>,
+) -> AxResult<(VirtAddr, [AuxvEntry; 16])> {
     let uspace_base = uspace.base().as_usize();
     let elf_parser = ELFParser::new(
         elf,
@@ -40,21 +122,43 @@ fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEn
             segement.flags
         );
         let seg_pad = segement.vaddr.align_offset_4k();
-        assert_eq!(seg_pad, segement.offset % PAGE_SIZE_4K);
-
-        let seg_align_size =
-            (segement.memsz as usize + seg_pad + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
-        uspace.map_alloc(
-            segement.vaddr.align_down_4k(),
-            seg_align_size,
-            segement.flags,
-            true,
-        )?;
-        let seg_data = elf
-            .input
-            .get(segement.offset..segement.offset + segement.filesz as usize)
-            .ok_or(AxError::InvalidData)?;
-        uspace.write(segement.vaddr, seg_data)?;
+        if seg_pad != segement.offset % PAGE_SIZE_4K || segement.filesz > segement.memsz {
+            return Err(AxError::InvalidData);
+        }
+        let seg_start = segement.vaddr.align_down_4k();
+        let seg_offset = (segement.offset - seg_pad) as u64;
+        let data_end = segement.vaddr + segement.filesz as usize;
+        let seg_end = (segement.vaddr + segement.memsz as usize).align_up_4k();
+
+        // The whole pages of file data.
+        let file_end = data_end.align_down_4k();
+        if file_end > seg_start {
+            uspace.map_file(
+                seg_start,
+                file_end - seg_start,
+                segement.flags,
+                cache.clone(),
+                seg_offset,
+                false,
+                true,
+            )?;
+        }
+        // The page holding the end of the file data, zeroed past it.
+        let mut bss_start = file_end.max(seg_start);
+        if data_end > bss_start {
+            uspace.map_alloc(bss_start, PAGE_SIZE_4K, segement.flags, true)?;
+            let offset = seg_offset + (bss_start - seg_start) as u64;
+            let data = read_file_at(file, offset, data_end - bss_start)?;
+            if data.len() < data_end - bss_start {
+                return Err(AxError::InvalidData);
+            }
+            uspace.write(bss_start, &data)?;
+            bss_start += PAGE_SIZE_4K;
+        }
+        // The rest of `.bss`.
+        if seg_end > bss_start {
+            uspace.map_alloc(bss_start, seg_end - bss_start, segement.flags, false)?;
+        }
         // TDOO: flush the I-cache
     }
 
@@ -64,11 +168,107 @@ fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEn
     ))
 }
 
+/// The maximum number of nested `#!` interpreters, as in Linux.
+const MAX_SCRIPT_DEPTH: usize = 4;
+/// The number of bytes looked at for the `#!` line, as in Linux.
+const BINPRM_BUF_SIZE: usize = 256;
+/// The maximum length of an argument or environment string, as in Linux.
+const MAX_ARG_STRLEN: usize = 32 * PAGE_SIZE_4K;
+
+/// Checks that `path` names a regular file with an execute permission bit.
+fn check_executable(path: &str) -> LinuxResult {
+    let metadata = axfs::api::metadata(path)?;
+    if !metadata.is_file() || metadata.permissions().bits() & 0o111 == 0 {
+        return Err(LinuxError::EACCES);
+    }
+    Ok(())
+}
+
+/// Parses the `#!interpreter [arg]` line at the start of `head`.
+///
+/// Everything after the interpreter is passed as a single argument.
+fn parse_shebang(head: &[u8]) -> LinuxResult<(String, Option<String>)> {
+    let line = &head[2..];
+    let line = match line.iter().position(|&c| c == b'\n') {
+        Some(end) => &line[..end],
+        None if head.len() < BINPRM_BUF_SIZE => line,
+        None => return Err(LinuxError::ENOEXEC),
+    };
+    let blank: &[char] = &[' ', '\t'];
+    let line = core::str::from_utf8(line)
+        .map_err(|_| LinuxError::ENOEXEC)?
+        .trim_matches(blank);
+    let (interp, arg) = match line.find(blank) {
+        Some(end) => (&line[..end], Some(line[end..].trim_matches(blank))),
+        None => (line, None),
+    };
+    if interp.is_empty() {
+        return Err(LinuxError::ENOEXEC);
+    }
+    Ok((interp.to_string(), arg.map(ToString::to_string)))
+}
+
+/// Resolves the program run by `execve(path, args)`.
+///
+/// A `#!` script is run by its interpreter, with the optional argument of
+/// the interpreter and the path of the script in place of `args[0]`. The
+/// interpreter may itself be a script, up to [`MAX_SCRIPT_DEPTH`] levels.
+/// Symbolic links to the program and the interpreters are followed.
+///
+/// # Returns
+/// - The path of the ELF file to load.
+/// - The arguments to pass to it.
+pub fn resolve_executable(path: &str, args: &[String]) -> LinuxResult<(String, Vec<String>)> {
+    let mut path = path.to_string();
+    let mut args = args.to_vec();
+    for _ in 0..=MAX_SCRIPT_DEPTH {
+        let file_path = axfs::api::resolve_path(&path, true)?;
+        check_executable(&file_path)?;
+        let mut head = [0; BINPRM_BUF_SIZE];
+        let mut file = axfs::api::File::open(&file_path)?;
+        let mut len = 0;
+        while len < head.len() {
+            match file.read(&mut head[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        if !head[..len].starts_with(b"#!") {
+            return Ok((file_path, args));
+        }
+        let (interp, interp_arg) = parse_shebang(&head[..len])?;
+        let mut new_args = vec![interp.clone()];
+        new_args.extend(interp_arg);
+        new_args.push(path);
+        new_args.extend(args.into_iter().skip(1));
+        path = interp;
+        args = new_args;
+    }
+    Err(LinuxError::ELOOP)
+}
+
+/// Checks that the arguments and environment variables fit in the user
+/// stack. As in Linux, they may take up to a quarter of it.
+pub fn check_args_size(args: &[String], envs: &[String]) -> LinuxResult {
+    let mut size = 0;
+    for arg in args.iter().chain(envs) {
+        if arg.len() + 1 > MAX_ARG_STRLEN {
+            return Err(LinuxError::E2BIG);
+        }
+        size += arg.len() + 1 + size_of::<usize>();
+    }
+    if size > axconfig::plat::USER_STACK_SIZE / 4 {
+        return Err(LinuxError::E2BIG);
+    }
+    Ok(())
+}
+
 /// Load the user app to the user address space.
 ///
 /// # Arguments
 /// - `uspace`: The address space of the user app.
-/// - `args`: The arguments of the user app. The first argument is the path of the user app.
+/// - `path`: The path of the user app.
+/// - `args`: The arguments of the user app.
 /// - `envs`: The environment variables of the user app.
 ///
 /// # Returns
@@ -76,43 +276,41 @@ fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEn
 /// - The stack pointer of the user app.
 pub fn load_user_app(
     uspace: &mut AddrSpace,
+    path: &str,
     args: &[String],
     envs: &[String],
 ) -> AxResult<(VirtAddr, VirtAddr)> {
     if args.is_empty() {
         return Err(AxError::InvalidInput);
     }
-    let file_data = axfs::api::read(args[0].as_str())?;
-    let elf = ElfFile::new(&file_data).map_err(|_| AxError::InvalidData)?;
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    let file = File::open(path, &opts)?;
+    let headers = read_elf_headers(&file)?;
+    let elf = ElfFile::new(&headers).map_err(|_| AxError::InvalidData)?;
     if let Some(interp) = elf
         .program_iter()
         .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Interp))
-    {   
+    {
         let interp = match interp.get_data(&elf) {
             Ok(SegmentData::Undefined(data)) => data,
-            _ => panic!("Invalid data in Interp Elf Program Header"),
+            _ => return Err(AxError::InvalidData),
         };
-        let mut interp_path = axfs::api::canonicalize(
-            CStr::from_bytes_with_nul(interp)
-                .map_err(|_| AxError::InvalidData)?
-                .to_str()
-                .map_err(|_| AxError::InvalidData)?,
-        )?;
-
-        if interp_path == "/lib/ld-linux-riscv64-lp64.so.1"
-            || interp_path == "/lib64/ld-linux-loongarch-lp64d.so.1"
-            || interp_path == "/lib64/ld-linux-x86-64.so.2"
-            || interp_path == "/lib/ld-linux-aarch64.so.1"
-        {
-            // TODO: Use soft link
-            interp_path = String::from("/musl/lib/libc.so");
-        }
-        // Set the first argument to the path of the user app.
-        let mut new_args = vec![interp_path];
-        new_args.extend_from_slice(args);
-        return load_user_app(uspace, &new_args, envs);
-    }
-    let (entry, mut auxv) = map_elf(uspace, &elf)?;
+        let interp = CStr::from_bytes_with_nul(interp)
+            .map_err(|_| AxError::InvalidData)?
+            .to_str()
+            .map_err(|_| AxError::InvalidData)?;
+        // The interpreter is usually a symbolic link to the real loader. A
+        // path left unresolved fails to load below with `ENOENT`.
+        let interp_path =
+            axfs::api::resolve_path(interp, true).unwrap_or_else(|_| interp.to_string());
+        // Run the interpreter with the path of the user app as its first argument.
+        let mut new_args = vec![interp_path.clone(), path.to_string()];
+        new_args.extend_from_slice(&args[1..]);
+        return load_user_app(uspace, &interp_path, &new_args, envs);
+    }
+    let (entry, mut auxv) = map_elf(uspace, &elf, &file, page_cache(path)?)?;
+    crate::signal::map_signal_trampoline(uspace)?;
     // The user stack is divided into two parts:
     // `ustack_start` -> `ustack_pointer`: It is the stack space that users actually read and write.
     // `ustack_pointer` -> `ustack_end`: It is the space that contains the arguments, environment variables and auxv passed to the app.
@@ -149,11 +347,163 @@ pub fn load_user_app(
     Ok((entry, user_sp))
 }
 
+/// A handle of a mapped file, through which its page cache is filled and
+/// written back.
+struct MappedFile(File);
+
+impl FileIo for MappedFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        self.0.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        self.0.write_at(offset, buf)
+    }
+
+    fn size(&self) -> AxResult<u64> {
+        Ok(self.0.get_attr()?.size())
+    }
+}
+
+/// Identifies a file for its page cache.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum FileKey {
+    /// A file on a filesystem with hard links, by the filesystem and the inode.
+    Inode(u64, u64),
+    /// A file on another filesystem, by the filesystem and the absolute path,
+    /// which is its only name.
+    Path(u64, String),
+}
+
+impl FileKey {
+    fn of(path: &str) -> AxResult<Self> {
+        let path = axfs::api::canonicalize(path)?;
+        Ok(match axfs::api::file_id(&path)? {
+            (fsid, Some(ino)) => Self::Inode(fsid, ino),
+            (fsid, None) => Self::Path(fsid, path),
+        })
+    }
+}
+
+/// The page caches of the mapped files.
+static PAGE_CACHES: Mutex<BTreeMap<FileKey, Weak<PageCache>>> = Mutex::new(BTreeMap::new());
+
+/// Returns the page cache of the file at `path`, if it is mapped.
+fn mapped_page_cache(path: &str) -> Option<Arc<PageCache>> {
+    let key = FileKey::of(path).ok()?;
+    PAGE_CACHES.lock().get(&key).and_then(Weak::upgrade)
+}
+
+/// Returns the page cache of the file at `path`, shared by all the mappings
+/// of the file through any of its names.
+pub fn page_cache(path: &str) -> AxResult<Arc<PageCache>> {
+    let key = FileKey::of(path)?;
+    let path = axfs::api::canonicalize(path)?;
+    let mut caches = PAGE_CACHES.lock();
+    if let Some(cache) = caches.get(&key).and_then(Weak::upgrade) {
+        return Ok(cache);
+    }
+    // The cache opens its own handle, writable if possible for writing back
+    // shared mappings, so it does not depend on the descriptor being mapped.
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    opts.write(true);
+    let file = File::open(&path, &opts).or_else(|_| {
+        opts.write(false);
+        File::open(&path, &opts)
+    })?;
+    let cache = Arc::new(PageCache::new(Arc::new(MappedFile(file))));
+    caches.retain(|_, cache| cache.strong_count() > 0);
+    caches.insert(key, Arc::downgrade(&cache));
+    Ok(cache)
+}
+
+/// Writes back the dirty pages of the file at `path`, if it is mapped.
+pub fn sync_page_cache(path: &str) -> LinuxResult {
+    if let Some(cache) = mapped_page_cache(path) {
+        cache.sync(0..u64::MAX)?;
+    }
+    Ok(())
+}
+
+/// Detaches the page cache of the file at `path` before its last name is
+/// removed, so that a new file reusing the name or the inode gets its own.
+///
+/// The mappings keep using the detached cache.
+pub fn forget_page_cache(path: &str) {
+    let Ok(key) = FileKey::of(path) else {
+        return;
+    };
+    if matches!(key, FileKey::Inode(..)) && axfs::api::link_count(path).is_ok_and(|n| n > 1) {
+        return;
+    }
+    PAGE_CACHES.lock().remove(&key);
+}
+
+/// Keeps the page caches coherent with the reads and writes through file
+/// descriptors, which do not go through the caches.
+struct PageCacheCoherence;
+
+impl arceos_posix_api::FileCache for PageCacheCoherence {
+    fn before_read(&self, path: &str, offset: u64, len: usize) {
+        if let Some(cache) = mapped_page_cache(path) {
+            let first = offset / PAGE_SIZE_4K as u64;
+            let last = (offset + len as u64).div_ceil(PAGE_SIZE_4K as u64);
+            if let Err(e) = cache.sync(first..last) {
+                warn!("Failed to write back the page cache of {}: {:?}", path, e);
+            }
+        }
+    }
+
+    fn after_write(&self, path: &str, offset: u64, data: &[u8]) {
+        if let Some(cache) = mapped_page_cache(path) {
+            cache.write_through(offset, data);
+        }
+    }
+
+    fn after_truncate(&self, path: &str, size: u64) {
+        if let Some(cache) = mapped_page_cache(path) {
+            cache.truncate(size);
+        }
+    }
+}
+
+/// Write-protects the pages written back by the page caches in the address
+/// spaces of the tasks mapping them.
+struct TaskWriteProtect;
+
+impl axmm::WriteProtect for TaskWriteProtect {
+    fn write_protect(&self, root: PhysAddr, vaddr: VirtAddr, frame: PhysAddr) -> bool {
+        let mut busy = false;
+        for task in crate::task::all_tasks() {
+            let aspace = task.task_ext().aspace();
+            let Some(mut aspace) = aspace.try_lock() else {
+                busy = true;
+                continue;
+            };
+            if aspace.page_table_root() == root {
+                return aspace.write_protect(vaddr, frame);
+            }
+        }
+        // The address space is gone, unless it is one of the busy ones.
+        !busy
+    }
+}
+
+/// Registers the coherence of the page caches with the file I/O.
+pub fn init_page_caches() {
+    arceos_posix_api::set_file_cache(&PageCacheCoherence);
+    axmm::set_write_protect(&TaskWriteProtect);
+}
+
 #[percpu::def_percpu]
 static mut ACCESSING_USER_MEM: bool = false;
 
 /// Enables scoped access into user memory, allowing page faults to occur inside
 /// kernel.
+///
+/// The faults that cannot be resolved make [`axhal::trap::copy_user`] fail, so
+/// the accesses that may fault must go through it.
 pub fn access_user_memory<R>(f: impl FnOnce() -> R) -> R {
     ACCESSING_USER_MEM.with_current(|v| {
         *v = true;
@@ -172,18 +522,30 @@ fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool)
     if !is_user && !ACCESSING_USER_MEM.read_current() {
         return false;
     }
-    if !axtask::current()
-        .task_ext()
-        .aspace
-        .lock()
-        .handle_page_fault(vaddr, access_flags)
-    {
-        warn!(
-            "{}: segmentation fault at {:#x}, exit!",
-            axtask::current().id_name(),
-            vaddr
-        );
-        axtask::exit(-1);
+    let curr = axtask::current();
+    let aspace = curr.task_ext().aspace();
+    let mut aspace = aspace.lock();
+    if aspace.handle_page_fault(vaddr, access_flags) {
+        return true;
+    }
+    if !is_user {
+        // The trap handler resumes the faulting copy, which fails with EFAULT.
+        warn!("{}: bad user access at {:#x}", curr.id_name(), vaddr);
+        return false;
+    }
+    let mapped = aspace.check_region_access(
+        VirtAddrRange::from_start_size(vaddr.align_down_4k(), PAGE_SIZE_4K),
+        MappingFlags::empty(),
+    );
+    if aspace.is_past_file_end(vaddr) {
+        drop(aspace);
+        warn!("{}: bus error at {:#x}", curr.id_name(), vaddr);
+        force_signal(SIGBUS, BUS_ADRERR, vaddr.as_usize());
+        return true;
     }
+    drop(aspace);
+    warn!("{}: segmentation fault at {:#x}", curr.id_name(), vaddr);
+    let code = if mapped { SEGV_ACCERR } else { SEGV_MAPERR };
+    force_signal(SIGSEGV, code, vaddr.as_usize());
     true
 }
@@ -1,5 +1,6 @@
 use axerrno::{LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
+use axhal::trap::copy_user;
 use axtask::{TaskExtRef, current};
 use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
 
@@ -14,7 +15,8 @@ fn check_region(start: VirtAddr, layout: Layout, access_flags: MappingFlags) ->
     }
 
     let task = current();
-    let mut aspace = task.task_ext().aspace.lock();
+    let aspace = task.task_ext().aspace();
+    let mut aspace = aspace.lock();
 
     if !aspace.check_region_access(
         VirtAddrRange::from_start_size(start, layout.size()),
@@ -25,7 +27,7 @@ fn check_region(start: VirtAddr, layout: Layout, access_flags: MappingFlags) ->
 
     let page_start = start.align_down_4k();
     let page_end = (start + layout.size()).align_up_4k();
-    aspace.populate_area(page_start, page_end - page_start)?;
+    aspace.populate_area(page_start, page_end - page_start, access_flags)?;
 
     Ok(())
 }
@@ -60,7 +62,8 @@ fn check_null_terminated<T: Eq + Default>(
                 // querying the page table since the page might has not been
                 // allocated yet.
                 let task = current();
-                let aspace = task.task_ext().aspace.lock();
+                let aspace = task.task_ext().aspace();
+                let aspace = aspace.lock();
                 if !aspace.check_region_access(
                     VirtAddrRange::from_start_size(page, PAGE_SIZE_4K),
                     access_flags,
@@ -71,9 +74,21 @@ fn check_null_terminated<T: Eq + Default>(
                 page += PAGE_SIZE_4K;
             }
 
-            // This might trigger a page fault
-            // SAFETY: The pointer is valid and points to a valid memory region.
-            if unsafe { ptr.read_volatile() } == zero {
+            // This might trigger a page fault, which fails the copy if it
+            // cannot be resolved.
+            let mut value = T::default();
+            // SAFETY: `value` is valid for writes of a `T`.
+            let copied = unsafe {
+                copy_user(
+                    (&mut value as *mut T).cast(),
+                    ptr.cast(),
+                    mem::size_of::<T>(),
+                )
+            };
+            if copied.is_err() {
+                return Err(LinuxError::EFAULT);
+            }
+            if value == zero {
                 break;
             }
             len += 1;
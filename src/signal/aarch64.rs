@@ -0,0 +1,107 @@
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::{FpState, TrapFrame};
+use core::mem::size_of;
+
+core::arch::global_asm!(
+    "
+    .pushsection .text.signal_trampoline, \"ax\"
+    .balign 4096
+    .global signal_trampoline
+signal_trampoline:
+    mov     x8, #139
+    svc     #0
+    .balign 4096
+    .popsection
+"
+);
+
+/// The condition flags (NZCV) of `PSTATE`, the only bits user space may change.
+const PSTATE_NZCV: u64 = 0xf000_0000;
+
+/// The magic number of [`FpsimdContext`].
+const FPSIMD_MAGIC: u32 = 0x4650_8001;
+
+/// `struct fpsimd_context`, the record of the FP/SIMD registers.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct FpsimdContext {
+    pub magic: u32,
+    pub size: u32,
+    pub fpsr: u32,
+    pub fpcr: u32,
+    pub vregs: [u128; 32],
+}
+
+impl FpsimdContext {
+    /// Saves the FP/SIMD registers, which still hold the state of user space
+    /// as the kernel does not use them.
+    fn save() -> Self {
+        let mut fpstate = FpState::default();
+        fpstate.save();
+        Self {
+            magic: FPSIMD_MAGIC,
+            size: size_of::<Self>() as u32,
+            fpsr: fpstate.fpsr,
+            fpcr: fpstate.fpcr,
+            vregs: fpstate.regs,
+        }
+    }
+
+    fn restore(&self) {
+        FpState {
+            regs: self.vregs,
+            fpcr: self.fpcr,
+            fpsr: self.fpsr,
+        }
+        .restore();
+    }
+}
+
+/// `struct sigcontext` of AArch64.
+///
+/// `__reserved` holds a list of records, here the [`FpsimdContext`] followed
+/// by a terminating null record.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct MContext {
+    pub fault_address: u64,
+    pub regs: [u64; 31],
+    pub sp: u64,
+    pub pc: u64,
+    pub pstate: u64,
+    pub fpsimd: FpsimdContext,
+    pub reserved: [u8; 4096 - size_of::<FpsimdContext>()],
+}
+
+impl MContext {
+    pub fn new(tf: &TrapFrame) -> Self {
+        Self {
+            fault_address: 0,
+            regs: tf.r,
+            sp: tf.usp,
+            pc: tf.elr,
+            pstate: tf.spsr,
+            fpsimd: FpsimdContext::save(),
+            reserved: [0; 4096 - size_of::<FpsimdContext>()],
+        }
+    }
+
+    pub fn restore(&self, tf: &mut TrapFrame) -> LinuxResult<()> {
+        if self.fpsimd.magic != FPSIMD_MAGIC
+            || self.fpsimd.size as usize != size_of::<FpsimdContext>()
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        tf.r = self.regs;
+        tf.usp = self.sp;
+        tf.elr = self.pc;
+        tf.spsr = (tf.spsr & !PSTATE_NZCV) | (self.pstate & PSTATE_NZCV);
+        self.fpsimd.restore();
+        Ok(())
+    }
+
+    /// The value of the syscall return register.
+    pub fn retval(&self) -> usize {
+        self.regs[0] as _
+    }
+}
@@ -0,0 +1,47 @@
+use axerrno::LinuxResult;
+use axhal::arch::{GeneralRegisters, TrapFrame};
+
+core::arch::global_asm!(
+    "
+    .pushsection .text.signal_trampoline, \"ax\"
+    .balign 4096
+    .global signal_trampoline
+signal_trampoline:
+    li.w    $a7, 139
+    syscall 0
+    .balign 4096
+    .popsection
+"
+);
+
+/// `struct sigcontext` of LoongArch64.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct MContext {
+    pub pc: usize,
+    pub regs: GeneralRegisters,
+    pub flags: u32,
+    _pad: u32,
+}
+
+impl MContext {
+    pub fn new(tf: &TrapFrame) -> Self {
+        Self {
+            pc: tf.era,
+            regs: tf.regs,
+            flags: 0,
+            _pad: 0,
+        }
+    }
+
+    pub fn restore(&self, tf: &mut TrapFrame) -> LinuxResult<()> {
+        tf.regs = self.regs;
+        tf.era = self.pc;
+        Ok(())
+    }
+
+    /// The value of the syscall return register.
+    pub fn retval(&self) -> usize {
+        self.regs.a0
+    }
+}
@@ -0,0 +1,435 @@
+//! Signal delivery.
+//!
+//! A handled signal is delivered on the way back to user space: the current
+//! [`TrapFrame`] is saved in a signal frame (`siginfo_t` + `ucontext_t`) pushed
+//! on the user stack, and the trap frame is redirected to the handler. The
+//! handler returns to the signal trampoline (or `sa_restorer`), which issues
+//! `rt_sigreturn` to restore the saved context.
+
+use alloc::collections::VecDeque;
+use core::{mem::size_of, sync::atomic::Ordering};
+
+use axerrno::{AxResult, LinuxResult};
+use axhal::{arch::TrapFrame, mem::virt_to_phys, paging::MappingFlags};
+use axmm::AddrSpace;
+use axtask::{TaskExtRef, current};
+use memory_addr::{PAGE_SIZE_4K, VirtAddr};
+
+use crate::{
+    ctypes::{SIG_DFL, SIG_IGN, SigAction, SigActionFlags, SiginfoT, SignalFlags},
+    ptr::{PtrWrapper, UserConstPtr, UserPtr},
+};
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "loongarch64")]
+mod loongarch64;
+#[cfg(target_arch = "riscv64")]
+mod riscv;
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+
+#[cfg(target_arch = "aarch64")]
+use self::aarch64::MContext;
+#[cfg(target_arch = "loongarch64")]
+use self::loongarch64::MContext;
+#[cfg(target_arch = "riscv64")]
+use self::riscv::MContext;
+#[cfg(target_arch = "x86_64")]
+use self::x86_64::{FpState, MContext, save_fpstate};
+
+/// Signals that can be neither caught, blocked nor ignored.
+pub const UNBLOCKABLE_SIGNALS: SignalFlags = SignalFlags::SIGKILL.union(SignalFlags::SIGSTOP);
+
+/// The first real-time signal. Real-time signals are queued, while standard
+/// signals are merged when already pending.
+pub const SIGRTMIN: usize = 32;
+
+/// Illegal instruction.
+pub const SIGILL: usize = 4;
+/// Bus error.
+pub const SIGBUS: usize = 7;
+/// Erroneous arithmetic operation.
+pub const SIGFPE: usize = 8;
+/// Invalid memory reference.
+pub const SIGSEGV: usize = 11;
+/// The signal sent to the parent when a child stops, continues or exits.
+pub const SIGCHLD: usize = 17;
+/// Continue a stopped process.
+pub const SIGCONT: usize = 18;
+
+/// Signals that stop the process by default.
+pub const STOP_SIGNALS: SignalFlags = SignalFlags::SIGSTOP
+    .union(SignalFlags::SIGTSTP)
+    .union(SignalFlags::SIGTTIN)
+    .union(SignalFlags::SIGTTOU);
+
+/// Signals that are ignored by default.
+const IGNORED_SIGNALS: SignalFlags = SignalFlags::SIGCHLD
+    .union(SignalFlags::SIGURG)
+    .union(SignalFlags::SIGWINCH);
+
+/// Signals that terminate the process and dump core by default.
+const CORE_SIGNALS: SignalFlags = SignalFlags::SIGQUIT
+    .union(SignalFlags::SIGILL)
+    .union(SignalFlags::SIGTRAP)
+    .union(SignalFlags::SIGABRT)
+    .union(SignalFlags::SIGBUS)
+    .union(SignalFlags::SIGFPE)
+    .union(SignalFlags::SIGSEGV)
+    .union(SignalFlags::SIGXCPU)
+    .union(SignalFlags::SIGXFSZ)
+    .union(SignalFlags::SIGSYS);
+
+/// The action taken for a signal whose handler is `SIG_DFL`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefaultAction {
+    /// Terminate the process.
+    Terminate,
+    /// Terminate the process and dump core.
+    CoreDump,
+    /// Ignore the signal.
+    Ignore,
+    /// Stop the process.
+    Stop,
+    /// Continue the process if it is stopped.
+    Continue,
+}
+
+impl DefaultAction {
+    /// Returns the default action of the signal `signum`, following signal(7).
+    pub fn of(signum: usize) -> Self {
+        let Some(signal) = SignalFlags::from_signum(signum) else {
+            return Self::Terminate;
+        };
+        if signal == SignalFlags::SIGCONT {
+            Self::Continue
+        } else if STOP_SIGNALS.contains(signal) {
+            Self::Stop
+        } else if IGNORED_SIGNALS.contains(signal) {
+            Self::Ignore
+        } else if CORE_SIGNALS.contains(signal) {
+            Self::CoreDump
+        } else {
+            Self::Terminate
+        }
+    }
+}
+
+/// A stop or continue event of a child, reported through `wait4`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobControlEvent {
+    /// Stopped by the given signal.
+    Stopped(usize),
+    /// Continued by SIGCONT.
+    Continued,
+}
+
+/// A set of pending signals along with their `siginfo_t` payloads.
+pub struct PendingSignals {
+    set: SignalFlags,
+    queue: VecDeque<SiginfoT>,
+}
+
+impl PendingSignals {
+    pub const fn new() -> Self {
+        Self {
+            set: SignalFlags::empty(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Returns the set of pending signals.
+    pub fn pending(&self) -> SignalFlags {
+        self.set
+    }
+
+    /// Adds a signal to the pending set.
+    ///
+    /// Returns `false` if it is a standard signal that is already pending, in
+    /// which case the new instance is discarded.
+    pub fn push(&mut self, info: SiginfoT) -> bool {
+        let signum = info.si_signo as usize;
+        let Some(signal) = SignalFlags::from_signum(signum) else {
+            return false;
+        };
+        if signum < SIGRTMIN && self.set.contains(signal) {
+            return false;
+        }
+        self.set |= signal;
+        self.queue.push_back(info);
+        true
+    }
+
+    /// Removes the lowest pending signal that is not in `mask`.
+    pub fn pop(&mut self, mask: SignalFlags) -> Option<SiginfoT> {
+        let signum = (self.set - mask).lowest_signum()?;
+        let index = self
+            .queue
+            .iter()
+            .position(|info| info.si_signo as usize == signum)?;
+        let info = self.queue.remove(index)?;
+        if !self.queue.iter().any(|info| info.si_signo as usize == signum) {
+            self.set.remove(SignalFlags::from_signum(signum).unwrap());
+        }
+        Some(info)
+    }
+
+    /// Discards all pending instances of the signals in `signals`.
+    pub fn remove(&mut self, signals: SignalFlags) {
+        self.set.remove(signals);
+        self.queue.retain(|info| {
+            SignalFlags::from_signum(info.si_signo as usize)
+                .is_some_and(|signal| !signals.contains(signal))
+        });
+    }
+}
+
+/// The alternate signal stack is disabled.
+const SS_DISABLE: i32 = 2;
+
+/// `stack_t`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalStack {
+    pub ss_sp: usize,
+    pub ss_flags: i32,
+    pub ss_size: usize,
+}
+
+impl Default for SignalStack {
+    fn default() -> Self {
+        Self {
+            ss_sp: 0,
+            ss_flags: SS_DISABLE,
+            ss_size: 0,
+        }
+    }
+}
+
+/// `ucontext_t` of the generic Linux ABI, where `uc_sigmask` is padded to
+/// 1024 bits before the machine context.
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UContext {
+    pub uc_flags: usize,
+    pub uc_link: usize,
+    pub uc_stack: SignalStack,
+    pub uc_sigmask: u64,
+    _unused: [u8; 120],
+    pub uc_mcontext: MContext,
+}
+
+/// `ucontext_t` of x86_64, where `uc_sigmask` follows the machine context.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UContext {
+    pub uc_flags: usize,
+    pub uc_link: usize,
+    pub uc_stack: SignalStack,
+    pub uc_mcontext: MContext,
+    pub uc_sigmask: u64,
+}
+
+impl UContext {
+    fn new(tf: &TrapFrame, mask: SignalFlags) -> Self {
+        Self {
+            uc_flags: 0,
+            uc_link: 0,
+            uc_stack: SignalStack::default(),
+            uc_sigmask: mask.bits(),
+            #[cfg(not(target_arch = "x86_64"))]
+            _unused: [0; 120],
+            uc_mcontext: MContext::new(tf),
+        }
+    }
+}
+
+/// The frame pushed on the user stack when a signal handler is invoked.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalFrame {
+    info: SiginfoT,
+    ucontext: UContext,
+    /// The FP/SIMD state, which `uc_mcontext.fpstate` points to. Other
+    /// architectures keep it in the machine context itself.
+    #[cfg(target_arch = "x86_64")]
+    fpstate: FpState,
+}
+
+unsafe extern "C" {
+    fn signal_trampoline();
+}
+
+/// Maps the signal trampoline page into the user address space.
+///
+/// Handlers without `SA_RESTORER` return to this page, which calls
+/// `rt_sigreturn`.
+pub fn map_signal_trampoline(uspace: &mut AddrSpace) -> AxResult {
+    let paddr = virt_to_phys(VirtAddr::from(signal_trampoline as usize));
+    uspace.map_linear(
+        VirtAddr::from(axconfig::plat::SIGNAL_TRAMPOLINE),
+        paddr,
+        PAGE_SIZE_4K,
+        MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
+    )
+}
+
+/// Delivers the pending and unblocked signals of the current task.
+///
+/// It is called right before returning to user space. It returns early if a
+/// signal stops the task, leaving the remaining signals pending until the task
+/// is continued.
+pub fn handle_signals(tf: &mut TrapFrame) {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    while let Some(info) = task_ext.take_pending_signal() {
+        let signum = info.si_signo as usize;
+        let action = task_ext.get_signal_action(signum);
+        match action.sa_handler {
+            SIG_IGN => continue,
+            SIG_DFL => match DefaultAction::of(signum) {
+                DefaultAction::Ignore | DefaultAction::Continue => continue,
+                DefaultAction::Terminate => terminate_current(signum, false),
+                DefaultAction::CoreDump => terminate_current(signum, true),
+                DefaultAction::Stop => {
+                    task_ext.process.stop(signum);
+                    return;
+                }
+            },
+            _ => {}
+        }
+        if let Err(e) = setup_frame(tf, signum, &action, info) {
+            warn!(
+                "{}: failed to deliver signal {}: {:?}, killed by SIGSEGV",
+                curr.id_name(),
+                signum,
+                e
+            );
+            // The frame cannot be written to the user stack, so the process is
+            // killed as by Linux's `force_sigsegv`.
+            terminate_current(SIGSEGV, true);
+        }
+    }
+}
+
+/// Sends a synchronous fault signal to the current thread.
+///
+/// A fault signal cannot be deferred: if it is blocked or ignored, its action
+/// is reset to the default and it is unblocked, so that the task gets killed.
+pub fn force_signal(signum: usize, code: i32, addr: usize) {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let signal = SignalFlags::from_signum(signum).unwrap();
+    let blocked = task_ext.get_mask().contains(signal);
+    if blocked || task_ext.get_signal_action(signum).sa_handler == SIG_IGN {
+        task_ext.set_signal_action(signum, &SigAction::default());
+        task_ext.del_mask(signal);
+    }
+    let mut info = SiginfoT::new(signum, code);
+    info.set_addr(addr);
+    task_ext.send_signal_to_thread(info);
+}
+
+/// Terminates the current task because of the signal `signum`.
+pub fn terminate_current(signum: usize, core_dumped: bool) -> ! {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    info!("{}: terminated by signal {}", curr.id_name(), signum);
+    task_ext.killed.store(true, Ordering::Release);
+    if task_ext.process.group_exit(128 + signum as i32) {
+        task_ext.process.set_term_signal(signum, core_dumped);
+    }
+    crate::task::exit_current(128 + signum as i32)
+}
+
+/// Pushes a signal frame on the user stack and redirects `tf` to the handler.
+fn setup_frame(
+    tf: &mut TrapFrame,
+    signum: usize,
+    action: &SigAction,
+    info: SiginfoT,
+) -> LinuxResult<()> {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let old_mask = task_ext.get_mask();
+
+    // Skip the red zone below the user stack pointer.
+    #[cfg(target_arch = "x86_64")]
+    let sp = tf.get_sp() - 128;
+    #[cfg(not(target_arch = "x86_64"))]
+    let sp = tf.get_sp();
+    let frame_addr = (sp - size_of::<SignalFrame>()) & !0xf;
+    let frame_ptr = UserPtr::<SignalFrame>::from(frame_addr).get()?;
+    #[allow(unused_mut)]
+    let mut ucontext = UContext::new(tf, old_mask);
+    #[cfg(target_arch = "x86_64")]
+    {
+        ucontext.uc_mcontext.fpstate =
+            (frame_addr + core::mem::offset_of!(SignalFrame, fpstate)) as u64;
+    }
+    unsafe {
+        frame_ptr.write(SignalFrame {
+            info,
+            ucontext,
+            #[cfg(target_arch = "x86_64")]
+            fpstate: save_fpstate(),
+        });
+    }
+
+    let flags = SigActionFlags::from_bits_truncate(action.sa_flags);
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    let restorer = if flags.contains(SigActionFlags::SA_RESTORER) && action.sa_restorer != 0 {
+        action.sa_restorer
+    } else {
+        axconfig::plat::SIGNAL_TRAMPOLINE
+    };
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    let restorer = axconfig::plat::SIGNAL_TRAMPOLINE;
+
+    tf.set_ip(action.sa_handler);
+    tf.set_arg0(signum);
+    tf.set_arg1(frame_addr + core::mem::offset_of!(SignalFrame, info));
+    tf.set_arg2(frame_addr + core::mem::offset_of!(SignalFrame, ucontext));
+    #[cfg(target_arch = "x86_64")]
+    {
+        // The return address is pushed on the stack, as if by `call`.
+        let ret_addr = frame_addr - size_of::<usize>();
+        unsafe { *UserPtr::<usize>::from(ret_addr).get()? = restorer };
+        tf.set_sp(ret_addr);
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        tf.set_ra(restorer);
+        tf.set_sp(frame_addr);
+    }
+
+    let mut new_mask = old_mask | SignalFlags::from_bits_truncate(action.sa_mask as u64);
+    if !flags.contains(SigActionFlags::SA_NODEFER) {
+        new_mask |= SignalFlags::from_signum(signum).unwrap();
+    }
+    task_ext.set_mask(new_mask - UNBLOCKABLE_SIGNALS);
+    if flags.contains(SigActionFlags::SA_RESETHAND) {
+        task_ext.set_signal_action(signum, &SigAction::default());
+    }
+    Ok(())
+}
+
+/// Restores the context saved by [`setup_frame`] into `tf`.
+///
+/// Returns the value of the syscall return register of the restored context,
+/// so that the syscall return path writes it back unchanged.
+pub fn restore_frame(tf: &mut TrapFrame) -> LinuxResult<isize> {
+    let curr = current();
+    let frame_ptr = UserConstPtr::<SignalFrame>::from(tf.get_sp()).get()?;
+    let ucontext = unsafe { (*frame_ptr).ucontext };
+    ucontext.uc_mcontext.restore(tf)?;
+    curr.task_ext()
+        .set_mask(SignalFlags::from_bits_retain(ucontext.uc_sigmask) - UNBLOCKABLE_SIGNALS);
+    // The syscall return path skips the `ecall`/`syscall` instruction again.
+    #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))]
+    tf.set_ip(tf.get_ip() - 4);
+    Ok(ucontext.uc_mcontext.retval() as isize)
+}
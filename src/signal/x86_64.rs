@@ -0,0 +1,143 @@
+use axerrno::LinuxResult;
+use axhal::arch::{FxsaveArea, TrapFrame};
+
+use crate::ptr::{PtrWrapper, UserConstPtr};
+
+core::arch::global_asm!(
+    "
+    .pushsection .text.signal_trampoline, \"ax\"
+    .balign 4096
+    .global signal_trampoline
+signal_trampoline:
+    mov     rax, 15
+    syscall
+    .balign 4096
+    .popsection
+"
+);
+
+/// The `RFLAGS` bits that user space may change (AC, OF, DF, TF, SF, ZF, AF,
+/// PF, CF and RF).
+const RFLAGS_USER: u64 = 0x50dd5;
+
+/// `struct _fpstate` of x86_64, the FXSAVE format.
+pub type FpState = FxsaveArea;
+
+/// Saves the FP/SIMD registers, which still hold the state of user space as
+/// the kernel does not use them.
+pub fn save_fpstate() -> FpState {
+    let mut fpstate = FpState::new();
+    fpstate.save();
+    fpstate
+}
+
+/// `struct sigcontext` of x86_64.
+///
+/// `fpstate` points to the [`FpState`] saved in the signal frame.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MContext {
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub rdx: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rsp: u64,
+    pub rip: u64,
+    pub eflags: u64,
+    pub cs: u16,
+    pub gs: u16,
+    pub fs: u16,
+    pub ss: u16,
+    pub err: u64,
+    pub trapno: u64,
+    pub oldmask: u64,
+    pub cr2: u64,
+    pub fpstate: u64,
+    pub reserved: [u64; 8],
+}
+
+impl MContext {
+    pub fn new(tf: &TrapFrame) -> Self {
+        Self {
+            r8: tf.r8,
+            r9: tf.r9,
+            r10: tf.r10,
+            r11: tf.r11,
+            r12: tf.r12,
+            r13: tf.r13,
+            r14: tf.r14,
+            r15: tf.r15,
+            rdi: tf.rdi,
+            rsi: tf.rsi,
+            rbp: tf.rbp,
+            rbx: tf.rbx,
+            rdx: tf.rdx,
+            rax: tf.rax,
+            rcx: tf.rcx,
+            rsp: tf.rsp,
+            rip: tf.rip,
+            eflags: tf.rflags,
+            cs: tf.cs as _,
+            gs: 0,
+            fs: 0,
+            ss: tf.ss as _,
+            err: tf.error_code,
+            trapno: tf.vector,
+            oldmask: 0,
+            cr2: 0,
+            fpstate: 0,
+            reserved: [0; 8],
+        }
+    }
+
+    pub fn restore(&self, tf: &mut TrapFrame) -> LinuxResult<()> {
+        tf.r8 = self.r8;
+        tf.r9 = self.r9;
+        tf.r10 = self.r10;
+        tf.r11 = self.r11;
+        tf.r12 = self.r12;
+        tf.r13 = self.r13;
+        tf.r14 = self.r14;
+        tf.r15 = self.r15;
+        tf.rdi = self.rdi;
+        tf.rsi = self.rsi;
+        tf.rbp = self.rbp;
+        tf.rbx = self.rbx;
+        tf.rdx = self.rdx;
+        tf.rax = self.rax;
+        tf.rcx = self.rcx;
+        tf.rsp = self.rsp;
+        tf.rip = self.rip;
+        tf.rflags = (tf.rflags & !RFLAGS_USER) | (self.eflags & RFLAGS_USER);
+
+        // A null `fpstate` resets the FP/SIMD state, as on Linux.
+        let mut fpstate = FpState::new();
+        if self.fpstate != 0 {
+            fpstate = unsafe { *UserConstPtr::<FpState>::from(self.fpstate as usize).get()? };
+            // FXRSTOR faults if reserved bits of MXCSR are set.
+            let mxcsr_mask = match save_fpstate().mxcsr_mask {
+                0 => 0xffbf,
+                mask => mask,
+            };
+            fpstate.mxcsr &= mxcsr_mask;
+        }
+        fpstate.restore();
+        Ok(())
+    }
+
+    /// The value of the syscall return register.
+    pub fn retval(&self) -> usize {
+        self.rax as _
+    }
+}
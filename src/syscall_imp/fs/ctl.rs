@@ -1,11 +1,15 @@
 use core::ffi::{c_char, c_void};
 
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 use arceos_posix_api::AT_FDCWD;
 use axerrno::{AxError, LinuxError, LinuxResult};
 use macro_rules_attribute::apply;
 
 use crate::{
+    ctypes::{
+        AT_SYMLINK_FOLLOW, LO_FLAGS_READ_ONLY, LOOP_CLR_FD, LOOP_GET_STATUS64, LOOP_SET_FD,
+        LoopInfo64,
+    },
     ptr::{PtrWrapper, UserConstPtr, UserPtr},
     syscall_imp::syscall_instrument,
 };
@@ -19,14 +23,74 @@ use crate::{
 ///   and of type int in musl and other UNIX systems.
 /// * `argp` - The argument to the request. It is a pointer to a memory location
 #[apply(syscall_instrument)]
-pub fn sys_ioctl(_fd: i32, _op: usize, _argp: UserPtr<c_void>) -> LinuxResult<isize> {
-    warn!("Unimplemented syscall: SYS_IOCTL");
+pub fn sys_ioctl(fd: i32, op: usize, argp: UserPtr<c_void>) -> LinuxResult<isize> {
+    match op as u32 {
+        op @ (LOOP_SET_FD | LOOP_CLR_FD | LOOP_GET_STATUS64) => loop_ioctl(fd, op, argp),
+        _ => {
+            warn!("Unimplemented syscall: SYS_IOCTL");
+            Ok(0)
+        }
+    }
+}
+
+/// Handles the requests to the loop device `fd`.
+fn loop_ioctl(fd: i32, op: u32, argp: UserPtr<c_void>) -> LinuxResult<isize> {
+    let dev = arceos_posix_api::File::from_fd(fd).map_err(|_| LinuxError::ENOTTY)?;
+    let loop_err = |err| match err {
+        AxError::Unsupported => LinuxError::ENOTTY,
+        AxError::BadState => LinuxError::ENXIO,
+        AxError::ResourceBusy => LinuxError::EBUSY,
+        err => err.into(),
+    };
+    let arg = argp.address().as_usize();
+    match op {
+        LOOP_SET_FD => {
+            // The argument is the file descriptor of the backing file, which
+            // is bound read-only unless opened for writing.
+            let file = arceos_posix_api::File::from_fd(arg as _)?;
+            let read_only = !file.inner().lock().is_writable();
+            axfs::api::loop_attach(dev.path(), file.path(), read_only).map_err(loop_err)?;
+        }
+        LOOP_CLR_FD => axfs::api::loop_detach(dev.path()).map_err(loop_err)?,
+        _ => {
+            let status = axfs::api::loop_status(dev.path()).map_err(loop_err)?;
+            let mut info: LoopInfo64 = unsafe { core::mem::zeroed() };
+            info.lo_number = status.number as _;
+            if status.read_only {
+                info.lo_flags |= LO_FLAGS_READ_ONLY;
+            }
+            let name = status.file_name.as_bytes();
+            // The name is truncated to leave the terminating null byte.
+            let len = name.len().min(info.lo_file_name.len() - 1);
+            info.lo_file_name[..len].copy_from_slice(&name[..len]);
+            unsafe { *UserPtr::<LoopInfo64>::from(arg).get()? = info };
+        }
+    }
     Ok(0)
 }
 
+/// Resolves `path` relative to the directory `dirfd` into an absolute path
+/// without symbolic links, following the last component if `follow` is set.
+pub(crate) fn resolve_at(dirfd: isize, path: &[c_char], follow: bool) -> LinuxResult<String> {
+    if path.is_empty() {
+        return Err(LinuxError::ENOENT);
+    }
+    let path = arceos_posix_api::handle_file_path(dirfd, Some(path.as_ptr() as _), false)?;
+    axfs::api::resolve_path(path.as_str(), follow)
+}
+
+/// Fails with `EROFS` if `path` is on a read-only mount.
+pub(crate) fn check_writable(path: &str) -> LinuxResult {
+    if axfs::api::is_read_only(path).unwrap_or(false) {
+        Err(LinuxError::EROFS)
+    } else {
+        Ok(())
+    }
+}
+
 pub fn sys_chdir(path: UserConstPtr<c_char>) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
-    axfs::api::set_current_dir(path).map(|_| 0).map_err(|err| {
+    let path = resolve_at(AT_FDCWD, path.get_as_null_terminated()?, true)?;
+    axfs::api::set_current_dir(&path).map(|_| 0).map_err(|err| {
         warn!("Failed to change directory: {err:?}");
         err.into()
     })
@@ -44,7 +108,9 @@ pub(crate) fn sys_mkdirat(dirfd: i32, path: UserConstPtr<c_char>, mode: u32) ->
         info!("directory mode not supported.");
     }
 
-    axfs::api::create_dir(path).map(|_| 0).map_err(|err| {
+    let path = axfs::api::resolve_path(path, false)?;
+    check_writable(&path)?;
+    axfs::api::create_dir(&path).map(|_| 0).map_err(|err| {
         warn!("Failed to create directory {path}: {err:?}");
         err.into()
     })
@@ -229,31 +295,33 @@ pub fn sys_linkat(
     let old_path = old_path.get_as_null_terminated()?;
     let new_path = new_path.get_as_null_terminated()?;
 
-    if flags != 0 {
+    if flags & !AT_SYMLINK_FOLLOW != 0 {
         warn!("Unsupported flags: {flags}");
     }
 
-    // handle old path
-    arceos_posix_api::handle_file_path(old_dirfd as isize, Some(old_path.as_ptr() as _), false)
-        .inspect_err(|err| warn!("Failed to convert new path: {err:?}"))
-        .and_then(|old_path| {
-            //handle new path
-            arceos_posix_api::handle_file_path(
-                new_dirfd as isize,
-                Some(new_path.as_ptr() as _),
-                false,
-            )
-            .inspect_err(|err| warn!("Failed to convert new path: {err:?}"))
-            .map(|new_path| (old_path, new_path))
-        })
-        .and_then(|(old_path, new_path)| {
-            arceos_posix_api::HARDLINK_MANAGER
-                .create_link(&new_path, &old_path)
-                .inspect_err(|err| warn!("Failed to create link: {err:?}"))
-                .map_err(Into::into)
-        })
+    // The old path is followed only with `AT_SYMLINK_FOLLOW`, so that a
+    // symbolic link itself is linked by default.
+    let old_path = resolve_at(old_dirfd as _, old_path, flags & AT_SYMLINK_FOLLOW != 0)
+        .inspect_err(|err| warn!("Failed to convert old path: {err:?}"))?;
+    let new_path = resolve_at(new_dirfd as _, new_path, false)
+        .inspect_err(|err| warn!("Failed to convert new path: {err:?}"))?;
+    check_writable(&new_path)?;
+    hard_link(&old_path, &new_path)
+        .inspect_err(|err| warn!("Failed to create link: {err:?}"))
         .map(|_| 0)
-        .map_err(|err| err.into())
+}
+
+/// Creates the hard link `new_path` to `old_path`, failing with `EXDEV`
+/// across mounted filesystems and with `EPERM` on the filesystems without
+/// hard links.
+fn hard_link(old_path: &str, new_path: &str) -> LinuxResult {
+    if !axfs::api::same_filesystem(old_path, new_path)? {
+        return Err(LinuxError::EXDEV);
+    }
+    axfs::api::hard_link(old_path, new_path).map_err(|err| match err {
+        AxError::Unsupported | AxError::PermissionDenied => LinuxError::EPERM,
+        err => err.into(),
+    })
 }
 
 /// remove link of specific file (can be used to delete file)
@@ -266,37 +334,102 @@ pub fn sys_unlinkat(dir_fd: isize, path: UserConstPtr<c_char>, flags: usize) ->
 
     const AT_REMOVEDIR: usize = 0x200;
 
-    arceos_posix_api::handle_file_path(dir_fd, Some(path.as_ptr() as _), false)
-        .inspect_err(|e| warn!("unlinkat error: {:?}", e))
-        .and_then(|path| {
-            if flags == AT_REMOVEDIR {
-                axfs::api::remove_dir(path.as_str())
-                    .inspect_err(|e| warn!("unlinkat error: {:?}", e))
-                    .map(|_| 0)
-            } else {
-                axfs::api::metadata(path.as_str()).and_then(|metadata| {
-                    if metadata.is_dir() {
-                        Err(AxError::IsADirectory)
-                    } else {
-                        debug!("unlink file: {:?}", path);
-                        arceos_posix_api::HARDLINK_MANAGER
-                            .remove_link(&path)
-                            .ok_or_else(|| {
-                                debug!("unlink file error");
-                                AxError::NotFound
-                            })
-                            .map(|_| 0)
-                    }
-                })
-            }
-        })
-        .map_err(|err| err.into())
+    // The link itself is removed, not its target.
+    let path = resolve_at(dir_fd, path, false).inspect_err(|e| warn!("unlinkat error: {:?}", e))?;
+    check_writable(&path)?;
+    if flags == AT_REMOVEDIR {
+        axfs::api::remove_dir(&path)
+            .inspect_err(|e| warn!("unlinkat error: {:?}", e))
+            .map(|_| 0)
+            .map_err(|err| err.into())
+    } else {
+        axfs::api::metadata(&path)
+            .and_then(|metadata| {
+                if metadata.is_dir() {
+                    Err(AxError::IsADirectory)
+                } else {
+                    debug!("unlink file: {:?}", path);
+                    crate::mm::forget_page_cache(&path);
+                    axfs::api::remove_file(&path)
+                        .inspect_err(|e| debug!("unlink file error: {:?}", e))
+                        .map(|_| 0)
+                }
+            })
+            .map_err(|err| err.into())
+    }
 }
 
 pub fn sys_unlink(path: UserConstPtr<c_char>) -> LinuxResult<isize> {
     sys_unlinkat(AT_FDCWD as _, path, 0)
 }
 
+/// Creates the symbolic link `link_path` pointing to `target`, which is
+/// stored as is and resolved when the link is followed.
+pub fn sys_symlinkat(
+    target: UserConstPtr<c_char>,
+    new_dirfd: i32,
+    link_path: UserConstPtr<c_char>,
+) -> LinuxResult<isize> {
+    let target = target.get_as_str()?;
+    if target.is_empty() {
+        return Err(LinuxError::ENOENT);
+    }
+    let link_path = resolve_at(new_dirfd as _, link_path.get_as_null_terminated()?, false)?;
+    check_writable(&link_path)?;
+    axfs::api::symlink(target, &link_path)
+        .map(|_| 0)
+        .map_err(|err| match err {
+            AxError::Unsupported => LinuxError::EPERM,
+            err => err.into(),
+        })
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn sys_symlink(
+    target: UserConstPtr<c_char>,
+    link_path: UserConstPtr<c_char>,
+) -> LinuxResult<isize> {
+    sys_symlinkat(target, AT_FDCWD as _, link_path)
+}
+
+/// Reads the target of the symbolic link `path` into `buf`, truncated to
+/// `size` bytes and not null-terminated.
+///
+/// Returns the number of bytes placed in `buf`.
+pub fn sys_readlinkat(
+    dirfd: i32,
+    path: UserConstPtr<c_char>,
+    buf: UserPtr<c_char>,
+    size: usize,
+) -> LinuxResult<isize> {
+    if size == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let path = resolve_at(dirfd as _, path.get_as_null_terminated()?, false)?;
+    axfs::api::metadata(&path)?;
+    let target = axfs::api::read_link(&path).map_err(|err| match err {
+        // Not a symbolic link
+        AxError::Unsupported | AxError::InvalidInput => LinuxError::EINVAL,
+        err => err.into(),
+    })?;
+    let len = target.len().min(size);
+    let buf = buf.get_as_array(len)?;
+    unsafe {
+        core::slice::from_raw_parts_mut(buf as *mut u8, len)
+            .copy_from_slice(&target.as_bytes()[..len]);
+    }
+    Ok(len as _)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn sys_readlink(
+    path: UserConstPtr<c_char>,
+    buf: UserPtr<c_char>,
+    size: usize,
+) -> LinuxResult<isize> {
+    sys_readlinkat(AT_FDCWD as _, path, buf, size)
+}
+
 pub fn sys_getcwd(buf: UserPtr<c_char>, size: usize) -> LinuxResult<isize> {
     Ok(arceos_posix_api::sys_getcwd(buf.get_as_null_terminated()?.as_ptr() as _, size) as _)
 }
@@ -14,11 +14,30 @@ pub fn sys_dup(old_fd: c_int) -> LinuxResult<isize> {
     }
 }
 
-pub fn sys_dup3(old_fd: c_int, new_fd: c_int) -> LinuxResult<isize> {
+#[cfg(target_arch = "x86_64")]
+pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> LinuxResult<isize> {
     Ok(api::sys_dup2(old_fd, new_fd) as _)
 }
 
+/// Like `dup2`, but fails if `old_fd` equals `new_fd`, and can set the
+/// close-on-exec flag of `new_fd` with `O_CLOEXEC`.
+pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> LinuxResult<isize> {
+    if old_fd == new_fd || flags & !(api::ctypes::O_CLOEXEC as c_int) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let fd = api::sys_dup2(old_fd, new_fd);
+    if fd >= 0 && flags != 0 {
+        api::set_cloexec(fd, true)?;
+    }
+    Ok(fd as _)
+}
+
 pub fn sys_close(fd: c_int) -> LinuxResult<isize> {
+    // Write back the pages of the file mapped with `MAP_SHARED`.
+    let synced = api::File::from_fd(fd).map(|file| crate::mm::sync_page_cache(file.path()));
+    if let Ok(Err(e)) = synced {
+        warn!("close: failed to write back file pages: {:?}", e);
+    }
     Ok(api::sys_close(fd) as _)
 }
 
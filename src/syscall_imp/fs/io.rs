@@ -1,8 +1,10 @@
 use core::ffi::{c_char, c_void};
 
-use arceos_posix_api::{self as api, ctypes::mode_t};
-use axerrno::LinuxResult;
+use alloc::ffi::CString;
+use arceos_posix_api::{self as api, AT_FDCWD, ctypes::mode_t};
+use axerrno::{LinuxError, LinuxResult};
 
+use super::{check_writable, resolve_at};
 use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
 
 pub(crate) fn sys_read(fd: i32, buf: UserPtr<c_void>, count: usize) -> LinuxResult<isize> {
@@ -40,7 +42,29 @@ pub(crate) fn sys_openat(
     modes: mode_t,
 ) -> LinuxResult<isize> {
     let path = path.get_as_null_terminated()?;
-    Ok(api::sys_openat(dirfd, path.as_ptr(), flags, modes) as _)
+    let flags_bits = flags as u32;
+    let nofollow = flags_bits & api::ctypes::O_NOFOLLOW != 0;
+    // An exclusive creation fails on any existing link, even dangling.
+    let exclusive = flags_bits & api::ctypes::O_CREAT != 0 && flags_bits & api::ctypes::O_EXCL != 0;
+    let path = resolve_at(dirfd as _, path, !nofollow && !exclusive)?;
+    if exclusive && axfs::api::absolute_path_exists(&path) {
+        return Err(LinuxError::EEXIST);
+    }
+    if nofollow && axfs::api::read_link(&path).is_ok() {
+        return Err(LinuxError::ELOOP);
+    }
+    let creates = flags_bits & api::ctypes::O_CREAT != 0 && !axfs::api::absolute_path_exists(&path);
+    if creates
+        || flags_bits & (api::ctypes::O_WRONLY | api::ctypes::O_RDWR | api::ctypes::O_TRUNC) != 0
+    {
+        check_writable(&path)?;
+    }
+    let path = CString::new(path).map_err(|_| LinuxError::EINVAL)?;
+    let fd = api::sys_openat(AT_FDCWD as _, path.as_ptr(), flags, modes);
+    if fd >= 0 && flags as u32 & api::ctypes::O_CLOEXEC != 0 {
+        api::set_cloexec(fd, true)?;
+    }
+    Ok(fd as _)
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -49,7 +73,6 @@ pub(crate) fn sys_open(
     flags: i32,
     modes: mode_t,
 ) -> LinuxResult<isize> {
-    use arceos_posix_api::AT_FDCWD;
     sys_openat(AT_FDCWD as _, path, flags, modes)
 }
 
@@ -65,4 +88,4 @@ pub(crate) fn sys_pread(
 ) -> LinuxResult<isize> {
     let buf = buf.get_as_bytes(count)?;
     Ok(api::sys_pread64(fd, buf, count, offset as u64))
-}
\ No newline at end of file
+}
@@ -1,138 +1,109 @@
-use alloc::vec::Vec;
-use arceos_posix_api::{AT_FDCWD, FilePath, handle_file_path};
-use axerrno::{LinuxError, LinuxResult};
-use axsync::Mutex;
+use arceos_posix_api::AT_FDCWD;
+use axerrno::{AxError, LinuxError, LinuxResult};
 use core::ffi::{c_char, c_void};
 
-use crate::ptr::UserConstPtr;
+use super::resolve_at;
+use crate::ptr::{PtrWrapper, UserConstPtr};
+
+bitflags::bitflags! {
+    /// flags for sys_mount
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/linux/mount.h>
+    #[derive(Debug, Clone, Copy)]
+    struct MountFlags: u32 {
+        /// Mount read-only.
+        const MS_RDONLY = 1 << 0;
+        /// Alter the flags of a mounted filesystem.
+        const MS_REMOUNT = 1 << 5;
+        /// Mount a directory again somewhere else.
+        const MS_BIND = 1 << 12;
+        /// Bind the mounts below the directory too.
+        const MS_REC = 1 << 14;
+    }
+}
+
+bitflags::bitflags! {
+    /// flags for sys_umount2
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/linux/fs.h>
+    #[derive(Debug, Clone, Copy)]
+    struct UmountFlags: i32 {
+        /// Unmount even if busy.
+        const MNT_FORCE = 1 << 0;
+        /// Unmount lazily, once the filesystem is not busy.
+        const MNT_DETACH = 1 << 1;
+        /// Mark the mount as expired.
+        const MNT_EXPIRE = 1 << 2;
+        /// Don't follow the target if it is a symbolic link.
+        const UMOUNT_NOFOLLOW = 1 << 3;
+    }
+}
+
+/// Maps the errors of the mount table to those of mount(2) and umount2(2).
+fn mount_err(err: AxError) -> LinuxError {
+    match err {
+        AxError::Unsupported => LinuxError::ENODEV,
+        AxError::ResourceBusy | AxError::AlreadyExists => LinuxError::EBUSY,
+        AxError::InvalidData => LinuxError::EINVAL,
+        err => err.into(),
+    }
+}
 
 pub fn sys_mount(
     source: UserConstPtr<c_char>,
     target: UserConstPtr<c_char>,
     fs_type: UserConstPtr<c_char>,
-    _flags: i32,
+    flags: u32,
     _data: UserConstPtr<c_void>,
 ) -> LinuxResult<isize> {
-    info!("sys_mount");
-    let source = source.get_as_null_terminated()?;
-    let target = target.get_as_null_terminated()?;
-    let fs_type = fs_type.get_as_str()?;
-    let device_path = handle_file_path(AT_FDCWD, Some(source.as_ptr() as _), false)?;
-    let mount_path = handle_file_path(AT_FDCWD, Some(target.as_ptr() as _), true)?;
-    info!(
-        "mount {:?} to {:?} with fs_type={:?}",
-        device_path, mount_path, fs_type
-    );
-
-    if fs_type != "vfat" {
-        debug!("fs_type can only be vfat.");
-        return Err(LinuxError::EPERM);
-    }
-
-    if !mount_path.exists() {
-        debug!("mount path not exist");
-        return Err(LinuxError::EPERM);
+    let flags = MountFlags::from_bits_truncate(flags);
+    let target = resolve_at(AT_FDCWD, target.get_as_null_terminated()?, true)?;
+    info!("sys_mount <= target: {target}, flags: {flags:?}");
+    let read_only = flags.contains(MountFlags::MS_RDONLY);
+
+    if flags.contains(MountFlags::MS_REMOUNT) {
+        // Only the read-only flag can be changed, which is per mount anyway.
+        axfs::api::remount(&target, read_only).map_err(mount_err)?;
+        return Ok(0);
     }
 
-    if check_mounted(&mount_path) {
-        debug!("mount path includes mounted fs");
-        return Err(LinuxError::EPERM);
+    if flags.contains(MountFlags::MS_BIND) {
+        // The mounts below `source` are not bound again even with `MS_REC`.
+        let source = resolve_at(AT_FDCWD, source.get_as_null_terminated()?, true)?;
+        axfs::api::bind_mount(&source, &target, read_only).map_err(mount_err)?;
+        return Ok(0);
     }
 
-    if !mount_fat_fs(&device_path, &mount_path) {
-        debug!("mount error");
-        return Err(LinuxError::EPERM);
-    }
+    let fs_type = fs_type.get_as_str()?;
+    let source = source
+        .nullable(|source| source.get_as_str())?
+        .unwrap_or("none");
+    // Block devices may be reached through symbolic links, e.g. /dev/disk/...
+    let source = if source.starts_with('/') {
+        axfs::api::resolve_path(source, true)?
+    } else {
+        source.into()
+    };
+    info!("mount {source} to {target} with fs_type={fs_type}");
+    axfs::api::mount(&source, &target, fs_type, read_only).map_err(mount_err)?;
     Ok(0)
 }
 
 pub fn sys_umount2(target: UserConstPtr<c_char>, flags: i32) -> LinuxResult<isize> {
-    info!("sys_umount2");
-    let target = target.get_as_null_terminated()?;
-    let mount_path = handle_file_path(AT_FDCWD, Some(target.as_ptr() as _), true)?;
-    if flags != 0 {
-        debug!("flags unimplemented");
-        return Err(LinuxError::EPERM);
-    }
-
-    if !mount_path.exists() {
-        debug!("mount path not exist");
-        return Err(LinuxError::EPERM);
-    }
-
-    if !umount_fat_fs(&mount_path) {
-        debug!("umount error");
-        return Err(LinuxError::EPERM);
+    let Some(flags) = UmountFlags::from_bits(flags) else {
+        return Err(LinuxError::EINVAL);
+    };
+    if flags.contains(UmountFlags::MNT_EXPIRE)
+        && flags.intersects(UmountFlags::MNT_FORCE | UmountFlags::MNT_DETACH)
+    {
+        return Err(LinuxError::EINVAL);
     }
+    let follow = !flags.contains(UmountFlags::UMOUNT_NOFOLLOW);
+    let target = resolve_at(AT_FDCWD, target.get_as_null_terminated()?, follow)?;
+    info!("sys_umount2 <= target: {target}, flags: {flags:?}");
+    // Filesystems are released once their operations finish, so a forced
+    // unmount is a lazy one too.
+    let detach = flags.intersects(UmountFlags::MNT_FORCE | UmountFlags::MNT_DETACH);
+    axfs::api::umount(&target, detach).map_err(mount_err)?;
     Ok(0)
 }
-
-/// Mounted File System
-/// "Mount" means read&write a file as a file system now
-pub struct MountedFs {
-    //pub inner: Arc<Mutex<FATFileSystem>>,
-    pub device: FilePath,
-    pub mnt_dir: FilePath,
-}
-
-impl MountedFs {
-    pub fn new(device: &FilePath, mnt_dir: &FilePath) -> Self {
-        assert!(
-            device.is_file() && mnt_dir.is_dir(),
-            "device must be a file and mnt_dir must be a dir"
-        );
-        Self {
-            device: device.clone(),
-            mnt_dir: mnt_dir.clone(),
-        }
-    }
-    #[allow(unused)]
-    pub fn device(&self) -> FilePath {
-        self.device.clone()
-    }
-
-    pub fn mnt_dir(&self) -> FilePath {
-        self.mnt_dir.clone()
-    }
-}
-
-/// List of mounted file system
-/// Note that the startup file system is not in the vec, but in mod.rs
-static MOUNTED: Mutex<Vec<MountedFs>> = Mutex::new(Vec::new());
-
-/// Mount a fatfs device
-pub fn mount_fat_fs(device_path: &FilePath, mount_path: &FilePath) -> bool {
-    // device_path needs symlink lookup, but mount_path does not
-    // only opened files will be added to the symlink table for now, so do not convert now
-    // debug!("mounting {} to {}", device_path.path(), mount_path.path());
-    // if let Some(true_device_path) = real_path(device_path) {
-    if mount_path.exists() {
-        MOUNTED.lock().push(MountedFs::new(device_path, mount_path));
-        info!(
-            "mounted {} to {}",
-            device_path.as_str(),
-            mount_path.as_str()
-        );
-        return true;
-    }
-    info!(
-        "mount failed: {} to {}",
-        device_path.as_str(),
-        mount_path.as_str()
-    );
-    false
-}
-
-/// unmount a fatfs device
-pub fn umount_fat_fs(mount_path: &FilePath) -> bool {
-    let mut mounted = MOUNTED.lock();
-    let length_before_deletion = mounted.len();
-    mounted.retain(|m| m.mnt_dir() != *mount_path);
-    length_before_deletion > mounted.len()
-}
-
-/// check if a path is mounted
-pub fn check_mounted(path: &FilePath) -> bool {
-    let mounted = MOUNTED.lock();
-    mounted.iter().any(|m| path.starts_with(&m.mnt_dir()))
-}
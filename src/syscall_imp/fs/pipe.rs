@@ -5,8 +5,13 @@ use axerrno::LinuxResult;
 
 use crate::ptr::{PtrWrapper, UserPtr};
 
-pub fn sys_pipe2(fds: UserPtr<i32>) -> LinuxResult<isize> {
+pub fn sys_pipe2(fds: UserPtr<i32>, flags: c_int) -> LinuxResult<isize> {
     let fds = fds.get_as_array(2)?;
     let fds_slice: &mut [c_int] = unsafe { core::slice::from_raw_parts_mut(fds, 2) };
-    Ok(api::sys_pipe(fds_slice) as _)
+    let ret = api::sys_pipe(fds_slice);
+    if ret == 0 && flags as u32 & api::ctypes::O_CLOEXEC != 0 {
+        api::set_cloexec(fds_slice[0], true)?;
+        api::set_cloexec(fds_slice[1], true)?;
+    }
+    Ok(ret as _)
 }
@@ -1,11 +1,13 @@
 use core::ffi::c_char;
 
-use alloc::ffi::CString;
+use alloc::{ffi::CString, string::String};
 use arceos_posix_api::ctypes::stat;
 use axerrno::{LinuxError, LinuxResult};
 use macro_rules_attribute::apply;
 
+use super::resolve_at;
 use crate::{
+    ctypes::{AT_EMPTY_PATH, AT_SYMLINK_NOFOLLOW},
     ptr::{PtrWrapper, UserConstPtr, UserPtr},
     syscall_imp::syscall_instrument,
 };
@@ -133,10 +135,13 @@ pub fn sys_fstatat(
     dir_fd: isize,
     path: UserConstPtr<c_char>,
     kstatbuf: UserPtr<Kstat>,
-    _flags: i32,
+    flags: i32,
 ) -> LinuxResult<isize> {
     let path = path.get_as_null_terminated()?;
-    let path = arceos_posix_api::handle_file_path(dir_fd, Some(path.as_ptr() as _), false)?;
+    if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+        return sys_fstat(dir_fd as _, kstatbuf);
+    }
+    let path = resolve_at(dir_fd, path, flags & AT_SYMLINK_NOFOLLOW == 0)?;
     let kstatbuf = kstatbuf.get()?;
 
     let mut statbuf = arceos_posix_api::ctypes::stat::default();
@@ -252,11 +257,10 @@ pub fn sys_statx(
     //        below), then the target file is the one referred to by the
     //        file descriptor dirfd.
 
-    let path = pathname.get_as_str()?;
+    let path = pathname.get_as_null_terminated()?;
     let mut status = arceos_posix_api::ctypes::stat::default();
-    const AT_EMPTY_PATH: u32 = 0x1000;
     if path.is_empty() {
-        if flags & AT_EMPTY_PATH == 0 {
+        if flags & AT_EMPTY_PATH as u32 == 0 {
             return Err(LinuxError::EINVAL);
         }
         let res = unsafe { arceos_posix_api::sys_fstat(dirfd, &mut status as *mut _) };
@@ -264,7 +268,13 @@ pub fn sys_statx(
             return Err(LinuxError::try_from(-res).unwrap());
         }
     } else {
-        unsafe {arceos_posix_api::sys_stat(CString::new(path).unwrap().as_ptr(), &mut status as *mut _);}
+        let path = resolve_at(dirfd as _, path, flags & AT_SYMLINK_NOFOLLOW as u32 == 0)?;
+        let res = unsafe {
+            arceos_posix_api::sys_stat(CString::new(path).unwrap().as_ptr(), &mut status as *mut _)
+        };
+        if res < 0 {
+            return Err(LinuxError::try_from(-res).unwrap());
+        }
     }
     let statx = unsafe { &mut *statxbuf.get()? };
     statx.stx_blksize = status.st_blksize as u32;
@@ -290,15 +300,43 @@ pub fn sys_statx(
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct Statfs {
-    pub f_ftype: u64,   // 文件系统的类型
-    pub f_bsize: u64,   // 经优化后的传输块的大小
-    pub f_blocks: u64,  // 文件系统数据块总数
-    pub f_bfree: u64,   // 可用块数
-    pub f_bavail: u64,  // 普通用户能够获得的块数
-    pub f_files: u64,   // 文件结点总数
-    pub f_ffree: u64,   // 可用文件结点数
-    pub f_fsid: u64,    // 文件系统标识
-    pub f_namelen: u64, // 文件名的最大长度
+    pub f_type: u64,      // 文件系统的类型（魔数）
+    pub f_bsize: u64,     // 经优化后的传输块的大小
+    pub f_blocks: u64,    // 文件系统数据块总数
+    pub f_bfree: u64,     // 可用块数
+    pub f_bavail: u64,    // 普通用户能够获得的块数
+    pub f_files: u64,     // 文件结点总数
+    pub f_ffree: u64,     // 可用文件结点数
+    pub f_fsid: [i32; 2], // 文件系统标识
+    pub f_namelen: u64,   // 文件名的最大长度
+    pub f_frsize: u64,    // 片段的大小
+    pub f_flags: u64,     // 挂载标志
+    pub f_spare: [u64; 4],
+}
+
+/// Writes the statistics of the filesystem holding `path` to `statfsbuf`.
+fn write_statfs(path: &str, statfsbuf: UserPtr<Statfs>) -> LinuxResult<isize> {
+    const ST_RDONLY: u64 = 1;
+    const ST_VALID: u64 = 0x20;
+
+    let stat = axfs::api::statfs(path)?;
+    let statfs = Statfs {
+        f_type: stat.magic,
+        f_bsize: stat.block_size,
+        f_blocks: stat.blocks,
+        f_bfree: stat.blocks_free,
+        f_bavail: stat.blocks_avail,
+        f_files: stat.files,
+        f_ffree: stat.files_free,
+        f_fsid: [stat.fsid as i32, (stat.fsid >> 32) as i32],
+        f_namelen: stat.name_max,
+        f_frsize: stat.block_size,
+        f_flags: if stat.read_only { ST_VALID | ST_RDONLY } else { ST_VALID },
+        ..Default::default()
+    };
+    debug!("statfs {path}: {statfs:?}");
+    unsafe { *statfsbuf.get()? = statfs };
+    Ok(0)
 }
 
 #[apply(syscall_instrument)]
@@ -306,38 +344,26 @@ pub fn sys_statfs(
     pathname: UserConstPtr<c_char>,
     statfsbuf: UserPtr<Statfs>,
 ) -> LinuxResult<isize> {
-    let path = pathname.get_as_str()?;
-    let _path = arceos_posix_api::handle_file_path(-1, Some(path.as_ptr() as _), false)?;
-    let statfs = unsafe { &mut *statfsbuf.get()? };
-    statfs.f_bsize = 4096;
-    statfs.f_blocks = 255;
-    statfs.f_bfree = 0;
-    statfs.f_bavail = 0;
-    statfs.f_files = 1;
-    statfs.f_ffree = 0;
-    statfs.f_fsid = 0;
-    statfs.f_namelen = 255;
-    statfs.f_ftype = 0;
-    // statfs.f_spare = [0; 6];
-    info!("statfs:{:?}", statfs);
-    Ok(0)
+    let path = resolve_at(arceos_posix_api::AT_FDCWD, pathname.get_as_null_terminated()?, true)?;
+    write_statfs(&path, statfsbuf)
 }
 
 #[apply(syscall_instrument)]
-pub fn sys_stat(
-    path: UserConstPtr<c_char>, 
-    kstatbuf: UserPtr<Kstat>,) -> LinuxResult<isize> {
-    let kstatbuf = kstatbuf.get()?;
-    let mut statbuf = arceos_posix_api::ctypes::stat::default();
-    unsafe{
-        arceos_posix_api::sys_stat(
-            path.get().unwrap(),
-            &mut statbuf as *mut _
-        );
-    }
-    unsafe {
-        let kstat = Kstat::from(statbuf);
-        kstatbuf.write(kstat);
-    }
-    Ok(0)
+pub fn sys_fstatfs(fd: i32, statfsbuf: UserPtr<Statfs>) -> LinuxResult<isize> {
+    let path: String = match arceos_posix_api::File::from_fd(fd) {
+        Ok(file) => file.path().into(),
+        Err(_) => arceos_posix_api::Directory::from_fd(fd)?.path().into(),
+    };
+    write_statfs(&path, statfsbuf)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_stat(path: UserConstPtr<c_char>, kstatbuf: UserPtr<Kstat>) -> LinuxResult<isize> {
+    sys_fstatat(arceos_posix_api::AT_FDCWD, path, kstatbuf, 0)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[apply(syscall_instrument)]
+pub fn sys_lstat(path: UserConstPtr<c_char>, kstatbuf: UserPtr<Kstat>) -> LinuxResult<isize> {
+    sys_fstatat(arceos_posix_api::AT_FDCWD, path, kstatbuf, AT_SYMLINK_NOFOLLOW)
 }
\ No newline at end of file
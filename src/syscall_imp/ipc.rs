@@ -0,0 +1,330 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::{ffi::c_char, mem::size_of, slice, time::Duration};
+
+use arceos_posix_api::{self as api, FileLike};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{TimeValue, monotonic_time, wall_time};
+use macro_rules_attribute::apply;
+
+use crate::{
+    ctypes::{
+        GETALL, GETNCNT, GETPID, GETVAL, GETZCNT, IPC_64, IPC_RMID, IPC_SET, IPC_STAT, MqAttr,
+        MsqidDs, SETALL, SETVAL, SemBuf, SemidDs, ShmidDs, SigEvent,
+    },
+    ipc::{
+        mqueue::{self, MqueueFd},
+        msg, sem, shm,
+    },
+    ptr::{PtrWrapper, UserConstPtr, UserPtr},
+    syscall_imp::syscall_instrument,
+};
+
+#[apply(syscall_instrument)]
+pub fn sys_shmget(key: i32, size: usize, flags: i32) -> LinuxResult<isize> {
+    Ok(shm::shmget(key, size, flags)? as _)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_shmat(shmid: i32, addr: usize, flags: i32) -> LinuxResult<isize> {
+    Ok(shm::shmat(shmid, addr, flags)? as _)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_shmdt(addr: usize) -> LinuxResult<isize> {
+    shm::shmdt(addr)?;
+    Ok(0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_shmctl(shmid: i32, cmd: i32, buf: usize) -> LinuxResult<isize> {
+    match cmd & !IPC_64 {
+        IPC_STAT => {
+            let ds = shm::shm_stat(shmid)?;
+            unsafe { *UserPtr::<ShmidDs>::from(buf).get()? = ds };
+        }
+        IPC_SET => {
+            let ds = unsafe { *UserConstPtr::<ShmidDs>::from(buf).get()? };
+            shm::shm_set(shmid, &ds)?;
+        }
+        IPC_RMID => shm::shm_remove(shmid)?,
+        _ => return Err(LinuxError::EINVAL),
+    }
+    Ok(0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_semget(key: i32, nsems: i32, flags: i32) -> LinuxResult<isize> {
+    if nsems < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(sem::semget(key, nsems as usize, flags)? as _)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_semop(semid: i32, sops: usize, nsops: usize) -> LinuxResult<isize> {
+    sys_semtimedop(semid, sops, nsops, 0)
+}
+
+/// The semtimedop syscall.
+///
+/// `timeout` is a pointer to a relative `timespec`, or null to wait forever.
+#[apply(syscall_instrument)]
+pub fn sys_semtimedop(semid: i32, sops: usize, nsops: usize, timeout: usize) -> LinuxResult<isize> {
+    if nsops == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let sops = UserConstPtr::<SemBuf>::from(sops).get_as_array(nsops)?;
+    let sops = unsafe { slice::from_raw_parts(sops, nsops) };
+    let deadline = if timeout == 0 {
+        None
+    } else {
+        let ts = unsafe { *UserConstPtr::<api::ctypes::timespec>::from(timeout).get()? };
+        if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+            return Err(LinuxError::EINVAL);
+        }
+        Some(monotonic_time() + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    };
+    sem::semop(semid, sops, deadline)?;
+    Ok(0)
+}
+
+/// The semctl syscall.
+///
+/// `arg` is the `semun` argument: the value for `SETVAL`, or a pointer to a
+/// `semid_ds` or to an array of values for the others.
+#[apply(syscall_instrument)]
+pub fn sys_semctl(semid: i32, semnum: i32, cmd: i32, arg: usize) -> LinuxResult<isize> {
+    // The commands on one semaphore take its index in `semnum`.
+    let on_one = matches!(cmd & !IPC_64, GETVAL | GETPID | GETNCNT | GETZCNT | SETVAL);
+    if on_one && (semnum < 0 || semnum as usize >= sem::sem_count(semid)?) {
+        return Err(LinuxError::EINVAL);
+    }
+    let semnum = semnum as usize;
+    match cmd & !IPC_64 {
+        IPC_STAT => {
+            let ds = sem::sem_stat(semid)?;
+            unsafe { *UserPtr::<SemidDs>::from(arg).get()? = ds };
+        }
+        IPC_SET => {
+            let ds = unsafe { *UserConstPtr::<SemidDs>::from(arg).get()? };
+            sem::sem_set(semid, &ds)?;
+        }
+        IPC_RMID => sem::sem_remove(semid)?,
+        GETVAL => return Ok(sem::sem_info(semid, semnum)?.0 as _),
+        GETPID => return Ok(sem::sem_info(semid, semnum)?.1 as _),
+        GETNCNT => return Ok(sem::sem_info(semid, semnum)?.2 as _),
+        GETZCNT => return Ok(sem::sem_info(semid, semnum)?.3 as _),
+        GETALL => {
+            let vals = sem::sem_get_all(semid)?;
+            let buf = UserPtr::<u16>::from(arg).get_as_array(vals.len())?;
+            unsafe { slice::from_raw_parts_mut(buf, vals.len()) }.copy_from_slice(&vals);
+        }
+        SETVAL => sem::sem_set_values(semid, semnum, &[arg as i32])?,
+        SETALL => {
+            let nsems = sem::sem_count(semid)?;
+            let buf = UserConstPtr::<u16>::from(arg).get_as_array(nsems)?;
+            let vals: Vec<i32> = unsafe { slice::from_raw_parts(buf, nsems) }
+                .iter()
+                .map(|&val| val as i32)
+                .collect();
+            sem::sem_set_values(semid, 0, &vals)?;
+        }
+        _ => return Err(LinuxError::EINVAL),
+    }
+    Ok(0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_msgget(key: i32, flags: i32) -> LinuxResult<isize> {
+    Ok(msg::msgget(key, flags)? as _)
+}
+
+/// The msgsnd syscall.
+///
+/// `msgp` points to the type of the message, followed by `msgsz` bytes of
+/// text.
+#[apply(syscall_instrument)]
+pub fn sys_msgsnd(msqid: i32, msgp: usize, msgsz: usize, flags: i32) -> LinuxResult<isize> {
+    if msgsz > msg::MSGMAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let mtype = unsafe { *UserConstPtr::<isize>::from(msgp).get()? };
+    let text = UserConstPtr::<u8>::from(msgp + size_of::<isize>()).get_as_bytes(msgsz)?;
+    let text = unsafe { slice::from_raw_parts(text, msgsz) }.to_vec();
+    msg::msgsnd(msqid, mtype, text, flags)?;
+    Ok(0)
+}
+
+/// The msgrcv syscall.
+///
+/// Stores the type and the text of the message at `msgp` as `msgsnd` takes
+/// them, and returns the size of the text.
+#[apply(syscall_instrument)]
+pub fn sys_msgrcv(
+    msqid: i32,
+    msgp: usize,
+    msgsz: usize,
+    msgtyp: isize,
+    flags: i32,
+) -> LinuxResult<isize> {
+    if (msgsz as isize) < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let mtype = UserPtr::<isize>::from(msgp).get()?;
+    let buf = UserPtr::<u8>::from(msgp + size_of::<isize>()).get_as_bytes(msgsz)?;
+    let (msg_type, text) = msg::msgrcv(msqid, msgsz, msgtyp, flags)?;
+    unsafe {
+        *mtype = msg_type;
+        slice::from_raw_parts_mut(buf, text.len()).copy_from_slice(&text);
+    }
+    Ok(text.len() as _)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_msgctl(msqid: i32, cmd: i32, buf: usize) -> LinuxResult<isize> {
+    match cmd & !IPC_64 {
+        IPC_STAT => {
+            let ds = msg::msg_stat(msqid)?;
+            unsafe { *UserPtr::<MsqidDs>::from(buf).get()? = ds };
+        }
+        IPC_SET => {
+            let ds = unsafe { *UserConstPtr::<MsqidDs>::from(buf).get()? };
+            msg::msg_set(msqid, &ds)?;
+        }
+        IPC_RMID => msg::msg_remove(msqid)?,
+        _ => return Err(LinuxError::EINVAL),
+    }
+    Ok(0)
+}
+
+/// Reads the absolute `CLOCK_REALTIME` timeout at `timeout`, if not null, as
+/// a deadline on the monotonic clock.
+fn read_abs_deadline(timeout: usize) -> LinuxResult<Option<TimeValue>> {
+    if timeout == 0 {
+        return Ok(None);
+    }
+    let ts = unsafe { *UserConstPtr::<api::ctypes::timespec>::from(timeout).get()? };
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    let timeout = Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+    Ok(Some(monotonic_time() + timeout.saturating_sub(wall_time())))
+}
+
+/// The mq_open syscall.
+///
+/// `name` is given without the leading `/`, and `attr` points to the
+/// attributes of a queue created with `O_CREAT`, or is null for the defaults.
+#[apply(syscall_instrument)]
+pub fn sys_mq_open(
+    name: UserConstPtr<c_char>,
+    flags: i32,
+    mode: u32,
+    attr: usize,
+) -> LinuxResult<isize> {
+    let name = name.get_as_str()?;
+    let attr = if attr != 0 && flags as u32 & api::ctypes::O_CREAT != 0 {
+        Some(unsafe { *UserConstPtr::<MqAttr>::from(attr).get()? })
+    } else {
+        None
+    };
+    let mqd = mqueue::mq_open(name, flags as u32, mode, attr)?;
+    let fd = api::add_file_like(Arc::new(mqd))?;
+    // Queue descriptors are always closed on exec.
+    api::set_cloexec(fd, true)?;
+    Ok(fd as _)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_mq_unlink(name: UserConstPtr<c_char>) -> LinuxResult<isize> {
+    mqueue::mq_unlink(name.get_as_str()?)?;
+    Ok(0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_mq_timedsend(
+    mqdes: i32,
+    msg_ptr: usize,
+    msg_len: usize,
+    msg_prio: u32,
+    abs_timeout: usize,
+) -> LinuxResult<isize> {
+    let mqd = MqueueFd::from_fd(mqdes)?;
+    let deadline = read_abs_deadline(abs_timeout)?;
+    let data = UserConstPtr::<u8>::from(msg_ptr).get_as_bytes(msg_len)?;
+    let data = unsafe { slice::from_raw_parts(data, msg_len) }.to_vec();
+    mqd.send(data, msg_prio, deadline)?;
+    Ok(0)
+}
+
+/// The mq_timedreceive syscall.
+///
+/// Stores the priority of the message at `msg_prio` if not null, and returns
+/// the size of the message.
+#[apply(syscall_instrument)]
+pub fn sys_mq_timedreceive(
+    mqdes: i32,
+    msg_ptr: usize,
+    msg_len: usize,
+    msg_prio: usize,
+    abs_timeout: usize,
+) -> LinuxResult<isize> {
+    let mqd = MqueueFd::from_fd(mqdes)?;
+    let deadline = read_abs_deadline(abs_timeout)?;
+    let buf = UserPtr::<u8>::from(msg_ptr).get_as_bytes(msg_len)?;
+    let prio = if msg_prio != 0 {
+        Some(UserPtr::<u32>::from(msg_prio).get()?)
+    } else {
+        None
+    };
+    let (data, msg_prio) = mqd.receive(msg_len, deadline)?;
+    unsafe {
+        slice::from_raw_parts_mut(buf, data.len()).copy_from_slice(&data);
+        if let Some(prio) = prio {
+            *prio = msg_prio;
+        }
+    }
+    Ok(data.len() as _)
+}
+
+/// The mq_notify syscall.
+///
+/// `sevp` points to the notification to register, or is null to remove the
+/// registration.
+#[apply(syscall_instrument)]
+pub fn sys_mq_notify(mqdes: i32, sevp: usize) -> LinuxResult<isize> {
+    let mqd = MqueueFd::from_fd(mqdes)?;
+    let event = if sevp != 0 {
+        Some(unsafe { *UserConstPtr::<SigEvent>::from(sevp).get()? })
+    } else {
+        None
+    };
+    mqd.set_notification(event)?;
+    Ok(0)
+}
+
+/// The mq_getsetattr syscall.
+///
+/// Only `O_NONBLOCK` in the flags of `newattr` can be changed, and the
+/// previous attributes are stored at `oldattr`.
+#[apply(syscall_instrument)]
+pub fn sys_mq_getsetattr(mqdes: i32, newattr: usize, oldattr: usize) -> LinuxResult<isize> {
+    let mqd = MqueueFd::from_fd(mqdes)?;
+    let new = if newattr != 0 {
+        let attr = unsafe { *UserConstPtr::<MqAttr>::from(newattr).get()? };
+        if attr.mq_flags & !(api::ctypes::O_NONBLOCK as isize) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        Some(attr)
+    } else {
+        None
+    };
+    let old = mqd.attr();
+    if oldattr != 0 {
+        unsafe { *UserPtr::<MqAttr>::from(oldattr).get()? = old };
+    }
+    if let Some(new) = new {
+        mqd.set_nonblocking(new.mq_flags != 0)?;
+    }
+    Ok(0)
+}
@@ -1,6 +1,7 @@
-use alloc::vec;
+use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
+use axmm::SharedMemory;
 use axtask::{TaskExtRef, current};
 use macro_rules_attribute::apply;
 use memory_addr::{VirtAddr, VirtAddrRange};
@@ -80,7 +81,8 @@ pub fn sys_mmap(
 
     let curr = current();
     let curr_ext = curr.task_ext();
-    let mut aspace = curr_ext.aspace.lock();
+    let aspace = curr_ext.aspace();
+    let mut aspace = aspace.lock();
     let permission_flags = MmapProt::from_bits_truncate(prot);
     // TODO: check illegal flags for mmap
     // An example is the flags contained none of MAP_PRIVATE, MAP_SHARED, or MAP_SHARED_VALIDATE.
@@ -102,6 +104,29 @@ pub fn sys_mmap(
         addr, length, fd, permission_flags, map_flags,  offset
     );
 
+    // Resolve the mapped file before touching the address space.
+    let page_cache = if fd == -1 || map_flags.contains(MmapFlags::MAP_ANONYMOUS) {
+        None
+    } else {
+        if offset < 0 || !memory_addr::is_aligned_4k(offset as usize) {
+            return Err(LinuxError::EINVAL);
+        }
+        let file = arceos_posix_api::get_file_like(fd)?
+            .into_any()
+            .downcast::<arceos_posix_api::File>()
+            .map_err(|_| LinuxError::EBADF)?;
+        // The page cache writes through its own handle, so a shared mapping
+        // may only be writable if the descriptor is.
+        let may_write = file.inner().lock().is_writable();
+        if map_flags.contains(MmapFlags::MAP_SHARED)
+            && permission_flags.contains(MmapProt::PROT_WRITE)
+            && !may_write
+        {
+            return Err(LinuxError::EACCES);
+        }
+        Some((crate::mm::page_cache(file.path())?, may_write))
+    };
+
     let start_addr = if map_flags.contains(MmapFlags::MAP_FIXED) {
         if addr.is_null() {
             return Err(LinuxError::EINVAL);
@@ -124,35 +149,23 @@ pub fn sys_mmap(
             .ok_or(LinuxError::ENOMEM)?
     };
 
-    let populate = if fd == -1 {
-        false
+    if let Some((page_cache, may_write)) = page_cache {
+        // Pages are read from the page cache of the file on demand.
+        aspace.map_file(
+            start_addr,
+            aligned_length,
+            permission_flags.into(),
+            page_cache,
+            offset as u64,
+            map_flags.contains(MmapFlags::MAP_SHARED),
+            may_write,
+        )?;
+    } else if map_flags.contains(MmapFlags::MAP_SHARED) {
+        // The frames stay shared with the children forked later.
+        let memory = Arc::new(SharedMemory::new(aligned_length));
+        aspace.map_shared(start_addr, aligned_length, permission_flags.into(), memory)?;
     } else {
-        !map_flags.contains(MmapFlags::MAP_ANONYMOUS)
-    };
-
-    aspace.map_alloc(
-        start_addr,
-        aligned_length,
-        permission_flags.into(),
-        populate,
-    )?;
-
-    if populate {
-        let file = arceos_posix_api::get_file_like(fd)?;
-        let file_size = file.stat()?.st_size as usize;
-        let file = file
-            .into_any()
-            .downcast::<arceos_posix_api::File>()
-            .map_err(|_| LinuxError::EBADF)?;
-        let file = file.inner().lock();
-        if offset < 0 || offset as usize >= file_size {
-            return Err(LinuxError::EINVAL);
-        }
-        let offset = offset as usize;
-        let length = core::cmp::min(length, file_size - offset);
-        let mut buf = vec![0u8; length];
-        file.read_at(offset as u64, &mut buf)?;
-        aspace.write(start_addr, &buf)?;
+        aspace.map_alloc(start_addr, aligned_length, permission_flags.into(), false)?;
     }
     Ok(start_addr.as_usize() as _)
 }
@@ -164,14 +177,62 @@ pub fn sys_munmap(addr: UserPtr<usize>, length: usize) -> LinuxResult<isize> {
 
     let curr = current();
     let curr_ext = curr.task_ext();
-    let mut aspace = curr_ext.aspace.lock();
+    let aspace = curr_ext.aspace();
+    let mut aspace = aspace.lock();
     let length = memory_addr::align_up_4k(length);
     let start_addr = VirtAddr::from(addr as usize);
+    // Write back shared file pages before the mapping goes away.
+    if let Err(e) = aspace.sync(start_addr, length) {
+        warn!("munmap: failed to write back file pages: {:?}", e);
+    }
     aspace.unmap(start_addr, length)?;
     axhal::arch::flush_tlb(None);
     Ok(0)
 }
 
+bitflags::bitflags! {
+    /// flags for sys_msync
+    ///
+    /// See <https://github.com/bminor/glibc/blob/master/bits/mman.h>
+    #[derive(Debug)]
+    struct MsyncFlags: i32 {
+        /// Sync memory asynchronously.
+        const MS_ASYNC = 1 << 0;
+        /// Invalidate the caches.
+        const MS_INVALIDATE = 1 << 1;
+        /// Synchronous memory sync.
+        const MS_SYNC = 1 << 2;
+    }
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_msync(addr: UserPtr<usize>, length: usize, flags: i32) -> LinuxResult<isize> {
+    // Safety: addr is used for syncing mappings, and we won't directly access it.
+    let addr = unsafe { addr.into_inner() } as usize;
+
+    let Some(flags) = MsyncFlags::from_bits(flags) else {
+        return Err(LinuxError::EINVAL);
+    };
+    if flags.contains(MsyncFlags::MS_ASYNC | MsyncFlags::MS_SYNC) || !memory_addr::is_aligned_4k(addr) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let curr = current();
+    let aspace = curr.task_ext().aspace();
+    let mut aspace = aspace.lock();
+    let length = memory_addr::align_up_4k(length);
+    let start_addr = VirtAddr::from(addr);
+    if !aspace.check_region_access(
+        VirtAddrRange::from_start_size(start_addr, length),
+        MappingFlags::empty(),
+    ) {
+        return Err(LinuxError::ENOMEM);
+    }
+    // Writes are done synchronously even for `MS_ASYNC`.
+    aspace.sync(start_addr, length)?;
+    Ok(0)
+}
+
 #[apply(syscall_instrument)]
 pub fn sys_mprotect(addr: UserPtr<usize>, length: usize, prot: i32) -> LinuxResult<isize> {
     // Safety: addr is used for mapping, and we won't directly access it.
@@ -187,7 +248,8 @@ pub fn sys_mprotect(addr: UserPtr<usize>, length: usize, prot: i32) -> LinuxResu
 
     let curr = current();
     let curr_ext = curr.task_ext();
-    let mut aspace = curr_ext.aspace.lock();
+    let aspace = curr_ext.aspace();
+    let mut aspace = aspace.lock();
     let length = memory_addr::align_up_4k(length);
     let start_addr = VirtAddr::from(addr as usize);
     aspace.protect(start_addr, length, permission_flags.into())?;
@@ -1,4 +1,5 @@
 mod fs;
+mod ipc;
 mod mm;
 mod signal;
 mod sys;
@@ -14,6 +15,7 @@ use axhal::{
 use syscalls::Sysno;
 
 use self::fs::*;
+use self::ipc::*;
 use self::mm::*;
 use self::signal::*;
 use self::sys::*;
@@ -74,11 +76,11 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg5() as _,
         ),
         Sysno::futex => sys_futex(
-            tf.arg0().into(),
+            tf.arg0() as _,
             tf.arg1() as _,
             tf.arg2() as _,
-            tf.arg3().into(),
-            tf.arg4().into(),
+            tf.arg3() as _,
+            tf.arg4() as _,
             tf.arg5() as _,
         ),
         Sysno::ioctl => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2().into()),
@@ -87,14 +89,16 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
         Sysno::nanosleep => sys_nanosleep(tf.arg0().into(), tf.arg1().into()),
         Sysno::getpid => sys_getpid(),
         Sysno::getppid => sys_getppid(),
+        Sysno::getpgid => sys_getpgid(tf.arg0() as _),
+        Sysno::setpgid => sys_setpgid(tf.arg0() as _, tf.arg1() as _),
         Sysno::gettid => sys_gettid(),
         Sysno::exit => sys_exit(tf.arg0() as _),
         Sysno::gettimeofday => sys_get_time_of_day(tf.arg0().into()),
         Sysno::getcwd => sys_getcwd(tf.arg0().into(), tf.arg1() as _),
         Sysno::dup => sys_dup(tf.arg0() as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::dup2 => sys_dup3(tf.arg0() as _, tf.arg1() as _),
-        Sysno::dup3 => sys_dup3(tf.arg0() as _, tf.arg1() as _),
+        Sysno::dup2 => sys_dup2(tf.arg0() as _, tf.arg1() as _),
+        Sysno::dup3 => sys_dup3(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::fcntl => sys_fcntl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::clone => sys_clone(
             tf.arg0() as _,
@@ -111,17 +115,40 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             0,
             0,
         ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::vfork => sys_clone(
+            (crate::ctypes::CloneFlags::CLONE_VM | crate::ctypes::CloneFlags::CLONE_VFORK).bits()
+                as usize
+                | crate::signal::SIGCHLD,
+            0,
+            0,
+            0,
+            0,
+        ),
         Sysno::lseek => sys_lseek(
             tf.arg0() as _,
             tf.arg1() as _,
             tf.arg2() as _,
         ),
         #[cfg(target_arch = "x86_64")]
-        Sysno::pipe => sys_pipe2(tf.arg0().into()),
+        Sysno::pipe => sys_pipe2(tf.arg0().into(), 0),
         #[cfg(target_arch = "x86_64")]
         Sysno::unlink => sys_unlink(tf.arg0().into()),
-        Sysno::wait4 => sys_wait4(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
-        Sysno::pipe2 => sys_pipe2(tf.arg0().into()),
+        Sysno::wait4 => sys_wait4(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+        ),
+        Sysno::waitid => sys_waitid(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3() as _,
+            tf.arg4().into(),
+        ),
+        Sysno::pidfd_open => sys_pidfd_open(tf.arg0() as _, tf.arg1() as _),
+        Sysno::pipe2 => sys_pipe2(tf.arg0().into(), tf.arg1() as _),
         Sysno::close => sys_close(tf.arg0() as _),
         Sysno::chdir => sys_chdir(tf.arg0().into()),
         Sysno::mkdirat => sys_mkdirat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
@@ -143,6 +170,17 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg4() as _,
         ),
         Sysno::unlinkat => sys_unlinkat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::symlinkat => sys_symlinkat(tf.arg0().into(), tf.arg1() as _, tf.arg2().into()),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::symlink => sys_symlink(tf.arg0().into(), tf.arg1().into()),
+        Sysno::readlinkat => sys_readlinkat(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::readlink => sys_readlink(tf.arg0().into(), tf.arg1().into(), tf.arg2() as _),
         Sysno::uname => sys_uname(tf.arg0().into()),
         Sysno::fstat => sys_fstat(tf.arg0() as _, tf.arg1().into()),
         Sysno::mount => sys_mount(
@@ -165,6 +203,8 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg0().into(),
             tf.arg1().into(),
         ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::lstat => sys_lstat(tf.arg0().into(), tf.arg1().into()),
         #[cfg(not(target_arch = "x86_64"))]
         Sysno::fstatat => sys_fstatat(
             tf.arg0() as _,
@@ -181,18 +221,83 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
         ),
         Sysno::munmap => sys_munmap(tf.arg0().into(), tf.arg1() as _),
         Sysno::mprotect => sys_mprotect(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::msync => sys_msync(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::shmget => sys_shmget(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::shmat => sys_shmat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::shmdt => sys_shmdt(tf.arg0() as _),
+        Sysno::shmctl => sys_shmctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::semget => sys_semget(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::semop => sys_semop(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::semtimedop => sys_semtimedop(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::semctl => sys_semctl(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::msgget => sys_msgget(tf.arg0() as _, tf.arg1() as _),
+        Sysno::msgsnd => sys_msgsnd(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::msgrcv => sys_msgrcv(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ),
+        Sysno::msgctl => sys_msgctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::mq_open => sys_mq_open(
+            tf.arg0().into(),
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::mq_unlink => sys_mq_unlink(tf.arg0().into()),
+        Sysno::mq_timedsend => sys_mq_timedsend(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ),
+        Sysno::mq_timedreceive => sys_mq_timedreceive(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ),
+        Sysno::mq_notify => sys_mq_notify(tf.arg0() as _, tf.arg1() as _),
+        Sysno::mq_getsetattr => sys_mq_getsetattr(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::times => sys_times(tf.arg0().into()),
         Sysno::brk => sys_brk(tf.arg0() as _),
         Sysno::statfs => sys_statfs(
             tf.arg0().into(),
             tf.arg1().into(),
         ),
+        Sysno::fstatfs => sys_fstatfs(tf.arg0() as _, tf.arg1().into()),
         #[cfg(target_arch = "x86_64")]
         Sysno::arch_prctl => sys_arch_prctl(tf.arg0() as _, tf.arg1().into()),
         Sysno::set_tid_address => sys_set_tid_address(tf.arg0().into()),
+        Sysno::set_robust_list => sys_set_robust_list(tf.arg0() as _, tf.arg1() as _),
+        Sysno::get_robust_list => {
+            sys_get_robust_list(tf.arg0() as _, tf.arg1().into(), tf.arg2().into())
+        }
         Sysno::clock_gettime => sys_clock_gettime(tf.arg0() as _, tf.arg1().into()),
         Sysno::exit_group => sys_exit_group(tf.arg0() as _),
         Sysno::getuid => sys_getuid(),
+        Sysno::getgid => sys_getgid(),
+        Sysno::getresuid => sys_getresuid(tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
+        Sysno::getresgid => sys_getresgid(tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
         Sysno::prlimit64 => sys_prlimit64(
             tf.arg0() as _,
             tf.arg1() as _,
@@ -220,6 +325,14 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg0() as _,
             tf.arg1() as _,
         ),
+        Sysno::tkill => sys_tkill(tf.arg0() as _, tf.arg1() as _),
+        Sysno::tgkill => sys_tgkill(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::rt_sigqueueinfo => sys_rt_sigqueueinfo(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+        ),
+        Sysno::rt_sigpending => sys_rt_sigpending(tf.arg0().into(), tf.arg1() as _),
         Sysno::rt_sigreturn => sys_rt_sigreturn(),
         Sysno::geteuid => sys_geteuid(),
         Sysno::getegid => sys_getegid(),
@@ -296,7 +409,7 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
         ),
         _ => {
             warn!("Unimplemented syscall: {}", syscall_num);
-            axtask::exit(LinuxError::ENOSYS as _)
+            Err(LinuxError::ENOSYS)
         }
     };
     let ans = result.unwrap_or_else(|err| -err.code() as _);
@@ -1,74 +1,111 @@
-use core::ffi::c_void;
+use alloc::{vec, vec::Vec};
+use core::{ffi::c_void, mem::size_of};
 
-use axerrno::LinuxResult;
-use axhal::trap::DEAL_SIGNAL;
-use axtask::{current, yield_now, TaskExtRef};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::{
+    arch::TrapFrame,
+    trap::{DEAL_SIGNAL, USER_EXCEPTION, UserException},
+};
+use axtask::{current, TaskExtRef};
 use crate::syscall_imp::register_trap_handler;
-use crate::{ctypes::{SigAction, SignalFlags, SignalSet}, ptr::{PtrWrapper, UserConstPtr, UserPtr}, task::get_task_by_id};
+use crate::{
+    ctypes::{
+        BUS_ADRALN, FPE_FLTUNK, FPE_INTDIV, ILL_ILLOPC, ILL_PRVOPC, SEGV_ACCERR, SI_TKILL,
+        SI_USER, SigAction, SiginfoT, SignalFlags, SignalSet,
+    },
+    ptr::{PtrWrapper, UserConstPtr, UserPtr},
+    signal::{
+        SIGBUS, SIGFPE, SIGILL, SIGSEGV, UNBLOCKABLE_SIGNALS, force_signal, handle_signals,
+        restore_frame,
+    },
+    task::{
+        all_tasks, exit_current, get_task_by_id, init_pid, read_trapframe_from_kstack,
+        write_trapframe_to_kstack,
+    },
+};
 
 
 #[register_trap_handler(DEAL_SIGNAL)]
-pub fn dealwith_signal(){
-    loop{
-        check_pending_signals();
-        let(frozen, kill) = {
-            let curr = current();
-            (curr.task_ext().frozen, curr.task_ext().killed)
-        };
-        if !frozen || !kill{
+pub fn dealwith_signal(tf: &mut TrapFrame) {
+    let curr = current();
+    loop {
+        if curr.task_ext().process.is_group_exiting() {
+            exit_current(0);
+        }
+        handle_signals(tf);
+        if !curr.task_ext().is_frozen() {
             break;
         }
-        yield_now();
+        // Stopped: wait for SIGCONT or SIGKILL, then handle the signals again.
+        curr.task_ext().wait_while_frozen();
     }
 }
 
-fn check_pending_signals(){
-
+/// Raises the fault signal of a user exception, to be delivered before
+/// returning to user space.
+#[register_trap_handler(USER_EXCEPTION)]
+fn handle_user_exception(kind: UserException, addr: usize) -> bool {
+    warn!(
+        "{}: user exception {:?} at {:#x}",
+        current().id_name(),
+        kind,
+        addr
+    );
+    let (signum, code) = match kind {
+        UserException::IllegalInstruction => (SIGILL, ILL_ILLOPC),
+        UserException::PrivilegedInstruction => (SIGILL, ILL_PRVOPC),
+        UserException::Misaligned => (SIGBUS, BUS_ADRALN),
+        UserException::AccessFault => (SIGSEGV, SEGV_ACCERR),
+        UserException::DivideByZero => (SIGFPE, FPE_INTDIV),
+        UserException::FloatingPoint => (SIGFPE, FPE_FLTUNK),
+    };
+    force_signal(signum, code, addr);
+    true
 }
+
 pub fn sys_rt_sigprocmask(
-    _how: i32,
-    _set: UserPtr<u64>,
-    _oldset: UserPtr<u64>,
-    _sigsetsize: usize,
+    how: i32,
+    set: UserConstPtr<u64>,
+    oldset: UserPtr<u64>,
+    sigsetsize: usize,
 ) -> LinuxResult<isize> {
-    let curr = current();
-    let new_set = _set.get();
-    let old_set = _oldset.get();
-    match old_set{
-        Ok(old_set) => {
-            unsafe{ *old_set = curr.task_ext().get_mask().bits();}
-            info!("old_set: {:?}", curr.task_ext().get_mask());            
-        }
-        Err(e) => {
-            info!("old_set: {:?}", e);
-        }
+    if sigsetsize != size_of::<u64>() {
+        return Err(LinuxError::EINVAL);
     }
-    match new_set {
-        Ok(new_set) => {
-            let flag = SignalFlags::from_bits(unsafe {*new_set} as u64).unwrap();
-            info!("new_set: {:?}", flag);
-            match SignalSet::try_from(_how).unwrap() {
-                SignalSet::SigBlock => {
-                    // Block signals
-                    curr
-                    .task_ext().add_mask(flag);
-                }
-                SignalSet::SigUnblock => {
-                    // Unblock signals
-                    curr
-                    .task_ext().del_mask(flag);
-                }
-                SignalSet::SigSetmask => {
-                    // Set the signal mask
-                    curr.task_ext().set_mask(flag);
-                }
+    let curr = current();
+    let old_mask = curr.task_ext().get_mask();
+    if let Some(set) = set.nullable(UserConstPtr::get)? {
+        // SIGKILL and SIGSTOP cannot be blocked, and are silently ignored.
+        let flag = SignalFlags::from_bits_retain(unsafe { *set }) - UNBLOCKABLE_SIGNALS;
+        info!("new_set: {:?}", flag);
+        match SignalSet::try_from(how).map_err(|_| LinuxError::EINVAL)? {
+            SignalSet::SigBlock => {
+                // Block signals
+                curr.task_ext().add_mask(flag);
+            }
+            SignalSet::SigUnblock => {
+                // Unblock signals
+                curr.task_ext().del_mask(flag);
+            }
+            SignalSet::SigSetmask => {
+                // Set the signal mask
+                curr.task_ext().set_mask(flag);
             }
-        }
-        Err(e) => {
-            info!("new_set: {:?}", e);
         }
     }
+    if let Some(oldset) = oldset.nullable(UserPtr::get)? {
+        unsafe { *oldset = old_mask.bits() };
+    }
+    Ok(0)
+}
 
+pub fn sys_rt_sigpending(set: UserPtr<u64>, sigsetsize: usize) -> LinuxResult<isize> {
+    if sigsetsize != size_of::<u64>() {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current();
+    let pending = curr.task_ext().get_pending() & curr.task_ext().get_mask();
+    unsafe { *set.get()? = pending.bits() };
     Ok(0)
 }
 
@@ -79,21 +116,20 @@ pub fn sys_rt_sigaction(
     _sigsetsize: usize,
 ) -> LinuxResult<isize> {
     let curr = current();
-    let new_act = _act.get();   
-    let old_act = _oldact.get();
-    if let Some(signal) = SignalFlags::from_bits(_signum as u64) {
-        if signal != SignalFlags::SIGKILL && signal != SignalFlags::SIGSTOP {
-            if let Ok(new_act) = new_act {
-                if let Ok(old_act) = old_act {
-                    unsafe { *old_act = curr.task_ext().get_signal_action(_signum as usize) }
-                }
-                curr.task_ext().set_signal_action(_signum as usize, new_act);
-            } else {
-                return Err(axerrno::LinuxError::EINVAL);
-            }
-        } 
+    let signum = _signum as usize;
+    let Some(signal) = SignalFlags::from_signum(signum) else {
+        return Err(LinuxError::EINVAL);
+    };
+    let new_act = _act.nullable(UserConstPtr::get)?;
+    if let Some(old_act) = _oldact.nullable(UserPtr::get)? {
+        unsafe { *old_act = curr.task_ext().get_signal_action(signum) }
+    }
+    if let Some(new_act) = new_act {
+        if signal == SignalFlags::SIGKILL || signal == SignalFlags::SIGSTOP {
+            return Err(LinuxError::EINVAL);
+        }
+        curr.task_ext().set_signal_action(signum, new_act);
     }
-    // warn!("sys_rt_sigaction: not implemented");
     Ok(0)
 }
 
@@ -107,17 +143,117 @@ pub fn sys_rt_sigtimedwait(
 }
 
 pub fn sys_rt_sigreturn() -> LinuxResult<isize> {
-    
+    let curr = current();
+    let kstack_top = curr.get_kernel_stack_top().unwrap();
+    let mut tf = read_trapframe_from_kstack(kstack_top);
+    let ret = restore_frame(&mut tf)?;
+    write_trapframe_to_kstack(kstack_top, &tf);
+    Ok(ret)
+}
+
+/// Checks the signal number of kill-like syscalls.
+///
+/// Returns `None` for the null signal 0, which only checks that the target
+/// exists.
+fn check_signum(sig: i32) -> LinuxResult<Option<usize>> {
+    if sig == 0 {
+        return Ok(None);
+    }
+    SignalFlags::from_signum(sig as usize)
+        .map(|_| Some(sig as usize))
+        .ok_or(LinuxError::EINVAL)
+}
+
+pub fn sys_kill(pid: i32, sig: i32) -> LinuxResult<isize> {
+    let signum = check_signum(sig)?;
+    let curr = current();
+    let targets = match pid {
+        pid if pid > 0 => vec![get_task_by_id(pid as _).ok_or(LinuxError::ESRCH)?],
+        -1 => all_tasks()
+            .into_iter()
+            .filter(|task| {
+                // Signal every process once, through its group leader.
+                let proc_id = task.task_ext().proc_id();
+                task.task_ext().tid() == proc_id
+                    && proc_id != init_pid()
+                    && proc_id != curr.task_ext().proc_id()
+            })
+            .collect(),
+        pid => {
+            let pgid = match pid {
+                0 => curr.task_ext().process.pgid(),
+                pid => pid.unsigned_abs() as usize,
+            };
+            let targets: Vec<_> = all_tasks()
+                .into_iter()
+                .filter(|task| {
+                    let process = &task.task_ext().process;
+                    task.task_ext().tid() == process.pid() && process.pgid() == pgid
+                })
+                .collect();
+            if targets.is_empty() {
+                return Err(LinuxError::ESRCH);
+            }
+            targets
+        }
+    };
+    if let Some(signum) = signum {
+        for task in targets {
+            let mut info = SiginfoT::new(signum, SI_USER);
+            info.set_sender(curr.task_ext().proc_id() as _, 0);
+            task.task_ext().send_signal_to_process(info);
+        }
+    }
+    Ok(0)
+}
+
+pub fn sys_tkill(tid: i32, sig: i32) -> LinuxResult<isize> {
+    if tid <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let signum = check_signum(sig)?;
+    let task = get_task_by_id(tid as _).ok_or(LinuxError::ESRCH)?;
+    if let Some(signum) = signum {
+        let mut info = SiginfoT::new(signum, SI_TKILL);
+        info.set_sender(current().task_ext().proc_id() as _, 0);
+        task.task_ext().send_signal_to_thread(info);
+    }
     Ok(0)
 }
 
-pub fn sys_kill(_pid: usize, _sig: i32) -> LinuxResult<isize> {
-    // warn!("sys_kill: not implemented");
-    if let Some(task) = get_task_by_id(_pid as _) {
-        let _sig = SignalFlags::from_bits(_sig as u64).unwrap();
-        task.task_ext().add_mask(_sig as _);
-    }else{
-        warn!("sys_kill: task not found");
+pub fn sys_tgkill(tgid: i32, tid: i32, sig: i32) -> LinuxResult<isize> {
+    if tgid <= 0 || tid <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let task = get_task_by_id(tid as _)
+        .filter(|task| task.task_ext().proc_id() == tgid as usize)
+        .ok_or(LinuxError::ESRCH)?;
+    if let Some(signum) = check_signum(sig)? {
+        let mut info = SiginfoT::new(signum, SI_TKILL);
+        info.set_sender(current().task_ext().proc_id() as _, 0);
+        task.task_ext().send_signal_to_thread(info);
     }
     Ok(0)
-}
\ No newline at end of file
+}
+
+pub fn sys_rt_sigqueueinfo(
+    pid: i32,
+    sig: i32,
+    uinfo: UserConstPtr<SiginfoT>,
+) -> LinuxResult<isize> {
+    let signum = check_signum(sig)?;
+    let mut info = unsafe { *uinfo.get()? };
+    let curr = current();
+    // Only the kernel may send signals with a non-negative `si_code`, unless
+    // a process signals itself.
+    if (info.si_code >= 0 || info.si_code == SI_TKILL) && pid as usize != curr.task_ext().proc_id()
+    {
+        return Err(LinuxError::EPERM);
+    }
+    let task = get_task_by_id(pid as _).ok_or(LinuxError::ESRCH)?;
+    if let Some(signum) = signum {
+        info.si_signo = signum as _;
+        task.task_ext().send_signal_to_process(info);
+    }
+    Ok(0)
+}
@@ -3,10 +3,43 @@ use alloc::{borrow::ToOwned, ffi::CString, sync::Arc};
 use arceos_posix_api::{self as api, get_file_like, handle_file_path, File};
 use axerrno::LinuxResult;
 use axfs::api::OpenOptions;
+use axtask::{TaskExtRef, current};
 
 use crate::{ctypes::{UTIME_NOW, UTIME_OMIT}, ptr::{PtrWrapper, UserConstPtr, UserPtr}};
 
 pub fn sys_getuid() -> LinuxResult<isize> {
+    Ok(current().task_ext().process.cred().uid as _)
+}
+
+pub fn sys_getgid() -> LinuxResult<isize> {
+    Ok(current().task_ext().process.cred().gid as _)
+}
+
+pub fn sys_getresuid(
+    ruid: UserPtr<u32>,
+    euid: UserPtr<u32>,
+    suid: UserPtr<u32>,
+) -> LinuxResult<isize> {
+    let cred = current().task_ext().process.cred();
+    unsafe {
+        *ruid.get()? = cred.uid;
+        *euid.get()? = cred.euid;
+        *suid.get()? = cred.suid;
+    }
+    Ok(0)
+}
+
+pub fn sys_getresgid(
+    rgid: UserPtr<u32>,
+    egid: UserPtr<u32>,
+    sgid: UserPtr<u32>,
+) -> LinuxResult<isize> {
+    let cred = current().task_ext().process.cred();
+    unsafe {
+        *rgid.get()? = cred.gid;
+        *egid.get()? = cred.egid;
+        *sgid.get()? = cred.sgid;
+    }
     Ok(0)
 }
 
@@ -53,11 +86,11 @@ pub fn sys_uname(name: UserPtr<UtsName>) -> LinuxResult<isize> {
 }
 
 pub fn sys_geteuid() -> LinuxResult<isize> {
-    Ok(1000)
+    Ok(current().task_ext().process.cred().euid as _)
 }
 
 pub fn sys_getegid() -> LinuxResult<isize> {
-    Ok(1000)
+    Ok(current().task_ext().process.cred().egid as _)
 }
 
 pub fn sys_utimensat(fd:isize, path:UserConstPtr<c_char>, times: UserPtr<[api::ctypes::timespec;2]>, flags: usize) -> LinuxResult<isize> {
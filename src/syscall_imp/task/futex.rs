@@ -0,0 +1,131 @@
+use core::{mem::size_of, time::Duration};
+
+use arceos_posix_api as api;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{TimeValue, monotonic_time, wall_time};
+use macro_rules_attribute::apply;
+
+use axtask::{TaskExtRef, current};
+
+use crate::{
+    ctypes::{
+        FUTEX_BITSET_MATCH_ANY, FUTEX_CLOCK_REALTIME, FUTEX_PRIVATE_FLAG, FutexOp,
+        RobustListHead,
+    },
+    futex::{
+        FutexKey, futex_lock_pi, futex_requeue, futex_unlock_pi, futex_wait, futex_wake,
+        futex_wake_op,
+    },
+    ptr::{PtrWrapper, UserConstPtr, UserPtr},
+    syscall_imp::syscall_instrument,
+    task::get_task_by_id,
+};
+
+fn read_timeout(timeout: usize) -> LinuxResult<Option<Duration>> {
+    if timeout == 0 {
+        return Ok(None);
+    }
+    let ts = unsafe { *UserConstPtr::<api::ctypes::timespec>::from(timeout).get()? };
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)))
+}
+
+/// Converts an absolute timeout on the clock chosen by `realtime` to a
+/// deadline on the monotonic clock.
+fn absolute_deadline(timeout: Duration, realtime: bool) -> TimeValue {
+    let now = if realtime { wall_time() } else { monotonic_time() };
+    monotonic_time() + timeout.saturating_sub(now)
+}
+
+/// The futex syscall.
+///
+/// `timeout` is a pointer to a `timespec` for the wait operations, and the
+/// number of waiters to requeue or wake on `uaddr2` (`val2`) for the others.
+#[apply(syscall_instrument)]
+pub fn sys_futex(
+    uaddr: usize,
+    futex_op: u32,
+    val: u32,
+    timeout: usize,
+    uaddr2: usize,
+    val3: u32,
+) -> LinuxResult<isize> {
+    let shared = futex_op & FUTEX_PRIVATE_FLAG == 0;
+    let realtime = futex_op & FUTEX_CLOCK_REALTIME != 0;
+    let op = FutexOp::try_from(futex_op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME))
+        .map_err(|_| LinuxError::ENOSYS)?;
+    if realtime && !matches!(op, FutexOp::Wait | FutexOp::WaitBitset) {
+        return Err(LinuxError::ENOSYS);
+    }
+    let key = FutexKey::new(uaddr, shared)?;
+    let val2 = timeout as u32 as i32;
+    match op {
+        FutexOp::Wait => {
+            let deadline = read_timeout(timeout)?.map(|timeout| monotonic_time() + timeout);
+            futex_wait(uaddr, key, val, FUTEX_BITSET_MATCH_ANY, deadline)
+        }
+        FutexOp::WaitBitset => {
+            if val3 == 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            let deadline =
+                read_timeout(timeout)?.map(|timeout| absolute_deadline(timeout, realtime));
+            futex_wait(uaddr, key, val, val3, deadline)
+        }
+        FutexOp::Wake => Ok(futex_wake(key, val as usize, FUTEX_BITSET_MATCH_ANY) as isize),
+        FutexOp::WakeBitset => {
+            if val3 == 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok(futex_wake(key, val as usize, val3) as isize)
+        }
+        FutexOp::Requeue | FutexOp::CmpRequeue => {
+            if (val as i32) < 0 || val2 < 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            let key2 = FutexKey::new(uaddr2, shared)?;
+            let cmp = (op == FutexOp::CmpRequeue).then_some((uaddr, val3));
+            futex_requeue(key, key2, val as usize, val2 as usize, cmp)
+        }
+        FutexOp::WakeOp => {
+            let key2 = FutexKey::new(uaddr2, shared)?;
+            futex_wake_op(key, key2, uaddr2, val as usize, val2.max(0) as usize, val3)
+        }
+        FutexOp::LockPi => {
+            // The timeout is always absolute on CLOCK_REALTIME.
+            let deadline = read_timeout(timeout)?.map(|timeout| absolute_deadline(timeout, true));
+            futex_lock_pi(uaddr, key, deadline, false)
+        }
+        FutexOp::TrylockPi => futex_lock_pi(uaddr, key, None, true),
+        FutexOp::UnlockPi => futex_unlock_pi(uaddr, key),
+    }
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_set_robust_list(head: usize, len: usize) -> LinuxResult<isize> {
+    if len != size_of::<RobustListHead>() {
+        return Err(LinuxError::EINVAL);
+    }
+    current().task_ext().set_robust_list(head);
+    Ok(0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_get_robust_list(
+    tid: usize,
+    head_ptr: UserPtr<usize>,
+    len_ptr: UserPtr<usize>,
+) -> LinuxResult<isize> {
+    let task = if tid == 0 {
+        current().as_task_ref().clone()
+    } else {
+        get_task_by_id(tid).ok_or(LinuxError::ESRCH)?
+    };
+    unsafe {
+        *head_ptr.get()? = task.task_ext().robust_list();
+        *len_ptr.get()? = size_of::<RobustListHead>();
+    }
+    Ok(0)
+}
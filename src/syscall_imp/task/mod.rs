@@ -1,5 +1,7 @@
+mod futex;
 mod schedule;
 mod thread;
 
+pub(crate) use self::futex::*;
 pub(crate) use self::schedule::*;
 pub(crate) use self::thread::*;
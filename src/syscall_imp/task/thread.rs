@@ -1,16 +1,20 @@
-use core::{ffi::{c_char, c_int}, ptr};
+use core::ffi::{c_char, c_int};
 
-use alloc::vec::Vec;
-use arceos_posix_api::{query_futex, add_futex, remove_futex};
+use alloc::{sync::Arc, vec::Vec};
+use arceos_posix_api::{PidFd, ctypes::timeval};
 use axerrno::{LinuxError, LinuxResult};
-use axtask::{TaskExtRef, current, yield_now};
+use axhal::time::{NANOS_PER_MICROS, NANOS_PER_SEC};
+use axtask::{TaskExtRef, current};
 use macro_rules_attribute::apply;
 use num_enum::TryFromPrimitive;
 use crate::{
-    ctypes::{FluxStatus, WaitFlags, WaitStatus},
+    ctypes::{Rusage, SiginfoT, WaitFlags, WaitIdType},
     ptr::{PtrWrapper, UserConstPtr, UserPtr},
+    signal::SIGCHLD,
     syscall_imp::syscall_instrument,
-    task::{get_fdlimit, wait_pid},
+    task::{
+        WaitTarget, all_tasks, exit_current, get_fdlimit, get_task_by_id, wait_child,
+    },
 };
 
 /// ARCH_PRCTL codes
@@ -38,12 +42,12 @@ enum ArchPrctlCode {
 
 #[apply(syscall_instrument)]
 pub fn sys_getpid() -> LinuxResult<isize> {
-    Ok(axtask::current().task_ext().proc_id as _)
+    Ok(axtask::current().task_ext().proc_id() as _)
 }
 
 #[apply(syscall_instrument)]
 pub fn sys_gettid() -> LinuxResult<isize> {
-    Ok(axtask::current().id().get_id() as isize)
+    Ok(axtask::current().task_ext().tid() as isize)
 }
 
 #[apply(syscall_instrument)]
@@ -63,7 +67,7 @@ pub fn sys_prlimit64(
             arceos_posix_api::ctypes::RLIMIT_NOFILE => {
                 let task = axtask::current();
                 let limit = unsafe { *new_limit };
-                task.task_ext().set_fdlimit(limit.rlim_cur as _);
+                task.task_ext().process.set_fdlimit(limit.rlim_cur as _);
                 // let mut task = axtask::current().task_ext();
                 // task.set_fd_limit(unsafe{(*rlimits).rlim_cur as _});
             }
@@ -97,22 +101,12 @@ pub fn sys_getppid() -> LinuxResult<isize> {
 }
 
 pub fn sys_exit(status: i32) -> ! {
-    let curr = current();
-    let clear_child_tid = curr.task_ext().clear_child_tid() as *mut i32;
-    if !clear_child_tid.is_null() {
-        // TODO: check whether the address is valid
-        unsafe {
-            // TODO: Encapsulate all operations that access user-mode memory into a unified function
-            *(clear_child_tid) = 0;
-        }
-        // TODO: wake up threads, which are blocked by futex, and waiting for the address pointed by clear_child_tid
-    }
-    axtask::exit(status);
+    exit_current(status)
 }
 
 pub fn sys_exit_group(status: i32) -> ! {
-    warn!("Temporarily replace sys_exit_group with sys_exit");
-    axtask::exit(status);
+    current().task_ext().process.group_exit(status);
+    exit_current(status)
 }
 
 /// To set the clear_child_tid field in the task extended data.
@@ -124,7 +118,7 @@ pub fn sys_set_tid_address(tid_ptd: UserConstPtr<i32>) -> LinuxResult<isize> {
     let curr = current();
     curr.task_ext()
         .set_clear_child_tid(tid_ptd.address().as_ptr() as _);
-    Ok(curr.id().as_u64() as isize)
+    Ok(curr.task_ext().tid() as isize)
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -172,52 +166,160 @@ pub fn sys_clone(
     arg3: usize,
     arg4: usize,
 ) -> LinuxResult<isize> {
-    let tls = arg3;
-    let ctid = arg4;
+    // x86_64 passes `ctid` before `tls`, unlike the other architectures.
+    #[cfg(target_arch = "x86_64")]
+    let (ctid, tls) = (arg3, arg4);
+    #[cfg(not(target_arch = "x86_64"))]
+    let (tls, ctid) = (arg3, arg4);
     let curr_task = current();    
     let stack = if user_stack == 0 {
         None
     } else {
         Some(user_stack)
     };
-    if let Ok(new_task_id) = curr_task
+    let new_task_id = curr_task
         .task_ext()
-        .clone_task(flags, stack, ptid, tls, ctid)
-    {
-        Ok(new_task_id as isize)
-    } else {
-        Err(LinuxError::ENOMEM)
+        .clone_task(flags, stack, ptid, tls, ctid)?;
+    Ok(new_task_id as isize)
+}
+
+fn nanos_to_timeval(nanos: usize) -> timeval {
+    timeval {
+        tv_sec: (nanos / NANOS_PER_SEC as usize) as _,
+        tv_usec: (nanos % NANOS_PER_SEC as usize / NANOS_PER_MICROS as usize) as _,
     }
 }
 
+fn write_rusage(rusage: UserPtr<Rusage>, (utime, stime): (usize, usize)) -> LinuxResult<()> {
+    if let Some(rusage) = rusage.nullable(UserPtr::get)? {
+        unsafe {
+            *rusage = Rusage {
+                ru_utime: nanos_to_timeval(utime),
+                ru_stime: nanos_to_timeval(stime),
+                ..Default::default()
+            };
+        }
+    }
+    Ok(())
+}
+
 #[apply(syscall_instrument)]
-pub fn sys_wait4(pid: i32, exit_code_ptr: UserPtr<i32>, option: u32) -> LinuxResult<isize> {
-    let option_flag = WaitFlags::from_bits(option).unwrap();
-    let exit_code_ptr = exit_code_ptr.nullable(UserPtr::get)?;
-    loop {
-        let answer = wait_pid(pid, exit_code_ptr.unwrap_or_else(ptr::null_mut));
-        match answer {
-            Ok(pid) => {
-                return Ok(pid as isize);
-            }
-            Err(status) => match status {
-                WaitStatus::NotExist => {
-                    return Err(LinuxError::ECHILD);
-                }
-                WaitStatus::Running => {
-                    if option_flag.contains(WaitFlags::WNOHANG) {
-                        return Ok(0);
-                    } else {
+pub fn sys_wait4(
+    pid: i32,
+    exit_code_ptr: UserPtr<i32>,
+    option: u32,
+    rusage: UserPtr<Rusage>,
+) -> LinuxResult<isize> {
+    let options = WaitFlags::from_bits_truncate(option) | WaitFlags::WEXITED;
+    let target = match pid {
+        -1 => WaitTarget::Any,
+        0 => WaitTarget::Pgid(current().task_ext().process.pgid()),
+        pid if pid > 0 => WaitTarget::Pid(pid as usize),
+        pid => WaitTarget::Pgid(pid.unsigned_abs() as usize),
+    };
+    let Some((event, times)) = wait_child(target, options)? else {
+        return Ok(0);
+    };
+    if let Some(exit_code_ptr) = exit_code_ptr.nullable(UserPtr::get)? {
+        unsafe { *exit_code_ptr = event.wait_status() };
+    }
+    write_rusage(rusage, times)?;
+    Ok(event.pid as isize)
+}
 
-                        yield_now();
-                    }
-                }
-                _ => {
-                    panic!("Shouldn't reach here!");
-                }
-            },
-        }
+#[apply(syscall_instrument)]
+pub fn sys_waitid(
+    idtype: u32,
+    id: i32,
+    infop: UserPtr<SiginfoT>,
+    options: u32,
+    rusage: UserPtr<Rusage>,
+) -> LinuxResult<isize> {
+    let options = WaitFlags::from_bits(options).ok_or(LinuxError::EINVAL)?;
+    if !options.intersects(WaitFlags::WEXITED | WaitFlags::WIMTRACED | WaitFlags::WCONTINUED) {
+        return Err(LinuxError::EINVAL);
+    }
+    let target = match WaitIdType::try_from(idtype).map_err(|_| LinuxError::EINVAL)? {
+        WaitIdType::P_ALL => WaitTarget::Any,
+        WaitIdType::P_PID if id > 0 => WaitTarget::Pid(id as usize),
+        WaitIdType::P_PGID if id > 0 => WaitTarget::Pgid(id as usize),
+        WaitIdType::P_PGID if id == 0 => WaitTarget::Pgid(current().task_ext().process.pgid()),
+        WaitIdType::P_PIDFD => WaitTarget::Pid(PidFd::from_fd(id)?.pid()),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let result = wait_child(target, options)?;
+    if let Some(infop) = infop.nullable(UserPtr::get)? {
+        // Without a child to report, the fields are zeroed.
+        let info = result.map_or(SiginfoT::new(0, 0), |(event, _)| {
+            let mut info = SiginfoT::new(SIGCHLD, event.code);
+            info.set_sender(event.pid as u32, 0);
+            info.set_status(event.status);
+            info
+        });
+        unsafe { *infop = info };
     }
+    write_rusage(rusage, result.map_or((0, 0), |(_, times)| times))?;
+    Ok(0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_getpgid(pid: i32) -> LinuxResult<isize> {
+    let curr = current();
+    let task = match pid {
+        0 => curr.as_task_ref().clone(),
+        pid if pid > 0 => get_task_by_id(pid as usize).ok_or(LinuxError::ESRCH)?,
+        _ => return Err(LinuxError::ESRCH),
+    };
+    Ok(task.task_ext().process.pgid() as isize)
+}
+
+/// Moves the process `pid` (or the caller if 0) into the process group `pgid`
+/// (or the one of its own ID if 0).
+///
+/// The process must be the caller or one of its children, and the group must
+/// either exist or be created by the process itself.
+#[apply(syscall_instrument)]
+pub fn sys_setpgid(pid: i32, pgid: i32) -> LinuxResult<isize> {
+    if pid < 0 || pgid < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current();
+    let process = &curr.task_ext().process;
+    let target = if pid == 0 || pid as usize == process.pid() {
+        process.clone()
+    } else {
+        process
+            .children
+            .lock()
+            .iter()
+            .map(|child| child.task_ext().process.clone())
+            .find(|child| child.pid() == pid as usize)
+            .ok_or(LinuxError::ESRCH)?
+    };
+    let pgid = if pgid == 0 { target.pid() } else { pgid as usize };
+    if pgid != target.pid()
+        && !all_tasks()
+            .iter()
+            .any(|task| task.task_ext().process.pgid() == pgid)
+    {
+        return Err(LinuxError::EPERM);
+    }
+    target.set_pgid(pgid);
+    Ok(0)
+}
+
+/// Creates a pidfd referring to the process `pid`.
+#[apply(syscall_instrument)]
+pub fn sys_pidfd_open(pid: i32, flags: u32) -> LinuxResult<isize> {
+    if pid <= 0 || flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let process = get_task_by_id(pid as usize)
+        .map(|task| task.task_ext().process.clone())
+        .filter(|process| process.pid() == pid as usize)
+        .ok_or(LinuxError::ESRCH)?;
+    let pidfd = PidFd::new(pid as usize, Arc::new(move || process.is_zombie()));
+    Ok(pidfd.add_to_fd_table()? as isize)
 }
 
 #[apply(syscall_instrument)]
@@ -252,50 +354,6 @@ pub fn sys_execve(
         path_str, args, envs
     );
 
-    if let Err(e) = crate::task::exec(path_str, &args, &envs) {
-        error!("Failed to exec: {:?}", e);
-        return Err::<isize, _>(LinuxError::ENOSYS);
-    }
-
+    crate::task::exec(path_str, &args, &envs)?;
     unreachable!("execve should never return");
 }
-
-pub fn sys_futex( 
-    futex: UserPtr<i32>,
-    _op: usize,
-    _val: usize,
-    _timeout: usize,
-    newval: usize,
-    _val3: usize,
-) -> LinuxResult<isize> {
-    let futex = futex.get();
-    let flux_status = FluxStatus::try_from(_op as i32).unwrap();
-    info!("flux_status:{:?}, futex:{:?}, _op:{:?}, _val:{:?}, id:{}, time:{:?}", flux_status, futex, _op, _val as i32, sys_gettid().unwrap(), _timeout);
-    match flux_status {
-        FluxStatus::WaitPrivate => {
-            unsafe {
-                info!("real_futex:{}", *futex.unwrap());
-                if *(futex.unwrap()) == _val as i32{
-                    add_futex(futex.unwrap() as usize, sys_gettid().unwrap() as usize);
-                    // WaitPrivate
-                    loop{
-                        if query_futex(futex.unwrap() as usize, sys_gettid().unwrap() as usize){
-                            yield_now();
-                        }else{
-                            return Ok(0);
-                        }
-                    }
-                }else{
-                    return Ok(0);
-                }
-            }
-        }
-        FluxStatus::WakePrivate => {
-            // WakePrivate
-            return Ok(remove_futex(_val) as isize);
-        }
-        _ => {
-            return Err(LinuxError::ENOSYS);
-        }
-    }
-}
\ No newline at end of file
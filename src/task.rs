@@ -1,106 +1,673 @@
 use alloc::{
-    string::{String, ToString}, sync::Arc, vec::Vec
+    ffi::CString, string::{String, ToString}, sync::Arc, vec::Vec
 };
-use arceos_posix_api::FD_TABLE;
-use axerrno::{AxError, AxResult};
+use arceos_posix_api::{FD_CLOEXEC, FD_TABLE};
+use axerrno::{AxError, LinuxError, LinuxResult};
 use axfs::{CURRENT_DIR, CURRENT_DIR_PATH};
 use core::{
     alloc::Layout,
     cell::UnsafeCell,
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicBool, AtomicI32, AtomicIsize, AtomicU64, AtomicUsize, Ordering},
 };
-use memory_addr::VirtAddrRange;
+use memory_addr::VirtAddr;
 use spin::Once;
 use hashbrown::HashMap;
 use lazyinit::LazyInit;
 use crate::{
-    copy_from_kernel,
-    ctypes::{CloneFlags, SigAction, SignalFlags, TimeStat, WaitStatus, VAILD_SIGNAL},
+    UserAspace, clear_kernel_mappings, copy_from_kernel,
+    futex::{exit_robust_list, futex_wake_addr},
+    ipc::{sem::SemUndoList, shm::ShmAttachments},
+    ctypes::{
+        CLD_CONTINUED, CLD_DUMPED, CLD_EXITED, CLD_KILLED, CLD_STOPPED, CloneFlags, S_ISGID,
+        S_ISUID, S_IXGRP, SIG_DFL, SIG_IGN, SigAction, SigActionFlags, SiginfoT, SignalFlags,
+        TimeStat, VAILD_SIGNAL, WaitFlags,
+    },
+    ptr::{PtrWrapper, UserPtr},
+    signal::{DefaultAction, JobControlEvent, PendingSignals, SIGCHLD, SIGCONT, STOP_SIGNALS},
 };
 use axhal::{
     arch::{TrapFrame, UspaceContext},
-    time::{NANOS_PER_MICROS, NANOS_PER_SEC, monotonic_time_nanos},
+    time::{NANOS_PER_MICROS, NANOS_PER_SEC, TimeValue, monotonic_time, monotonic_time_nanos},
 };
-use axmm::{AddrSpace, kernel_aspace};
+use axmm::AddrSpace;
 use axns::{AxNamespace, AxNamespaceIf};
 use axsync::Mutex;
-use axtask::{current, AxTaskRef, TaskExtRef, TaskInner, WeakAxTaskRef};
-  
+use axtask::{current, AxTaskRef, TaskExtRef, TaskInner, WaitQueue, WeakAxTaskRef};
 
+/// The scheduling priority of user threads.
+const DEFAULT_PRIO: isize = 0;
 
-/// Task extended data for the monolithic kernel.
-pub struct TaskExt {
-    /// The process ID.
-    pub proc_id: usize,
+/// A state change of a child process, reported by `wait4` and `waitid`.
+#[derive(Debug, Clone, Copy)]
+pub struct ChildEvent {
+    /// The process ID of the child
+    pub pid: usize,
+    /// The `CLD_*` code of the change
+    pub code: i32,
+    /// The exit code, or the signal that caused the change
+    pub status: i32,
+}
+
+impl ChildEvent {
+    /// Returns the status word reported by `wait4`.
+    pub fn wait_status(&self) -> i32 {
+        match self.code {
+            CLD_EXITED => (self.status & 0xff) << 8,
+            CLD_KILLED => self.status,
+            CLD_DUMPED => self.status | 0x80,
+            CLD_STOPPED => self.status << 8 | 0x7f,
+            _ => 0xffff,
+        }
+    }
+}
+
+/// The children waited for by `wait4` and `waitid`.
+#[derive(Debug, Clone, Copy)]
+pub enum WaitTarget {
+    /// The child with the given process ID
+    Pid(usize),
+    /// The children in the given process group
+    Pgid(usize),
+    /// Any child
+    Any,
+}
+
+/// The user and group IDs of a process.
+#[derive(Debug, Clone, Copy)]
+pub struct Credentials {
+    /// The real user ID
+    pub uid: u32,
+    /// The effective user ID
+    pub euid: u32,
+    /// The saved set-user-ID
+    pub suid: u32,
+    /// The real group ID
+    pub gid: u32,
+    /// The effective group ID
+    pub egid: u32,
+    /// The saved set-group-ID
+    pub sgid: u32,
+}
+
+impl Default for Credentials {
+    fn default() -> Self {
+        Self {
+            uid: 0,
+            euid: 1000,
+            suid: 1000,
+            gid: 0,
+            egid: 1000,
+            sgid: 1000,
+        }
+    }
+}
+
+/// Tells a parent blocked in a `CLONE_VFORK` clone that the child no longer
+/// uses its address space.
+struct VforkDone {
+    done: AtomicBool,
+    wq: WaitQueue,
+}
+
+/// The signal actions, shared by the threads created with `CLONE_SIGHAND`.
+pub type SignalActions = Mutex<[SigAction; VAILD_SIGNAL]>;
+
+/// A process, i.e. a thread group.
+///
+/// It holds the state shared by all the threads created with `CLONE_THREAD`.
+pub struct Process {
+    /// The process ID, which is the thread ID of the group leader.
+    pid: usize,
     /// The parent process ID.
-    pub parent_id: AtomicU64,
-    /// children process
+    parent_id: AtomicU64,
+    /// The process group ID.
+    pgid: AtomicUsize,
+    /// The group leaders of the child processes
     pub children: Mutex<Vec<AxTaskRef>>,
+    /// The threads of this process
+    threads: Mutex<Vec<WeakAxTaskRef>>,
+    /// The user heap bottom
+    heap_bottom: AtomicU64,
+    /// The user heap top
+    heap_top: AtomicU64,
+    /// The pending signals sent to the whole process
+    pub signal_pending: Mutex<PendingSignals>,
+    /// The signal that terminated the process, with bit 7 set if it dumped core
+    term_signal: AtomicI32,
+    /// The last stop or continue event not yet reported to the parent
+    job_event: Mutex<Option<JobControlEvent>>,
+    /// Woken up when the stopped threads are continued or killed
+    stop_wq: WaitQueue,
+    /// Whether the process has exited, waiting to be reaped by its parent
+    zombie: AtomicBool,
+    /// Whether the whole thread group is exiting
+    group_exiting: AtomicBool,
+    /// The number of threads that have not exited
+    live_threads: AtomicUsize,
+    /// Woken up when a thread of the process exits
+    thread_exit_wq: WaitQueue,
+    /// The user and group IDs
+    cred: Mutex<Credentials>,
+    /// The System V shared memory segments attached to the address space
+    pub(crate) shm_attachments: Mutex<ShmAttachments>,
+    /// The System V semaphore adjustments undone at exit
+    pub(crate) sem_undo: Mutex<Arc<SemUndoList>>,
+    /// The exit code of the process
+    exit_code: AtomicI32,
+    /// Woken up when a child process exits, stops or continues
+    child_wq: WaitQueue,
+    /// Counts the state changes of the child processes
+    child_events: AtomicUsize,
+    fd_limit: AtomicU64,
+}
+
+impl Process {
+    pub fn new(pid: usize, parent_id: u64, heap_bottom: u64, heap_top: u64) -> Self {
+        Self {
+            pid,
+            parent_id: AtomicU64::new(parent_id),
+            pgid: AtomicUsize::new(pid),
+            children: Mutex::new(Vec::new()),
+            threads: Mutex::new(Vec::new()),
+            heap_bottom: AtomicU64::new(heap_bottom),
+            heap_top: AtomicU64::new(heap_top),
+            signal_pending: Mutex::new(PendingSignals::new()),
+            term_signal: AtomicI32::new(0),
+            job_event: Mutex::new(None),
+            stop_wq: WaitQueue::new(),
+            zombie: AtomicBool::new(false),
+            group_exiting: AtomicBool::new(false),
+            live_threads: AtomicUsize::new(0),
+            thread_exit_wq: WaitQueue::new(),
+            cred: Mutex::new(Credentials::default()),
+            shm_attachments: Mutex::new(ShmAttachments::default()),
+            sem_undo: Mutex::new(Arc::default()),
+            exit_code: AtomicI32::new(0),
+            child_wq: WaitQueue::new(),
+            child_events: AtomicUsize::new(0),
+            fd_limit: AtomicU64::new(1024),
+        }
+    }
+
+    /// Returns the process ID.
+    pub fn pid(&self) -> usize {
+        self.pid
+    }
+
+    /// Adds a thread to this process.
+    pub(crate) fn add_thread(&self, task: &AxTaskRef) {
+        self.threads.lock().push(Arc::downgrade(task));
+    }
+
+    /// Returns the threads of this process that have not exited.
+    pub(crate) fn threads(&self) -> Vec<AxTaskRef> {
+        let mut threads = self.threads.lock();
+        threads.retain(|task| {
+            task.upgrade()
+                .is_some_and(|task| task.state() != axtask::TaskState::Exited)
+        });
+        threads.iter().filter_map(|task| task.upgrade()).collect()
+    }
+
+    /// Records that the process is terminated by `signum`.
+    pub(crate) fn set_term_signal(&self, signum: usize, core_dumped: bool) {
+        let status = signum as i32 | if core_dumped { 0x80 } else { 0 };
+        self.term_signal.store(status, Ordering::Release);
+    }
+
+    /// Returns the process group ID.
+    pub fn pgid(&self) -> usize {
+        self.pgid.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_pgid(&self, pgid: usize) {
+        self.pgid.store(pgid, Ordering::Release);
+    }
+
+    /// Returns the user and group IDs.
+    pub(crate) fn cred(&self) -> Credentials {
+        *self.cred.lock()
+    }
+
+    pub(crate) fn set_cred(&self, cred: Credentials) {
+        *self.cred.lock() = cred;
+    }
+
+    /// Updates the user and group IDs when the program at `path` is executed.
+    ///
+    /// The set-user-ID and set-group-ID bits of the file make its owner and
+    /// group effective. The effective IDs are then saved.
+    fn exec_cred(&self, path: &str) {
+        let mut cred = self.cred.lock();
+        let mut stat = arceos_posix_api::ctypes::stat::default();
+        let ret = CString::new(path)
+            .map(|path| unsafe { arceos_posix_api::sys_stat(path.as_ptr(), &mut stat) })
+            .unwrap_or(-1);
+        if ret == 0 {
+            if stat.st_mode & S_ISUID != 0 {
+                cred.euid = stat.st_uid;
+            }
+            // Without the group execute bit, set-group-ID marks mandatory
+            // locking instead.
+            if stat.st_mode & (S_ISGID | S_IXGRP) == S_ISGID | S_IXGRP {
+                cred.egid = stat.st_gid;
+            }
+        }
+        cred.suid = cred.euid;
+        cred.sgid = cred.egid;
+    }
+
+    /// Whether the process has exited.
+    pub(crate) fn is_zombie(&self) -> bool {
+        self.zombie.load(Ordering::Acquire)
+    }
+
+    /// Whether the whole thread group is exiting.
+    pub(crate) fn is_group_exiting(&self) -> bool {
+        self.group_exiting.load(Ordering::Acquire)
+    }
+
+    /// Makes the whole thread group exit with `exit_code`.
+    ///
+    /// The other threads are killed, and interrupted if they are blocked.
+    /// They exit on their way back to user space. Returns `false` if the
+    /// group was already exiting, in which case its exit code is kept.
+    pub(crate) fn group_exit(&self, exit_code: i32) -> bool {
+        if self.group_exiting.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        self.exit_code.store(exit_code, Ordering::Release);
+        let curr = current();
+        for thread in self.threads() {
+            if thread.id() == curr.id() {
+                continue;
+            }
+            let task_ext = thread.task_ext();
+            task_ext.killed.store(true, Ordering::Release);
+            task_ext.frozen.store(false, Ordering::Release);
+            thread.interrupt();
+            task_ext.interrupt();
+        }
+        self.stop_wq.notify_all(false);
+        true
+    }
+
+    /// Kills the other threads of the process for `execve`, and waits for
+    /// them to exit.
+    ///
+    /// Fails with `EAGAIN` if the whole group is already exiting.
+    fn kill_other_threads(&self) -> LinuxResult<()> {
+        if !self.group_exit(0) {
+            return Err(LinuxError::EAGAIN);
+        }
+        self.thread_exit_wq
+            .wait_until(|| self.live_threads.load(Ordering::Acquire) == 1);
+        self.group_exiting.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// Records that a thread of this process exited with `exit_code`.
+    ///
+    /// The exit code of the group leader is the one of the process, unless
+    /// the whole group exits. Once the last thread is gone, the process
+    /// exits.
+    fn thread_exit(&self, tid: usize, exit_code: i32) {
+        if tid == self.pid && !self.is_group_exiting() {
+            self.exit_code.store(exit_code, Ordering::Release);
+        }
+        if self.live_threads.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.exit();
+        }
+        self.thread_exit_wq.notify_all(false);
+    }
+
+    /// Records that the process exited, and reports it to the parent.
+    ///
+    /// The children of the process are handed over to the init process. If
+    /// the parent ignores SIGCHLD or set `SA_NOCLDWAIT`, nobody is going to
+    /// wait for the process, so it is reaped at once.
+    fn exit(&self) {
+        self.zombie.store(true, Ordering::Release);
+        self.shm_attachments.lock().detach_all(self.pid as i32);
+        // The adjustments are undone by the last process sharing them.
+        let sem_undo = core::mem::take(&mut *self.sem_undo.lock());
+        if let Some(sem_undo) = Arc::into_inner(sem_undo) {
+            sem_undo.apply(self.pid as i32);
+        }
+        self.reparent_children();
+        if let Some(parent) = get_task_by_id(self.get_parent() as usize) {
+            let action = parent.task_ext().get_signal_action(SIGCHLD);
+            let flags = SigActionFlags::from_bits_truncate(action.sa_flags);
+            if action.sa_handler == SIG_IGN || flags.contains(SigActionFlags::SA_NOCLDWAIT) {
+                parent
+                    .task_ext()
+                    .process
+                    .children
+                    .lock()
+                    .retain(|child| child.task_ext().proc_id() != self.pid);
+                remove_task(self.pid);
+            }
+        }
+        let event = self.exit_event().unwrap();
+        self.notify_parent(event.code, event.status);
+    }
+
+    /// Hands the children of this process over to the init process.
+    fn reparent_children(&self) {
+        let children = core::mem::take(&mut *self.children.lock());
+        if children.is_empty() {
+            return;
+        }
+        let init = &INIT_TASK.task_ext().process;
+        for child in &children {
+            child.task_ext().process.set_parent(init.pid() as u64);
+        }
+        init.children.lock().extend(children);
+        // Some of them may have exited already.
+        init.child_events.fetch_add(1, Ordering::AcqRel);
+        init.child_wq.notify_all(false);
+    }
+
+    /// Returns the exit of this process as a child event, if it exited.
+    fn exit_event(&self) -> Option<ChildEvent> {
+        if !self.is_zombie() {
+            return None;
+        }
+        let (code, status) = match self.term_signal.load(Ordering::Acquire) {
+            0 => (CLD_EXITED, self.exit_code.load(Ordering::Acquire) & 0xff),
+            signal if signal & 0x80 != 0 => (CLD_DUMPED, signal & 0x7f),
+            signal => (CLD_KILLED, signal),
+        };
+        Some(ChildEvent {
+            pid: self.pid,
+            code,
+            status,
+        })
+    }
+
+    /// Takes the unreported stop or continue event of this process if
+    /// `options` asks for it. With `WNOWAIT`, the event is left unreported.
+    fn take_job_event(&self, options: WaitFlags) -> Option<ChildEvent> {
+        let mut event = self.job_event.lock();
+        let (code, status) = match (*event)? {
+            JobControlEvent::Stopped(signum) if options.contains(WaitFlags::WIMTRACED) => {
+                (CLD_STOPPED, signum as i32)
+            }
+            JobControlEvent::Continued if options.contains(WaitFlags::WCONTINUED) => {
+                (CLD_CONTINUED, SIGCONT as i32)
+            }
+            _ => return None,
+        };
+        if !options.contains(WaitFlags::WNOWAIT) {
+            *event = None;
+        }
+        Some(ChildEvent {
+            pid: self.pid,
+            code,
+            status,
+        })
+    }
+
+    /// Returns the user and system times of the threads of this process, in
+    /// nanoseconds.
+    pub(crate) fn time_stat_output(&self) -> (usize, usize) {
+        self.threads
+            .lock()
+            .iter()
+            .filter_map(|task| task.upgrade())
+            .fold((0, 0), |(utime, stime), task| {
+                let (task_utime, task_stime) = task.task_ext().time_stat_output();
+                (utime + task_utime, stime + task_stime)
+            })
+    }
+
+    /// Stops all the threads because of the stop signal `signum`.
+    ///
+    /// They stay stopped until the process receives SIGCONT or SIGKILL.
+    pub(crate) fn stop(&self, signum: usize) {
+        for thread in self.threads() {
+            thread.task_ext().frozen.store(true, Ordering::Release);
+        }
+        *self.job_event.lock() = Some(JobControlEvent::Stopped(signum));
+        self.notify_parent(CLD_STOPPED, signum as i32);
+    }
+
+    /// Applies the job control side effects of generating a signal.
+    ///
+    /// SIGCONT resumes a stopped process and discards its pending stop
+    /// signals, while a stop signal discards a pending SIGCONT. SIGKILL wakes
+    /// the stopped threads up so that they can die.
+    fn prepare_signal(&self, signum: usize) {
+        let Some(signal) = SignalFlags::from_signum(signum) else {
+            return;
+        };
+        let threads = self.threads();
+        if signal == SignalFlags::SIGCONT {
+            self.signal_pending.lock().remove(STOP_SIGNALS);
+            let mut continued = false;
+            for thread in &threads {
+                let task_ext = thread.task_ext();
+                task_ext.signal_pending.lock().remove(STOP_SIGNALS);
+                continued |= task_ext.frozen.swap(false, Ordering::AcqRel);
+            }
+            self.stop_wq.notify_all(false);
+            if continued {
+                *self.job_event.lock() = Some(JobControlEvent::Continued);
+                self.notify_parent(CLD_CONTINUED, signum as i32);
+            }
+        } else if STOP_SIGNALS.contains(signal) {
+            self.signal_pending.lock().remove(SignalFlags::SIGCONT);
+            for thread in &threads {
+                thread.task_ext().signal_pending.lock().remove(SignalFlags::SIGCONT);
+            }
+        } else if signal == SignalFlags::SIGKILL {
+            for thread in &threads {
+                thread.task_ext().killed.store(true, Ordering::Release);
+                thread.task_ext().frozen.store(false, Ordering::Release);
+            }
+            self.stop_wq.notify_all(false);
+        }
+    }
+
+    /// Sends SIGCHLD to the parent to report a state change of this process.
+    ///
+    /// Stop and continue events are not reported if the parent set
+    /// `SA_NOCLDSTOP` for SIGCHLD.
+    pub(crate) fn notify_parent(&self, code: i32, status: i32) {
+        let Some(parent) = get_task_by_id(self.get_parent() as usize) else {
+            return;
+        };
+        let parent_process = &parent.task_ext().process;
+        parent_process.child_events.fetch_add(1, Ordering::AcqRel);
+        parent_process.child_wq.notify_all(false);
+        let action = parent.task_ext().get_signal_action(SIGCHLD);
+        let flags = SigActionFlags::from_bits_truncate(action.sa_flags);
+        if matches!(code, CLD_STOPPED | CLD_CONTINUED)
+            && flags.contains(SigActionFlags::SA_NOCLDSTOP)
+        {
+            return;
+        }
+        let mut info = SiginfoT::new(SIGCHLD, code);
+        info.set_sender(self.pid as u32, 0);
+        info.set_status(status);
+        parent.task_ext().send_signal_to_process(info);
+    }
+
+    pub(crate) fn get_parent(&self) -> u64 {
+        self.parent_id.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_parent(&self, parent_id: u64) {
+        self.parent_id.store(parent_id, Ordering::Release);
+    }
+
+    pub(crate) fn get_heap_bottom(&self) -> u64 {
+        self.heap_bottom.load(Ordering::Acquire)
+    }
+
+    #[allow(unused)]
+    pub(crate) fn set_heap_bottom(&self, bottom: u64) {
+        self.heap_bottom.store(bottom, Ordering::Release)
+    }
+
+    pub(crate) fn get_heap_top(&self) -> u64 {
+        self.heap_top.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_heap_top(&self, top: u64) {
+        self.heap_top.store(top, Ordering::Release)
+    }
+
+    pub(crate) fn set_fdlimit(&self, limit: u64) {
+        self.fd_limit.store(limit, Ordering::Release);
+    }
+
+    pub(crate) fn get_fdlimit(&self) -> u64 {
+        self.fd_limit.load(Ordering::Acquire)
+    }
+}
+
+/// Task extended data for the monolithic kernel.
+///
+/// Each task is a thread of a [`Process`].
+pub struct TaskExt {
+    /// The thread ID, which changes to the process ID when a thread other
+    /// than the group leader calls `execve`
+    tid: AtomicUsize,
+    /// The process this thread belongs to.
+    pub process: Arc<Process>,
     /// The clear thread tid field
     ///
     /// See <https://manpages.debian.org/unstable/manpages-dev/set_tid_address.2.en.html#clear_child_tid>
     ///
     /// When the thread exits, the kernel clears the word at this address if it is not NULL.
     clear_child_tid: AtomicU64,
+    /// The head of the robust futex list registered by `set_robust_list`
+    robust_list: AtomicUsize,
     /// The user space context.
     pub uctx: UspaceContext,
-    /// The virtual memory address space.
-    pub aspace: Arc<Mutex<AddrSpace>>,
+    /// The virtual memory address space, shared by the threads created with
+    /// `CLONE_VM`, and replaced by `execve`.
+    aspace: Mutex<Arc<Mutex<AddrSpace>>>,
     /// The resource namespace
     pub ns: AxNamespace,
     /// The time statistics
     pub time: UnsafeCell<TimeStat>,
-    /// The user heap bottom
-    pub heap_bottom: AtomicU64,
-    /// The user heap top
-    pub heap_top: AtomicU64,
+    /// The blocked signals of this thread
     pub signal_mask: Mutex<SignalFlags>,
-    pub sigaction: Mutex<[SigAction; VAILD_SIGNAL]>,
-    pub killed: bool,
-    pub frozen: bool,
-    pub fd_limit: AtomicU64,
+    /// The pending signals sent to this thread
+    pub signal_pending: Mutex<PendingSignals>,
+    /// The signal handlers, shared by the threads created with
+    /// `CLONE_SIGHAND`, and replaced by `execve`.
+    sigaction: Mutex<Arc<SignalActions>>,
+    /// Whether the task has been killed by a signal
+    pub killed: AtomicBool,
+    /// Whether the task is stopped by a job control signal
+    pub frozen: AtomicBool,
+    /// Set when an unblocked signal arrives, to end an interruptible sleep
+    interrupted: AtomicBool,
+    /// The wait queue this thread is sleeping on interruptibly, if any
+    interrupt_wq: Mutex<Option<usize>>,
+    /// The scheduling priority applied to this thread
+    prio: AtomicIsize,
+    /// The priority this thread is boosted to by the waiters on the PI futexes
+    /// it holds, or `isize::MAX`
+    pi_prio: AtomicIsize,
+    /// Signalled on `execve` or exit, if the parent waits for it after
+    /// `CLONE_VFORK`
+    vfork_done: Mutex<Option<Arc<VforkDone>>>,
 }
 
 impl TaskExt {
     pub fn new(
-        proc_id: usize,
+        tid: usize,
+        process: Arc<Process>,
         uctx: UspaceContext,
         aspace: Arc<Mutex<AddrSpace>>,
-        heap_bottom: u64,
+        sigaction: Arc<SignalActions>,
     ) -> Self {
+        // Counted before the thread starts, so that the count cannot drop to
+        // zero while other threads are still being created.
+        process.live_threads.fetch_add(1, Ordering::AcqRel);
         Self {
-            proc_id,
-            parent_id: AtomicU64::new(1),
-            children: Mutex::new(Vec::new()),
+            tid: AtomicUsize::new(tid),
+            process,
             uctx,
             clear_child_tid: AtomicU64::new(0),
-            aspace,
+            robust_list: AtomicUsize::new(0),
+            aspace: Mutex::new(aspace),
             ns: AxNamespace::new_thread_local(),
             time: TimeStat::new().into(),
-            heap_bottom: AtomicU64::new(heap_bottom),
-            heap_top: AtomicU64::new(heap_bottom),
             signal_mask: Mutex::new(SignalFlags::empty()),
-            sigaction: Mutex::new([
-                SigAction::default(); VAILD_SIGNAL
-            ]),
-            killed: false,
-            frozen: false,
-            fd_limit: AtomicU64::new(1024),
+            signal_pending: Mutex::new(PendingSignals::new()),
+            sigaction: Mutex::new(sigaction),
+            killed: AtomicBool::new(false),
+            frozen: AtomicBool::new(false),
+            interrupted: AtomicBool::new(false),
+            interrupt_wq: Mutex::new(None),
+            prio: AtomicIsize::new(DEFAULT_PRIO),
+            pi_prio: AtomicIsize::new(isize::MAX),
+            vfork_done: Mutex::new(None),
         }
     }
 
+    /// Returns the thread ID.
+    pub(crate) fn tid(&self) -> usize {
+        self.tid.load(Ordering::Acquire)
+    }
+
+    /// Returns the ID of the process this thread belongs to.
+    pub(crate) fn proc_id(&self) -> usize {
+        self.process.pid()
+    }
+
+    /// Returns the address space of this thread.
+    pub(crate) fn aspace(&self) -> Arc<Mutex<AddrSpace>> {
+        self.aspace.lock().clone()
+    }
+
+    /// Returns the signal handlers of this thread.
+    fn sigaction(&self) -> Arc<SignalActions> {
+        self.sigaction.lock().clone()
+    }
+
+    /// Creates a thread or a process as a copy of the current thread.
+    ///
+    /// The `CLONE_VM`, `CLONE_THREAD`, `CLONE_SIGHAND`, `CLONE_FILES` and
+    /// `CLONE_FS` flags decide which resources are shared with the new task
+    /// instead of being copied. With `CLONE_VFORK`, the caller is suspended
+    /// until the child calls `execve` or exits.
     pub fn clone_task(
         &self,
         flags: usize,
         stack: Option<usize>,
-        _ptid: usize,
-        _tls: usize,
-        _ctid: usize,
-    ) -> AxResult<u64> {
-        let _clone_flags = CloneFlags::from_bits((flags & !0x3f) as u32).unwrap();
+        ptid: usize,
+        tls: usize,
+        ctid: usize,
+    ) -> LinuxResult<u64> {
+        let clone_flags = CloneFlags::from_bits_truncate((flags & !0x3f) as u32);
+        if clone_flags.contains(CloneFlags::CLONE_THREAD)
+            && !clone_flags.contains(CloneFlags::CLONE_SIGHAND)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        if clone_flags.contains(CloneFlags::CLONE_SIGHAND)
+            && !clone_flags.contains(CloneFlags::CLONE_VM)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let set_child_tid = clone_flags
+            .contains(CloneFlags::CLONE_CHILD_SETTID)
+            .then_some(ctid);
         let mut new_task = TaskInner::new(
-            || {
+            move || {
                 let curr = axtask::current();
+                // The child stores its TID itself, in its own address space.
+                if let Some(ctid) = set_child_tid {
+                    if let Ok(ctid) = UserPtr::<i32>::from(ctid).get() {
+                        unsafe { ctid.write(curr.id().as_u64() as i32) };
+                    }
+                }
                 let kstack_top = curr.kernel_stack_top().unwrap();
                 info!(
                     "Enter user space: entry={:#x}, ustack={:#x}, kstack={:#x}",
@@ -114,14 +681,22 @@ impl TaskExt {
             axconfig::plat::KERNEL_STACK_SIZE,
         );
         #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
-        new_task.ctx_mut().set_tls(axhal::arch::read_thread_pointer().into());
+        if clone_flags.contains(CloneFlags::CLONE_SETTLS) {
+            new_task.ctx_mut().set_tls(VirtAddr::from(tls));
+        } else {
+            new_task.ctx_mut().set_tls(axhal::arch::read_thread_pointer().into());
+        }
         let current_task = current();
-        let mut current_aspace = current_task.task_ext().aspace.lock();
-        let mut new_aspace = current_aspace.clone_or_err()?;
-        copy_from_kernel(&mut new_aspace)?;
+        let new_aspace = if clone_flags.contains(CloneFlags::CLONE_VM) {
+            UserAspace::from(self.aspace())
+        } else {
+            let mut new_aspace = self.aspace().lock().clone_or_err()?;
+            copy_from_kernel(&mut new_aspace)?;
+            UserAspace::from(new_aspace)
+        };
         new_task
             .ctx_mut()
-            .set_page_table_root(new_aspace.page_table_root());
+            .set_page_table_root(new_aspace.get().lock().page_table_root());
 
         let trap_frame = read_trapframe_from_kstack(current_task.get_kernel_stack_top().unwrap());
         let mut new_uctx = UspaceContext::from(&trap_frame);
@@ -132,22 +707,90 @@ impl TaskExt {
         #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))]
         new_uctx.set_ip(new_uctx.get_ip() + 4);
         new_uctx.set_retval(0);
+        // The thread pointer of x86_64 and aarch64 lives in the task context.
+        #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))]
+        if clone_flags.contains(CloneFlags::CLONE_SETTLS) {
+            new_uctx.set_tls(tls);
+        }
         let return_id: u64 = new_task.id().as_u64();
+        if clone_flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
+            unsafe { UserPtr::<i32>::from(ptid).get()?.write(return_id as i32) };
+        }
+
+        let process = if clone_flags.contains(CloneFlags::CLONE_THREAD) {
+            self.process.clone()
+        } else {
+            let process = Process::new(
+                return_id as usize,
+                self.proc_id() as u64,
+                self.process.get_heap_bottom(),
+                self.process.get_heap_top(),
+            );
+            process.set_fdlimit(self.process.get_fdlimit());
+            process.set_pgid(self.process.pgid());
+            process.set_cred(self.process.cred());
+            if !clone_flags.contains(CloneFlags::CLONE_VM) {
+                // The copy of the address space maps the same segments.
+                *process.shm_attachments.lock() =
+                    self.process.shm_attachments.lock().fork(return_id as i32);
+            }
+            if clone_flags.contains(CloneFlags::CLONE_SYSVSEM) {
+                *process.sem_undo.lock() = self.process.sem_undo.lock().clone();
+            }
+            Arc::new(process)
+        };
+        let sigaction = if clone_flags.contains(CloneFlags::CLONE_SIGHAND) {
+            self.sigaction()
+        } else {
+            Arc::new(Mutex::new(*self.sigaction().lock()))
+        };
         let new_task_ext = TaskExt::new(
             return_id as usize,
+            process,
             new_uctx,
-            Arc::new(Mutex::new(new_aspace)),
-            axconfig::plat::USER_HEAP_BASE as _,
+            new_aspace.into_inner(),
+            sigaction,
         );
-        new_task_ext.set_parent(current_task.id().as_u64());
-        new_task_ext.ns_init_new();
+        new_task_ext.set_mask(self.get_mask());
+        if clone_flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
+            new_task_ext.set_clear_child_tid(ctid as u64);
+        }
+        let vfork_done = clone_flags.contains(CloneFlags::CLONE_VFORK).then(|| {
+            Arc::new(VforkDone {
+                done: AtomicBool::new(false),
+                wq: WaitQueue::new(),
+            })
+        });
+        *new_task_ext.vfork_done.lock() = vfork_done.clone();
+        new_task_ext.ns_init_clone(clone_flags);
         new_task.init_task_ext(new_task_ext);
         let new_task_ref = axtask::spawn_task(new_task);
         insert_task(return_id as usize, new_task_ref.clone());
-        current_task.task_ext().children.lock().push(new_task_ref);
+        let new_process = new_task_ref.task_ext().process.clone();
+        new_process.add_thread(&new_task_ref);
+        if new_process.is_group_exiting() {
+            // Missed by a concurrent group exit.
+            new_task_ref.interrupt();
+        }
+        if !Arc::ptr_eq(&new_process, &self.process) {
+            self.process.children.lock().push(new_task_ref);
+        }
+        if let Some(vfork_done) = vfork_done {
+            // Only SIGKILL ends the wait early. The child keeps the shared
+            // address space alive anyway.
+            let _ = wait_killable(&vfork_done.wq, || vfork_done.done.load(Ordering::Acquire));
+        }
         Ok(return_id)
     }
 
+    /// Wakes up the parent suspended by `CLONE_VFORK`, if any.
+    fn release_vfork_parent(&self) {
+        if let Some(vfork_done) = self.vfork_done.lock().take() {
+            vfork_done.done.store(true, Ordering::Release);
+            vfork_done.wq.notify_all(false);
+        }
+    }
+
     pub(crate) fn clear_child_tid(&self) -> u64 {
         self.clear_child_tid
             .load(core::sync::atomic::Ordering::Relaxed)
@@ -158,13 +801,45 @@ impl TaskExt {
             .store(clear_child_tid, core::sync::atomic::Ordering::Relaxed);
     }
 
-    pub(crate) fn get_parent(&self) -> u64 {
-        self.parent_id.load(Ordering::Acquire)
+    pub(crate) fn robust_list(&self) -> usize {
+        self.robust_list.load(Ordering::Relaxed)
     }
 
-    #[allow(unused)]
-    pub(crate) fn set_parent(&self, parent_id: u64) {
-        self.parent_id.store(parent_id, Ordering::Release);
+    pub(crate) fn set_robust_list(&self, head: usize) {
+        self.robust_list.store(head, Ordering::Relaxed);
+    }
+
+    /// Returns the scheduling priority applied to this thread. Lower values
+    /// mean higher priorities.
+    pub(crate) fn prio(&self) -> isize {
+        self.prio.load(Ordering::Acquire)
+    }
+
+    /// Boosts this thread, `task`, to at least the priority `prio`, on behalf
+    /// of a waiter on a PI futex it holds.
+    pub(crate) fn boost_priority(&self, task: &AxTaskRef, prio: isize) {
+        self.pi_prio.fetch_min(prio, Ordering::AcqRel);
+        self.apply_priority(task);
+    }
+
+    /// Sets the priority boost of this thread, `task`, to `prio`, the highest
+    /// priority of the waiters on the PI futexes it still holds, or
+    /// `isize::MAX` if there are none.
+    pub(crate) fn set_priority_boost(&self, task: &AxTaskRef, prio: isize) {
+        self.pi_prio.store(prio, Ordering::Release);
+        self.apply_priority(task);
+    }
+
+    /// Applies the boosted priority of this thread, `task`, to the scheduler.
+    fn apply_priority(&self, task: &AxTaskRef) {
+        let prio = self.pi_prio.load(Ordering::Acquire).min(DEFAULT_PRIO);
+        if prio != self.prio() && axtask::set_task_priority(task, prio) {
+            self.prio.store(prio, Ordering::Release);
+        }
+    }
+
+    pub(crate) fn get_parent(&self) -> u64 {
+        self.process.get_parent()
     }
 
     pub(crate) fn set_mask(&self, mask: SignalFlags) {
@@ -186,13 +861,95 @@ impl TaskExt {
         *signal &= !mask;
     }
 
+    /// Queues a signal directed at this thread.
+    pub(crate) fn send_signal_to_thread(&self, info: SiginfoT) {
+        let signum = info.si_signo as usize;
+        self.process.prepare_signal(signum);
+        self.signal_pending.lock().push(info);
+        self.interrupt_unless_blocked(signum);
+    }
+
+    /// Queues a signal directed at the process this thread belongs to.
+    pub(crate) fn send_signal_to_process(&self, info: SiginfoT) {
+        let signum = info.si_signo as usize;
+        self.process.prepare_signal(signum);
+        self.process.signal_pending.lock().push(info);
+        for thread in self.process.threads() {
+            thread.task_ext().interrupt_unless_blocked(signum);
+        }
+    }
+
+    /// Ends the interruptible sleep of this thread, if it does not block
+    /// the signal `signum`.
+    fn interrupt_unless_blocked(&self, signum: usize) {
+        if let Some(signal) = SignalFlags::from_signum(signum) {
+            if self.get_mask().contains(signal) {
+                return;
+            }
+        }
+        // Ignored signals are discarded on delivery, without interrupting.
+        let ignored = match self.get_signal_action(signum).sa_handler {
+            SIG_IGN => true,
+            SIG_DFL => matches!(
+                DefaultAction::of(signum),
+                DefaultAction::Ignore | DefaultAction::Continue
+            ),
+            _ => false,
+        };
+        if !ignored {
+            self.interrupt();
+        }
+    }
+
+    /// Ends the interruptible sleep of this thread, if any.
+    fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Release);
+        if let Some(wq) = *self.interrupt_wq.lock() {
+            // SAFETY: the sleeping thread unregisters the wait queue before
+            // it goes away, and it cannot do so while we hold the lock.
+            unsafe { &*(wq as *const WaitQueue) }.notify_all(false);
+        }
+    }
+
+    /// Whether a signal that is not blocked is pending for this thread.
+    pub(crate) fn has_unblocked_signal(&self) -> bool {
+        !(self.get_pending() - self.get_mask()).is_empty()
+    }
+
+    pub(crate) fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::Acquire)
+    }
+
+    /// Sleeps while this thread, the current one, is stopped, until the
+    /// process receives SIGCONT or SIGKILL.
+    pub(crate) fn wait_while_frozen(&self) {
+        self.process.stop_wq.wait_until(|| !self.is_frozen());
+    }
+
+    /// Returns the signals pending for this thread or its process.
+    pub(crate) fn get_pending(&self) -> SignalFlags {
+        self.signal_pending.lock().pending() | self.process.signal_pending.lock().pending()
+    }
+
+    /// Removes the lowest pending signal that is not blocked, preferring the
+    /// ones directed at this thread.
+    pub(crate) fn take_pending_signal(&self) -> Option<SiginfoT> {
+        let mask = self.get_mask();
+        self.signal_pending
+            .lock()
+            .pop(mask)
+            .or_else(|| self.process.signal_pending.lock().pop(mask))
+    }
+
     pub fn get_signal_action(&self, signum: usize) -> SigAction {
-        let sigaction = self.sigaction.lock();
+        let sigaction = self.sigaction();
+        let sigaction = sigaction.lock();
         sigaction[signum-1].clone()
     }
 
     pub fn set_signal_action(&self, signum: usize, action: *const SigAction) {
-        let mut sigaction = self.sigaction.lock();
+        let sigaction = self.sigaction();
+        let mut sigaction = sigaction.lock();
         sigaction[signum-1] = unsafe {*action};
     }
 
@@ -200,6 +957,9 @@ impl TaskExt {
         FD_TABLE
             .deref_from(&self.ns)
             .init_new(FD_TABLE.copy_inner());
+        FD_CLOEXEC
+            .deref_from(&self.ns)
+            .init_new(FD_CLOEXEC.copy_inner());
         CURRENT_DIR
             .deref_from(&self.ns)
             .init_new(CURRENT_DIR.copy_inner());
@@ -208,6 +968,40 @@ impl TaskExt {
             .init_new(CURRENT_DIR_PATH.copy_inner());
     }
 
+    /// Initializes the namespace of a cloned task, sharing the fd table with
+    /// the current task for `CLONE_FILES`, and the working directory for
+    /// `CLONE_FS`.
+    pub(crate) fn ns_init_clone(&self, clone_flags: CloneFlags) {
+        if clone_flags.contains(CloneFlags::CLONE_FILES) {
+            FD_TABLE.deref_from(&self.ns).init_shared(FD_TABLE.share());
+            FD_CLOEXEC
+                .deref_from(&self.ns)
+                .init_shared(FD_CLOEXEC.share());
+        } else {
+            FD_TABLE
+                .deref_from(&self.ns)
+                .init_new(FD_TABLE.copy_inner());
+            FD_CLOEXEC
+                .deref_from(&self.ns)
+                .init_new(FD_CLOEXEC.copy_inner());
+        }
+        if clone_flags.contains(CloneFlags::CLONE_FS) {
+            CURRENT_DIR
+                .deref_from(&self.ns)
+                .init_shared(CURRENT_DIR.share());
+            CURRENT_DIR_PATH
+                .deref_from(&self.ns)
+                .init_shared(CURRENT_DIR_PATH.share());
+        } else {
+            CURRENT_DIR
+                .deref_from(&self.ns)
+                .init_new(CURRENT_DIR.copy_inner());
+            CURRENT_DIR_PATH
+                .deref_from(&self.ns)
+                .init_new(CURRENT_DIR_PATH.copy_inner());
+        }
+    }
+
     pub(crate) fn time_stat_from_kernel_to_user(&self, current_tick: usize) {
         let time = self.time.get();
         unsafe {
@@ -226,30 +1020,6 @@ impl TaskExt {
         let time = self.time.get();
         unsafe { (*time).output() }
     }
-
-    pub(crate) fn get_heap_bottom(&self) -> u64 {
-        self.heap_bottom.load(Ordering::Acquire)
-    }
-
-    #[allow(unused)]
-    pub(crate) fn set_heap_bottom(&self, bottom: u64) {
-        self.heap_bottom.store(bottom, Ordering::Release)
-    }
-
-    pub(crate) fn get_heap_top(&self) -> u64 {
-        self.heap_top.load(Ordering::Acquire)
-    }
-
-    pub(crate) fn set_heap_top(&self, top: u64) {
-        self.heap_top.store(top, Ordering::Release)
-    }
-
-    pub(crate) fn set_fdlimit(&self, limit: u64) {
-        self.fd_limit.store(limit, Ordering::Release);
-    }
-    pub(crate) fn get_fdlimit(&self) -> u64 {
-        self.fd_limit.load(Ordering::Acquire)
-    }
 }
 
 struct AxNamespaceImpl;
@@ -277,12 +1047,10 @@ impl AxNamespaceIf for AxNamespaceImpl {
 
 impl Drop for TaskExt {
     fn drop(&mut self) {
-        if !cfg!(target_arch = "aarch64") && !cfg!(target_arch = "loongarch64") {
-            // See [`crate::new_user_aspace`]
-            let kernel = kernel_aspace().lock();
-            self.aspace
-                .lock()
-                .clear_mappings(VirtAddrRange::from_start_size(kernel.base(), kernel.size()));
+        // The address space may still be used by other threads.
+        let aspace = self.aspace.get_mut();
+        if Arc::strong_count(aspace) == 1 {
+            clear_kernel_mappings(&mut aspace.lock());
         }
     }
 }
@@ -311,20 +1079,186 @@ pub fn spawn_user_task(
     );
     task.ctx_mut()
         .set_page_table_root(aspace.lock().page_table_root());
+    let id = task.id().as_u64() as usize;
+    let process = Process::new(id, init_pid() as u64, heap_bottom, heap_bottom);
     task.init_task_ext(TaskExt::new(
-        task.id().as_u64() as usize,
+        id,
+        Arc::new(process),
         uctx,
         aspace,
-        heap_bottom,
+        Arc::new(Mutex::new([SigAction::default(); VAILD_SIGNAL])),
     ));
     task.task_ext().ns_init_new();
-    let id = task.id().as_u64() as usize;
     let new_task_ref = axtask::spawn_task(task);
     insert_task(id, new_task_ref.clone());
+    new_task_ref.task_ext().process.add_thread(&new_task_ref);
+    INIT_TASK
+        .task_ext()
+        .process
+        .children
+        .lock()
+        .push(new_task_ref.clone());
     new_task_ref
 }
 
-#[allow(unused)]
+static INIT_TASK: LazyInit<AxTaskRef> = LazyInit::new();
+
+/// Returns the process ID of the init process.
+pub fn init_pid() -> usize {
+    INIT_TASK.task_ext().proc_id()
+}
+
+/// Spawns the init process.
+///
+/// It runs in the kernel, is the parent of the processes spawned by
+/// [`spawn_user_task`], adopts the orphaned processes, and reaps its children
+/// when they exit.
+pub fn spawn_init_task() {
+    let mut task = TaskInner::new(
+        || {
+            let curr = current();
+            let process = &curr.task_ext().process;
+            loop {
+                let events = process.child_events.load(Ordering::Acquire);
+                let options = WaitFlags::WEXITED | WaitFlags::WNOHANG;
+                if let Ok(Some(_)) = wait_child(WaitTarget::Any, options) {
+                    continue;
+                }
+                process
+                    .child_wq
+                    .wait_until(|| process.child_events.load(Ordering::Acquire) != events);
+            }
+        },
+        "init".into(),
+        axconfig::plat::KERNEL_STACK_SIZE,
+    );
+    let id = task.id().as_u64() as usize;
+    // It never enters user space.
+    let aspace = crate::new_user_aspace_empty().expect("Failed to create init address space");
+    task.init_task_ext(TaskExt::new(
+        id,
+        Arc::new(Process::new(id, 0, 0, 0)),
+        UspaceContext::new(0, VirtAddr::from(0), 0),
+        Arc::new(Mutex::new(aspace)),
+        Arc::new(Mutex::new([SigAction::default(); VAILD_SIGNAL])),
+    ));
+    task.task_ext().ns_init_new();
+    let init_task = axtask::spawn_task(task);
+    insert_task(id, init_task.clone());
+    init_task.task_ext().process.add_thread(&init_task);
+    INIT_TASK.init_once(init_task);
+}
+
+/// Ends the current thread with `exit_code`, after releasing its futexes.
+///
+/// A thread other than the group leader leaves the task table at once, while
+/// the leader stays there until its process is reaped. The last thread to
+/// exit reports the exit of the process to the parent.
+pub fn exit_current(exit_code: i32) -> ! {
+    release_futexes_on_exit();
+    let curr = current();
+    curr.task_ext().release_vfork_parent();
+    let tid = curr.task_ext().tid();
+    if tid != curr.task_ext().proc_id() {
+        remove_task(tid);
+    }
+    curr.task_ext().process.thread_exit(tid, exit_code);
+    axtask::exit(exit_code)
+}
+
+/// Releases the futexes of the exiting current thread.
+///
+/// The robust futexes it still holds are marked with `FUTEX_OWNER_DIED`, and
+/// its `clear_child_tid` word is zeroed, waking up the futex waiters on it,
+/// which `pthread_join` relies on.
+fn release_futexes_on_exit() {
+    let curr = current();
+    let robust_list = curr.task_ext().robust_list();
+    if robust_list != 0 {
+        exit_robust_list(robust_list, curr.task_ext().tid() as u32);
+    }
+    let clear_child_tid = curr.task_ext().clear_child_tid() as usize;
+    if clear_child_tid == 0 {
+        return;
+    }
+    if let Ok(ptr) = UserPtr::<i32>::from(clear_child_tid).get() {
+        unsafe { ptr.write(0) };
+        futex_wake_addr(clear_child_tid, false);
+    }
+}
+
+/// Blocks the current thread on `wq` until `condition` holds.
+///
+/// The sleep ends early with `EINTR` if a signal that is not blocked arrives
+/// or the thread group exits, or with `ETIMEDOUT` once the monotonic clock
+/// reaches `deadline`.
+pub fn wait_interruptible<F>(
+    wq: &WaitQueue,
+    deadline: Option<TimeValue>,
+    condition: F,
+) -> LinuxResult<()>
+where
+    F: Fn() -> bool,
+{
+    let curr = current();
+    let task_ext = curr.task_ext();
+    *task_ext.interrupt_wq.lock() = Some(wq as *const WaitQueue as usize);
+    task_ext.interrupted.store(false, Ordering::Release);
+    let mut timed_out = false;
+    if !task_ext.has_unblocked_signal() && !curr.is_interrupted() {
+        let wake = || {
+            condition() || task_ext.interrupted.load(Ordering::Acquire) || curr.is_interrupted()
+        };
+        match deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_sub(monotonic_time());
+                timed_out = wq.wait_timeout_until(timeout, wake);
+            }
+            None => wq.wait_until(wake),
+        }
+    }
+    *task_ext.interrupt_wq.lock() = None;
+    if condition() {
+        Ok(())
+    } else if timed_out {
+        Err(LinuxError::ETIMEDOUT)
+    } else {
+        Err(LinuxError::EINTR)
+    }
+}
+
+/// Blocks the current thread on `wq` until `condition` holds.
+///
+/// Unlike [`wait_interruptible`], the sleep only ends early with `EINTR` if
+/// SIGKILL arrives or the thread group exits. Other signals are left pending.
+pub fn wait_killable<F>(wq: &WaitQueue, condition: F) -> LinuxResult<()>
+where
+    F: Fn() -> bool,
+{
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let killed = || {
+        task_ext.get_pending().contains(SignalFlags::SIGKILL) || curr.is_interrupted()
+    };
+    loop {
+        *task_ext.interrupt_wq.lock() = Some(wq as *const WaitQueue as usize);
+        task_ext.interrupted.store(false, Ordering::Release);
+        if !killed() {
+            wq.wait_until(|| {
+                condition() || task_ext.interrupted.load(Ordering::Acquire) || curr.is_interrupted()
+            });
+        }
+        *task_ext.interrupt_wq.lock() = None;
+        if condition() {
+            return Ok(());
+        }
+        if killed() {
+            return Err(LinuxError::EINTR);
+        }
+        // Woken up by another signal.
+    }
+}
+
 pub fn write_trapframe_to_kstack(kstack_top: usize, trap_frame: &TrapFrame) {
     let trap_frame_size = core::mem::size_of::<TrapFrame>();
     let trap_frame_ptr = (kstack_top - trap_frame_size) as *mut TrapFrame;
@@ -339,100 +1273,136 @@ pub fn read_trapframe_from_kstack(kstack_top: usize) -> TrapFrame {
     unsafe { *trap_frame_ptr }
 }
 
-pub fn wait_pid(pid: i32, exit_code_ptr: *mut i32) -> Result<u64, WaitStatus> {
-    let curr_task = current();
-    let mut exit_task_id: usize = 0;
-    let mut answer_id: u64 = 0;
-    let mut answer_status = WaitStatus::NotExist;
-    
-    for (index, child) in curr_task.task_ext().children.lock().iter().enumerate() {
-        if pid <= 0 {
-            if pid == 0 {
-                warn!("Don't support for process group.");
+/// Waits for a state change of a child of the current process matching
+/// `target`, among those `options` asks for.
+///
+/// The exited child is reaped unless `WNOWAIT` is given. Returns `None` if
+/// `WNOHANG` is given and no child has changed state yet, or `ECHILD` if no
+/// child matches `target`. Returns the event together with the user and
+/// system times of the child, in nanoseconds.
+pub fn wait_child(
+    target: WaitTarget,
+    options: WaitFlags,
+) -> LinuxResult<Option<(ChildEvent, (usize, usize))>> {
+    let curr = current();
+    let process = &curr.task_ext().process;
+    loop {
+        let events = process.child_events.load(Ordering::Acquire);
+        let mut found = false;
+        let mut children = process.children.lock();
+        for (index, child) in children.iter().enumerate() {
+            let child_process = &child.task_ext().process;
+            let matched = match target {
+                WaitTarget::Pid(pid) => child_process.pid() == pid,
+                WaitTarget::Pgid(pgid) => child_process.pgid() == pgid,
+                WaitTarget::Any => true,
+            };
+            if !matched {
+                continue;
             }
-
-            answer_status = WaitStatus::Running;
-            if child.state() == axtask::TaskState::Exited {
-                let exit_code = child.exit_code();
-                answer_status = WaitStatus::Exited;
-                info!(
-                    "wait pid _{}_ with code _{}_",
-                    child.id().as_u64(),
-                    exit_code
-                );
-                exit_task_id = index;
-                if !exit_code_ptr.is_null() {
-                    unsafe {
-                        *exit_code_ptr = exit_code << 8;
-                    }
+            found = true;
+            let exit_event = if options.contains(WaitFlags::WEXITED) {
+                child_process.exit_event()
+            } else {
+                None
+            };
+            if let Some(event) = exit_event {
+                let times = child_process.time_stat_output();
+                if !options.contains(WaitFlags::WNOWAIT) {
+                    info!("wait pid _{}_ with code _{}_", event.pid, event.status);
+                    children.remove(index);
+                    remove_task(event.pid);
                 }
-                answer_id = child.id().as_u64();
-                break;
+                return Ok(Some((event, times)));
             }
-        } else if child.id().as_u64() == pid as u64 {
-            
-            if let Some(exit_code) = child.join() {
-                answer_status = WaitStatus::Exited;
-                info!(
-                    "wait pid _{}_ with code _{:?}_",
-                    child.id().as_u64(),
-                    exit_code
-                );
-                exit_task_id = index;
-                if !exit_code_ptr.is_null() {
-                    unsafe {
-                        *exit_code_ptr = exit_code << 8;
-                    }
-                }
-                answer_id = child.id().as_u64();
-            } else {
-                answer_status = WaitStatus::Running;
+            if let Some(event) = child_process.take_job_event(options) {
+                return Ok(Some((event, child_process.time_stat_output())));
             }
-            break;
         }
+        drop(children);
+        if !found {
+            return Err(LinuxError::ECHILD);
+        }
+        if options.contains(WaitFlags::WNOHANG) {
+            return Ok(None);
+        }
+        wait_interruptible(&process.child_wq, None, || {
+            process.child_events.load(Ordering::Acquire) != events
+        })?;
     }
-
-    if answer_status == WaitStatus::Running {
-        axtask::yield_now();
-    }
-
-    if answer_status == WaitStatus::Exited {
-        curr_task.task_ext().children.lock().remove(exit_task_id);
-        remove_task(pid as usize);
-        return Ok(answer_id);
-    }
-    Err(answer_status)
 }
 
-pub fn exec(name: &str, args: &[String], envs: &[String]) -> AxResult<()> {
-    let current_task = current();
+/// Replaces the program of the current process with the one at `path`.
+///
+/// The program is loaded in a new address space first, so that nothing
+/// changes if it cannot be loaded. Then the other threads are killed, and
+/// the current thread becomes the group leader, with the signal handlers
+/// reset to the default and the close-on-exec files closed.
+pub fn exec(path: &str, args: &[String], envs: &[String]) -> LinuxResult<()> {
+    let (elf_path, args) = crate::mm::resolve_executable(path, args)?;
+    crate::mm::check_args_size(&args, envs)?;
+    let aspace = UserAspace::new()?;
+    let (entry_point, user_stack_base) =
+        crate::mm::load_user_app(&mut aspace.get().lock(), &elf_path, &args, envs).map_err(|err| {
+            error!("Failed to load app {}: {:?}", elf_path, err);
+            match err {
+                AxError::InvalidData | AxError::InvalidInput => LinuxError::ENOEXEC,
+                err => err.into(),
+            }
+        })?;
 
-    let program_name = name.to_string();
+    let curr = current();
+    let process = curr.task_ext().process.clone();
+    process.kill_other_threads()?;
+    // The robust futexes and the `clear_child_tid` word belong to the old
+    // program.
+    release_futexes_on_exit();
 
-    let mut aspace = current_task.task_ext().aspace.lock();
-    if Arc::strong_count(&current_task.task_ext().aspace) != 1 {
-        warn!("Address space is shared by multiple tasks, exec is not supported.");
-        return Err(AxError::Unsupported);
+    let task_ext = curr.task_ext();
+    let tid = task_ext.tid();
+    if tid != process.pid() {
+        // Take over the ID of the group leader, which has exited.
+        task_ext.tid.store(process.pid(), Ordering::Release);
+        insert_task(process.pid(), curr.as_task_ref().clone());
+        remove_task(tid);
     }
+    task_ext.set_clear_child_tid(0);
+    task_ext.set_robust_list(0);
+    // `path` may point to the old address space.
+    curr.set_name(path);
 
-    aspace.unmap_user_areas()?;
-    axhal::arch::flush_tlb(None);
+    let mut actions = *task_ext.sigaction().lock();
+    for action in actions.iter_mut() {
+        if action.sa_handler != SIG_IGN {
+            *action = SigAction::default();
+        }
+    }
+    // The handlers may be shared with another process by `CLONE_SIGHAND`.
+    *task_ext.sigaction.lock() = Arc::new(Mutex::new(actions));
 
-    let (entry_point, user_stack_base) = crate::mm::load_user_app(&mut aspace, args, envs)
-        .map_err(|_| {
-            error!("Failed to load app {}", program_name);
-            AxError::NotFound
-        })?;
-    current_task.set_name(&program_name);
-    drop(aspace);
+    // The old address space may be shared with another process by
+    // `CLONE_VM`, so it is left to that process.
+    let page_table_root = aspace.get().lock().page_table_root();
+    let old_aspace = core::mem::replace(&mut *task_ext.aspace.lock(), aspace.into_inner());
+    unsafe {
+        (*curr.ctx_mut_ptr()).set_page_table_root(page_table_root);
+        #[cfg(any(target_arch = "aarch64", target_arch = "loongarch64"))]
+        axhal::arch::write_page_table_root0(page_table_root);
+        #[cfg(not(any(target_arch = "aarch64", target_arch = "loongarch64")))]
+        axhal::arch::write_page_table_root(page_table_root);
+    }
+    drop(UserAspace::from(old_aspace));
+    process.shm_attachments.lock().detach_all(process.pid() as i32);
+    task_ext.release_vfork_parent();
 
-    let task_ext = unsafe { &mut *(current_task.task_ext_ptr() as *mut TaskExt) };
-    task_ext.uctx = UspaceContext::new(entry_point.as_usize(), user_stack_base, 0);
+    arceos_posix_api::close_on_exec();
+    process.exec_cred(&elf_path);
+    process.set_heap_top(process.get_heap_bottom());
 
+    let uctx = UspaceContext::new(entry_point.as_usize(), user_stack_base, 0);
     unsafe {
-        task_ext.uctx.enter_uspace(
-            current_task
-                .kernel_stack_top()
+        uctx.enter_uspace(
+            curr.kernel_stack_top()
                 .expect("No kernel stack top"),
         );
     }
@@ -475,6 +1445,15 @@ pub fn get_task_by_id(id: usize) -> Option<AxTaskRef> {
     None
 }
 
+/// Returns all the tasks that are still alive.
+pub fn all_tasks() -> Vec<AxTaskRef> {
+    TASK_ALL
+        .lock()
+        .values()
+        .filter_map(|task| task.upgrade())
+        .collect()
+}
+
 pub fn insert_task(id: usize, task: AxTaskRef) {
     let mut task_all = TASK_ALL.lock();
     task_all.insert(id, Arc::downgrade(&task));
@@ -487,5 +1466,5 @@ pub fn remove_task(id: usize) {
 
 pub fn get_fdlimit() -> u64{
     let curr_task = current();
-    curr_task.task_ext().get_fdlimit()
+    curr_task.task_ext().process.get_fdlimit()
 }
\ No newline at end of file